@@ -0,0 +1,161 @@
+// Table-driven machine against `legacy`, a copy of the char-based signal path
+// it replaced (wiring as Vec<char>, inverse wiring found with a linear search,
+// plugboard as a list of pairs). Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use sans_guard::enigma::analysis::index_of_coincidence;
+use sans_guard::enigma::{EnigmaMachine, ReflectorType, RotorType};
+
+const ROTORS: [(RotorType, char, char); 3] = [(RotorType::II, 'B', 'B'), (RotorType::IV, 'U', 'L'), (RotorType::V, 'L', 'A')];
+const PLUGS: &str = "AV BS CG DL FU HZ IN KM OW RX";
+
+mod legacy {
+    use sans_guard::enigma::{ReflectorType, RotorType};
+
+    struct Rotor {
+        wiring: Vec<char>,
+        notches: &'static str,
+        position: usize,
+        ring_setting: usize,
+    }
+
+    pub struct Machine {
+        rotors: [Rotor; 3],
+        reflector: Vec<char>,
+        plugboard: Vec<(char, char)>,
+    }
+
+    impl Machine {
+        pub fn new(rotors: [(RotorType, char, char); 3], reflector: ReflectorType, plugs: &str) -> Self {
+            let rotor = |(kind, ring, start): (RotorType, char, char)| Rotor {
+                wiring: kind.wiring().chars().collect(),
+                notches: kind.notches(),
+                position: (start as u8 - b'A') as usize,
+                ring_setting: (ring as u8 - b'A') as usize,
+            };
+            Machine {
+                rotors: rotors.map(rotor),
+                reflector: reflector.wiring().chars().collect(),
+                plugboard: plugs
+                    .split_whitespace()
+                    .map(|p| {
+                        let p: Vec<char> = p.chars().collect();
+                        (p[0], p[1])
+                    })
+                    .collect(),
+            }
+        }
+
+        fn map_char(c: char, map: &[(char, char)]) -> char {
+            for &(a, b) in map {
+                if c == a { return b; }
+                if c == b { return a; }
+            }
+            c
+        }
+
+        fn rotor_forward(c: char, rotor: &Rotor) -> char {
+            let offset = (rotor.position + 26 - rotor.ring_setting) % 26;
+            let input_idx = ((c as u8 - b'A') as usize + offset) % 26;
+            let output_idx = (rotor.wiring[input_idx] as u8 - b'A') as usize;
+            (((output_idx + 26 - offset) % 26) as u8 + b'A') as char
+        }
+
+        fn rotor_backward(c: char, rotor: &Rotor) -> char {
+            let offset = (rotor.position + 26 - rotor.ring_setting) % 26;
+            let input_idx = ((c as u8 - b'A') as usize + offset) % 26;
+            let mapped_char = (input_idx as u8 + b'A') as char;
+            let wiring_idx = rotor.wiring.iter().position(|&x| x == mapped_char).unwrap();
+            (((wiring_idx + 26 - offset) % 26) as u8 + b'A') as char
+        }
+
+        fn at_notch(rotor: &Rotor) -> bool {
+            rotor.notches.bytes().any(|n| (n - b'A') as usize == rotor.position)
+        }
+
+        fn step_rotors(&mut self) {
+            let right_at_notch = Self::at_notch(&self.rotors[2]);
+            let middle_at_notch = Self::at_notch(&self.rotors[1]);
+            if middle_at_notch { self.rotors[0].position = (self.rotors[0].position + 1) % 26; }
+            if right_at_notch || middle_at_notch { self.rotors[1].position = (self.rotors[1].position + 1) % 26; }
+            self.rotors[2].position = (self.rotors[2].position + 1) % 26;
+        }
+
+        pub fn process_char(&mut self, c: char) -> char {
+            if !c.is_ascii_alphabetic() {
+                return c;
+            }
+            self.step_rotors();
+            let mut res = Self::map_char(c.to_ascii_uppercase(), &self.plugboard);
+            for rotor in self.rotors.iter().rev() {
+                res = Self::rotor_forward(res, rotor);
+            }
+            res = self.reflector[(res as u8 - b'A') as usize];
+            for rotor in self.rotors.iter() {
+                res = Self::rotor_backward(res, rotor);
+            }
+            res = Self::map_char(res, &self.plugboard);
+            if c.is_ascii_lowercase() { res.to_ascii_lowercase() } else { res }
+        }
+
+        pub fn process_text(&mut self, text: &str) -> String {
+            text.chars().map(|c| self.process_char(c)).collect()
+        }
+    }
+}
+
+fn message(len: usize) -> String {
+    "DERFUEHRERISTTOTDERKAMPFGEHTWEITER".chars().cycle().take(len).collect()
+}
+
+fn bench_encrypt(c: &mut Criterion) {
+    let text = message(10_000);
+    assert_eq!(
+        legacy::Machine::new(ROTORS, ReflectorType::B, PLUGS).process_text(&text),
+        EnigmaMachine::new(ROTORS, ReflectorType::B, PLUGS).process_text(&text)
+    );
+
+    let mut group = c.benchmark_group("encrypt 10k letters");
+    group.bench_function("legacy", |b| {
+        b.iter(|| legacy::Machine::new(ROTORS, ReflectorType::B, PLUGS).process_text(black_box(&text)))
+    });
+    group.bench_function("tables", |b| {
+        b.iter(|| EnigmaMachine::new(ROTORS, ReflectorType::B, PLUGS).process_text(black_box(&text)))
+    });
+    group.finish();
+}
+
+// The shape of the rotor order search: every start position of the two fast
+// rotors, a 250-letter decrypt each, scored by index of coincidence.
+fn bench_position_sweep(c: &mut Criterion) {
+    let ciphertext = EnigmaMachine::new(ROTORS, ReflectorType::B, "").process_text(&message(250));
+    let starts = || (0..26 * 26).map(|s| [ROTORS[0], (ROTORS[1].0, 'A', letter(s / 26)), (ROTORS[2].0, 'A', letter(s % 26))]);
+
+    let mut group = c.benchmark_group("sweep 676 start positions");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            starts()
+                .map(|settings| index_of_coincidence(&legacy::Machine::new(settings, ReflectorType::B, "").process_text(&ciphertext)))
+                .fold(0.0, f64::max)
+        })
+    });
+    group.bench_function("tables", |b| {
+        b.iter(|| {
+            starts()
+                .map(|settings| {
+                    let mut machine = EnigmaMachine::new(settings, ReflectorType::B, "");
+                    let plain: Vec<u8> = ciphertext.bytes().map(|b| machine.process_byte(b)).collect();
+                    index_of_coincidence(std::str::from_utf8(&plain).expect("ASCII"))
+                })
+                .fold(0.0, f64::max)
+        })
+    });
+    group.finish();
+}
+
+fn letter(i: usize) -> char {
+    (i as u8 + b'A') as char
+}
+
+criterion_group!(benches, bench_encrypt, bench_position_sweep);
+criterion_main!(benches);
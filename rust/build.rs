@@ -0,0 +1,79 @@
+// Encrypts init_script.js with the settings in init_script.toml, so only the
+// ciphertext ends up in the binary. The cipher is the crate's own enigma
+// module compiled into the build script. With the sealed-script feature the
+// ciphertext is also encrypted and signed into a payload container, and only
+// the public key and the cipher key are written next to it.
+//
+// The signing key is 64 hex digits in the file named by
+// SANS_GUARD_SIGNING_KEY, kept outside the repository. Without it a key is
+// made up for the build and thrown away, which works but gives a different
+// binary every time. The cipher key and nonce are hashed from the signing
+// key and the script, so the same key still gives the same binary.
+
+use std::path::PathBuf;
+
+#[path = "src"]
+#[allow(dead_code, unused_imports, clippy::upper_case_acronyms)]
+mod src {
+    pub mod enigma;
+    pub mod payload;
+}
+
+use src::enigma::EnigmaSettings;
+
+const SIGNING_KEY_VAR: &str = "SANS_GUARD_SIGNING_KEY";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed={SIGNING_KEY_VAR}");
+    for path in ["init_script.js", "init_script.toml", "src/enigma.rs", "src/enigma", "src/payload.rs"] {
+        println!("cargo:rerun-if-changed={path}");
+    }
+    let script = std::fs::read_to_string("init_script.js").expect("read init_script.js");
+    let settings = EnigmaSettings::load("init_script.toml").unwrap_or_else(|e| panic!("init_script.toml: {e}"));
+    let mut machine = settings.machine().expect("validated on load");
+    let encrypted = machine.process_text(&script);
+
+    let out = PathBuf::from(std::env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    if std::env::var_os("CARGO_FEATURE_SEALED_SCRIPT").is_some() {
+        let key = signing_key();
+        let cipher_key = derive(&[b"init script key", &key]);
+        let nonce = derive(&[b"init script nonce", &key, encrypted.as_bytes()]);
+        let sealed = src::payload::seal(&key, &cipher_key, &nonce, encrypted.as_bytes());
+        std::fs::write(out.join("init_script.pub"), src::payload::public_key(&key)).expect("write public key");
+        std::fs::write(out.join("init_script.key"), cipher_key).expect("write cipher key");
+        std::fs::write(out.join("init_script.sealed"), sealed).expect("write sealed script");
+    } else {
+        std::fs::write(out.join("init_script.enc.js"), encrypted).expect("write encrypted script");
+    }
+}
+
+// The first N bytes of SHA-512 over the parts
+fn derive<const N: usize>(parts: &[&[u8]]) -> [u8; N] {
+    use sha2::{Digest, Sha512};
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize()[..N].try_into().expect("SHA-512 is 64 bytes")
+}
+
+fn signing_key() -> [u8; src::payload::SECRET_KEY_LEN] {
+    let mut key = [0; src::payload::SECRET_KEY_LEN];
+    let Some(path) = std::env::var_os(SIGNING_KEY_VAR) else {
+        use rand::RngCore;
+        println!("cargo:warning={SIGNING_KEY_VAR} is not set, signing the init script with a throwaway key");
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        return key;
+    };
+    println!("cargo:rerun-if-changed={}", PathBuf::from(&path).display());
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{SIGNING_KEY_VAR}: {e}"));
+    let hex = text.trim();
+    if hex.len() != 2 * key.len() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        panic!("{SIGNING_KEY_VAR}: expected {} hex digits", 2 * key.len());
+    }
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("checked hex digits");
+    }
+    key
+}
@@ -0,0 +1,117 @@
+(() => {
+  const send = (obj) => {
+    try { window.ipc.postMessage(JSON.stringify(obj)); } catch (_) {}
+  };
+
+  // One-time reload helper for hotkeys
+  let __hasReloaded = false;
+  const reloadOnce = () => { if (!__hasReloaded) { __hasReloaded = true; try { location.reload(); } catch (_) {} } };
+
+  // Konami code: Up, Up, Down, Down, Left, Right, Left, Right, B, A
+  (function(){
+    const seq = ['ArrowUp','ArrowUp','ArrowDown','ArrowDown','ArrowLeft','ArrowRight','ArrowLeft','ArrowRight','b','a'];
+    let idx = 0;
+    const matches = (expected, key) => expected.length === 1 ? expected === key.toLowerCase() : expected === key;
+    window.addEventListener('keydown', (e) => {
+      try {
+        const key = (e.key || '').toString();
+        if (matches(seq[idx], key)) {
+          idx++;
+          if (idx === seq.length) {
+            send({ event: 'konami' });
+            idx = 0;
+          }
+        } else {
+          // reset if mismatch, but allow restarting from first on immediate match
+          idx = matches(seq[0], key) ? 1 : 0;
+        }
+      } catch(_) {}
+    }, { capture: true });
+  })();
+
+  // Removed auto 'Z' press at startup
+
+  // Reload after 10 minutes if still running
+  const reloadTimer = setTimeout(() => {
+    try { location.reload(); } catch (_) {}
+  }, 10 * 60 * 1000);
+
+  // Hook console.log to detect win/loss
+  (function() {
+    const orig = console.log;
+    console.log = function(...args) {
+      try {
+        const text = args.map(a => {
+          try { return typeof a === 'string' ? a : JSON.stringify(a); } catch(_) { return String(a); }
+        }).join(' ');
+        if (text.includes('Won')) {
+          try { clearTimeout(reloadTimer); } catch(_) {}
+          send({ event: 'won' });
+        } else if (text.includes('Loss')) {
+          // No reload on loss
+        }
+      } catch (_) {}
+      return orig.apply(this, args);
+    };
+  })();
+
+  // Force browser fullscreen for the page content and reapply if lost.
+  (function(){
+    const isFull = () => !!(document.fullscreenElement || document.webkitFullscreenElement || document.msFullscreenElement);
+    const requestFull = () => {
+      try {
+        const el = document.documentElement;
+        const req = el.requestFullscreen || el.webkitRequestFullscreen || el.msRequestFullscreen;
+        if (req) req.call(el);
+      } catch (_) {}
+    };
+
+    let reloadAttempts = 0;
+    let pending = false; // debounce reload check
+    const enforce = () => {
+      if (!isFull()) {
+        requestFull();
+        if (!pending) {
+          pending = true;
+          setTimeout(() => {
+            try {
+              if (!isFull() && reloadAttempts < 3) {
+                reloadAttempts++;
+                location.reload();
+              }
+            } catch (_) {}
+            pending = false;
+          }, 1500);
+        }
+      }
+    };
+
+    // Initial try and per-frame enforcement
+    setTimeout(enforce, 500);
+    const tick = () => { try { enforce(); } catch (_) {} requestAnimationFrame(tick); };
+    requestAnimationFrame(tick);
+    document.addEventListener('fullscreenchange', () => { if (!isFull()) enforce(); });
+    document.addEventListener('webkitfullscreenchange', () => { if (!isFull()) enforce(); });
+  })();
+
+  // Soft handling of close-ish hotkeys inside the page context.
+  // Note: cannot intercept Ctrl+Alt+Del; Alt+F4 is OS-handled and may still close.
+  window.addEventListener('keydown', (e) => {
+    try {
+      const key = (e.key || '').toString();
+      const k = key.length ? key.toLowerCase() : '';
+      // Ctrl+W (common close-tab in browsers)
+      if (e.ctrlKey && (k === 'w')) {
+        e.preventDefault();
+        try { reloadOnce(); } catch(_) {}
+        return;
+      }
+      // Alt+F4 (best-effort; OS may close before this runs)
+      if (e.altKey && (key === 'F4' || k === 'f4')) {
+        e.preventDefault();
+        try { reloadOnce(); } catch(_) {}
+        return;
+      }
+    } catch (_) {}
+  }, { capture: true });
+})();
@@ -0,0 +1,315 @@
+// The attack scripts in gh-page/sans_*.csv. The game page runs one row at a
+// time: column 1 is the delay in seconds before the row runs, column 2 the
+// opcode and the rest up to eight arguments. A row whose opcode starts with a
+// colon is a label. Arguments starting with $ read a variable; everything
+// else is taken literally.
+//
+// Jumps name a label or a 1-based line number of the file, so line N is the
+// Nth row (including label rows). JMPREL moves relative to its own line.
+
+mod asm;
+mod dsl;
+pub mod lint;
+mod parse;
+mod vm;
+
+pub use asm::{assemble, disassemble, AsmError, AsmErrorKind};
+pub use dsl::{compile, decompile, CompileError, CompileErrorKind};
+pub use lint::{lint, lint_file, Lint, LintKind, Severity};
+pub use parse::{parse_rows, Cell, ParseError, ParseErrorKind, Row};
+pub use vm::{Event, Spawn, Timeline, Vm, VmError, VmErrorKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Var(String),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Var(name) => write!(f, "${name}"),
+        }
+    }
+}
+
+// Where a jump goes: a line number, a label, or a line number held in a
+// variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Line(usize),
+    Label(String),
+    Var(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Floor,
+    // Both in degrees, like the game's
+    Sin,
+    Cos,
+    // Radians to degrees and back
+    Deg,
+    Rad,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Zero(Value),
+    NotZero(Value),
+    Equal(Value, Value),
+    NotEqual(Value, Value),
+    Less(Value, Value),
+    NotLess(Value, Value),
+    Greater(Value, Value),
+    NotGreater(Value, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Label(String),
+    Set { var: String, value: Value },
+    Binary { op: BinaryOp, var: String, lhs: Value, rhs: Value },
+    Unary { op: UnaryOp, var: String, value: Value },
+    // Direction in degrees from (x1, y1) to (x2, y2)
+    Angle { var: String, x1: Value, y1: Value, x2: Value, y2: Value },
+    // A whole number from 0 to max - 1
+    Rnd { var: String, max: Value },
+    GetHeartPos { x: String, y: String },
+    Jump { target: Target, condition: Option<Condition> },
+    JumpRel { offset: Value },
+
+    BoneV { x: Value, y: Value, height: Value, direction: Value, speed: Value, color: Option<Value> },
+    BoneH { x: Value, y: Value, width: Value, direction: Value, speed: Value, color: Option<Value> },
+    BoneVRepeat { x: Value, y: Value, height: Value, direction: Value, speed: Value, count: Value, spacing: Value },
+    BoneHRepeat { x: Value, y: Value, width: Value, direction: Value, speed: Value, count: Value, spacing: Value },
+    BoneStab { direction: Value, height: Value, warning: Value, duration: Value },
+    SineBones { count: Value, spacing: Value, speed: Value, height: Value },
+    GasterBlaster { size: Value, x: Value, y: Value, end_x: Value, end_y: Value, angle: Value, aim: Value, fire: Value },
+    Platform { x: Value, y: Value, width: Value, direction: Value, speed: Value, kind: Option<Value> },
+    PlatformRepeat { x: Value, y: Value, width: Value, direction: Value, speed: Value, count: Value, spacing: Value },
+
+    // `then` names the function the game calls once the box has moved
+    CombatZoneResize { x1: Value, y1: Value, x2: Value, y2: Value, then: Option<String> },
+    CombatZoneResizeInstant { x1: Value, y1: Value, x2: Value, y2: Value },
+    CombatZoneSpeed(Value),
+    HeartTeleport { x: Value, y: Value },
+    HeartMode(Value),
+    HeartMaxFallSpeed(Value),
+
+    Sound { name: String, rate: Option<Value> },
+    Music(String),
+    BlackScreen(Value),
+    SansBody(String),
+    SansHead(String),
+    SansTorso(String),
+    SansAnimation(Option<String>),
+    SansSweat(Value),
+    SansSlam(Value),
+    SansSlamDamage(Value),
+    SansShake(Value),
+    SansText(String),
+    SansX(Value),
+    SansRepeat,
+    SansEndRepeat,
+
+    TLPause,
+    TLResume,
+    EndAttack,
+}
+
+// One script row as the game sees it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    // 1-based line in the file, which is also what jumps count in
+    pub line: usize,
+    pub delay: Value,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackScript {
+    // File name for error messages
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+impl AttackScript {
+    pub fn parse(name: &str, source: &str) -> Result<Self, ParseError> {
+        let steps = parse_rows(name, source)?.iter().map(|row| parse::step(name, row)).collect::<Result<_, _>>()?;
+        Ok(AttackScript { name: name.to_string(), steps })
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, ParseError> {
+        let name = path.display().to_string();
+        let source = std::fs::read_to_string(path).map_err(|e| ParseError::new(&name, 0, 0, ParseErrorKind::Io(e.to_string())))?;
+        Self::parse(&name, &source)
+    }
+
+    // Line a label row sits on
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.steps.iter().find(|s| matches!(&s.command, Command::Label(l) if l == name)).map(|s| s.line)
+    }
+}
+
+// Argument count range of every opcode the game page understands, labels
+// aside
+pub const OPCODES: &[(&str, usize, usize)] = &[
+    ("SET", 2, 2),
+    ("ADD", 3, 3),
+    ("SUB", 3, 3),
+    ("MUL", 3, 3),
+    ("DIV", 3, 3),
+    ("MOD", 3, 3),
+    ("FLOOR", 2, 2),
+    ("SIN", 2, 2),
+    ("COS", 2, 2),
+    ("DEG", 2, 2),
+    ("RAD", 2, 2),
+    ("ANGLE", 5, 5),
+    ("RND", 2, 2),
+    ("GetHeartPos", 2, 2),
+    ("JMPABS", 1, 1),
+    ("JMPREL", 1, 1),
+    ("JMPZ", 2, 2),
+    ("JMPNZ", 2, 2),
+    ("JMPE", 3, 3),
+    ("JMPNE", 3, 3),
+    ("JMPL", 3, 3),
+    ("JMPNL", 3, 3),
+    ("JMPG", 3, 3),
+    ("JMPNG", 3, 3),
+    ("BoneV", 5, 6),
+    ("BoneH", 5, 6),
+    ("BoneVRepeat", 7, 7),
+    ("BoneHRepeat", 7, 7),
+    ("BoneStab", 4, 4),
+    ("SineBones", 4, 4),
+    ("GasterBlaster", 8, 8),
+    ("Platform", 5, 6),
+    ("PlatformRepeat", 7, 7),
+    ("CombatZoneResize", 4, 5),
+    ("CombatZoneResizeInstant", 4, 4),
+    ("CombatZoneSpeed", 1, 1),
+    ("HeartTeleport", 2, 2),
+    ("HeartMode", 1, 1),
+    ("HeartMaxFallSpeed", 1, 1),
+    ("Sound", 1, 2),
+    ("Music", 1, 1),
+    ("BlackScreen", 1, 1),
+    ("SansBody", 1, 1),
+    ("SansHead", 1, 1),
+    ("SansTorso", 1, 1),
+    ("SansAnimation", 0, 1),
+    ("SansSweat", 1, 1),
+    ("SansSlam", 1, 1),
+    ("SansSlamDamage", 1, 1),
+    ("SansShake", 1, 1),
+    ("SansText", 1, 1),
+    ("SansX", 1, 1),
+    ("SansRepeat", 0, 0),
+    ("SansEndRepeat", 0, 0),
+    ("TLPause", 0, 0),
+    ("TLResume", 0, 0),
+    ("EndAttack", 0, 0),
+];
+
+pub fn arity(opcode: &str) -> Option<(usize, usize)> {
+    OPCODES.iter().find(|(name, ..)| *name == opcode).map(|&(_, min, max)| (min, max))
+}
+
+impl Command {
+    // Every numeric operand, in argument order
+    pub fn values(&self) -> Vec<&Value> {
+        fn opt(v: &Option<Value>) -> Vec<&Value> {
+            v.iter().collect()
+        }
+        match self {
+            Command::Set { value, .. } | Command::Unary { value, .. } => vec![value],
+            Command::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Command::Angle { x1, y1, x2, y2, .. } => vec![x1, y1, x2, y2],
+            Command::Rnd { max, .. } => vec![max],
+            Command::Jump { condition, .. } => match condition {
+                None => vec![],
+                Some(Condition::Zero(v) | Condition::NotZero(v)) => vec![v],
+                Some(
+                    Condition::Equal(a, b)
+                    | Condition::NotEqual(a, b)
+                    | Condition::Less(a, b)
+                    | Condition::NotLess(a, b)
+                    | Condition::Greater(a, b)
+                    | Condition::NotGreater(a, b),
+                ) => vec![a, b],
+            },
+            Command::JumpRel { offset } => vec![offset],
+            Command::BoneV { x, y, height: w, direction, speed, color }
+            | Command::BoneH { x, y, width: w, direction, speed, color }
+            | Command::Platform { x, y, width: w, direction, speed, kind: color } => {
+                [vec![x, y, w, direction, speed], opt(color)].concat()
+            }
+            Command::BoneVRepeat { x, y, height: w, direction, speed, count, spacing }
+            | Command::BoneHRepeat { x, y, width: w, direction, speed, count, spacing }
+            | Command::PlatformRepeat { x, y, width: w, direction, speed, count, spacing } => {
+                vec![x, y, w, direction, speed, count, spacing]
+            }
+            Command::BoneStab { direction, height, warning, duration } => vec![direction, height, warning, duration],
+            Command::SineBones { count, spacing, speed, height } => vec![count, spacing, speed, height],
+            Command::GasterBlaster { size, x, y, end_x, end_y, angle, aim, fire } => {
+                vec![size, x, y, end_x, end_y, angle, aim, fire]
+            }
+            Command::CombatZoneResize { x1, y1, x2, y2, .. } | Command::CombatZoneResizeInstant { x1, y1, x2, y2 } => {
+                vec![x1, y1, x2, y2]
+            }
+            Command::HeartTeleport { x, y } => vec![x, y],
+            Command::Sound { rate, .. } => opt(rate),
+            Command::CombatZoneSpeed(v)
+            | Command::HeartMode(v)
+            | Command::HeartMaxFallSpeed(v)
+            | Command::BlackScreen(v)
+            | Command::SansSweat(v)
+            | Command::SansSlam(v)
+            | Command::SansSlamDamage(v)
+            | Command::SansShake(v)
+            | Command::SansX(v) => vec![v],
+            _ => vec![],
+        }
+    }
+
+    // Variables the row reads, including a computed jump target
+    pub fn reads(&self) -> Vec<&str> {
+        let mut vars: Vec<&str> = self
+            .values()
+            .into_iter()
+            .filter_map(|v| match v {
+                Value::Var(name) => Some(name.as_str()),
+                Value::Number(_) => None,
+            })
+            .collect();
+        if let Command::Jump { target: Target::Var(name), .. } = self {
+            vars.insert(0, name);
+        }
+        vars
+    }
+
+    pub fn writes(&self) -> Vec<&str> {
+        match self {
+            Command::Set { var, .. }
+            | Command::Binary { var, .. }
+            | Command::Unary { var, .. }
+            | Command::Angle { var, .. }
+            | Command::Rnd { var, .. } => vec![var],
+            Command::GetHeartPos { x, y } => vec![x, y],
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// A small language for writing attack scripts without counting rows. It
+// compiles to the CSV opcodes the game page already plays:
+//
+//     # Fifteen blasters at the heart, one every 0.53 seconds
+//     CombatZoneResize(121, 186, 526, 391, "TLResume")
+//     wait 0.5
+//     repeat 15 {
+//         GetHeartPos("HeartX", "HeartY")
+//         let ang = rnd(360)
+//         let end_x = cos(ang) * 200 + HeartX
+//         if end_x < 50 { let end_x = 50 }
+//         blaster(size: 0, x: HeartX, y: HeartY, end_x: end_x, end_y: HeartY,
+//                 angle: ang, aim: 0.46666, fire: 0.03333)
+//         wait 0.53333
+//     }
+//     EndAttack()
+//
+// Statements end at the end of the line:
+//
+//     let x = expr            + - * / % on numbers and variables, and the
+//                             functions floor, sin, cos, deg, rad, rnd, angle
+//     wait expr               seconds before the next row runs
+//     repeat expr { ... }
+//     if cond { ... } else if cond { ... } else { ... }
+//     Name:                   names the next row
+//     :Name                   a label row, as in the game's own scripts
+//     goto Name [if cond]     cond compares with == != < <= > >=, combines
+//                             with and, or, not; a bare expr means "not 0"
+//     choose expr { A, B }    goes to the expr-th label, counting from 1
+//     bone_v(x: 10, ...)      named bone, blaster and platform constructors;
+//                             arguments by position or by name
+//     SansText("hello")       any other opcode, arguments in CSV order and
+//                             names and text in quotes
+//
+// Variables are the game's variables under the same names. Names starting
+// with _ belong to the compiler: _t0, _t1... hold partial results, _r0, _r1...
+// count repeats and _ is written by rows that only carry a wait. Raw JMP
+// rows are taken as written and are not moved when rows are added.
+//
+// `decompile` turns any CSV script into this language row for row, so that
+// `compile` of the result plays the same.
+
+mod decompile;
+mod emit;
+mod syntax;
+
+pub use decompile::decompile;
+
+use std::ops::Range;
+
+use super::parse::ParseErrorKind;
+use super::AsmErrorKind;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileErrorKind {
+    UnexpectedChar(char),
+    UnterminatedString,
+    Expected { expected: &'static str, found: String },
+    ReservedName(String),
+    UnknownFunction(String),
+    ArgCount { function: String, min: usize, max: usize, found: usize },
+    MissingArg(&'static str),
+    UnknownArg(String),
+    DuplicateArg(String),
+    // A quoted string where a number or variable is needed
+    NotANumber,
+    // Text that would split the CSV row
+    BadText(String),
+    BadRow(ParseErrorKind),
+    // A raw JMP row naming a label instead of using goto
+    JumpByName,
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    // The rows made did not assemble, which is a bug in the compiler; it
+    // has no place in the source so the error points at the start
+    Assemble(AsmErrorKind),
+}
+
+impl std::fmt::Display for CompileErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileErrorKind::UnexpectedChar(c) => write!(f, "unexpected {c:?}"),
+            CompileErrorKind::UnterminatedString => write!(f, "string is not closed on the same line"),
+            CompileErrorKind::Expected { expected, found } => write!(f, "expected {expected}, found {found}"),
+            CompileErrorKind::ReservedName(name) => write!(f, "names starting with _ are the compiler's, got {name}"),
+            CompileErrorKind::UnknownFunction(name) => write!(f, "unknown function {name}"),
+            CompileErrorKind::ArgCount { function, min, max, found } if min == max => {
+                write!(f, "{function} takes {min} arguments, got {found}")
+            }
+            CompileErrorKind::ArgCount { function, min, max, found } => {
+                write!(f, "{function} takes {min} to {max} arguments, got {found}")
+            }
+            CompileErrorKind::MissingArg(name) => write!(f, "missing argument {name}"),
+            CompileErrorKind::UnknownArg(name) => write!(f, "no argument called {name}"),
+            CompileErrorKind::DuplicateArg(name) => write!(f, "argument {name} is given twice"),
+            CompileErrorKind::NotANumber => write!(f, "expected a number or variable, not a string"),
+            CompileErrorKind::BadText(text) => write!(f, "{text:?} cannot go in a CSV cell"),
+            CompileErrorKind::BadRow(kind) => write!(f, "{kind}"),
+            CompileErrorKind::JumpByName => write!(f, "use goto to jump to a label"),
+            CompileErrorKind::UnknownLabel(l) => write!(f, "label {l} does not exist"),
+            CompileErrorKind::DuplicateLabel(l) => write!(f, "label {l} is already defined"),
+            CompileErrorKind::Assemble(kind) => write!(f, "compiled rows do not assemble: {kind}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub file: String,
+    // Where the span starts; line and column are 1-based, column in
+    // characters
+    pub line: usize,
+    pub column: usize,
+    // Byte range of the offending source
+    pub span: Range<usize>,
+    pub kind: CompileErrorKind,
+}
+
+impl CompileError {
+    fn new(file: &str, source: &str, span: Range<usize>, kind: CompileErrorKind) -> Self {
+        let before = &source[..span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        CompileError { file: file.to_string(), line, column, span, kind }
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+// Errors inside the compiler carry only the span and kind; `compile` adds
+// the file and works out the line and column
+type Error = (Range<usize>, CompileErrorKind);
+
+pub fn compile(file: &str, source: &str) -> Result<String, CompileError> {
+    let error = |(span, kind): Error| CompileError::new(file, source, span, kind);
+    let program = syntax::parse(source).map_err(error)?;
+    let rows = emit::emit(file, &program).map_err(error)?;
+    // Every label was checked and every raw row typed on the way, so this
+    // should not fail, but a bug here is an error and not a panic
+    super::assemble(file, &rows).map_err(|e| error((0..0, CompileErrorKind::Assemble(e.kind))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attack::{AttackScript, Vm};
+
+    fn bundled() -> Vec<(String, String)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../gh-page");
+        let mut scripts = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            if name.starts_with("sans_") && name.ends_with(".csv") {
+                scripts.push((name, std::fs::read_to_string(&path).unwrap()));
+            }
+        }
+        scripts
+    }
+
+    // Debug text of the run, as sans_final divides by zero and NaN is never
+    // equal to itself
+    fn run(name: &str, csv: &str, seed: u64) -> String {
+        let script = AttackScript::parse(name, csv).unwrap();
+        format!("{:?}", Vm::new(&script).with_seed(seed).run())
+    }
+
+    #[test]
+    fn test_compile() {
+        let source = "\
+let n = 3
+Top:
+bone_h(10, 20, width: 100, direction: 1, speed: n * 60)
+wait 0.25
+let n = n - 1
+goto Top if n > 0
+EndAttack()
+";
+        assert_eq!(
+            compile("t", source).unwrap(),
+            "\
+0,SET,n,3,,,
+0,MUL,_t0,$n,60,,
+0,BoneH,10,20,100,1,$_t0
+0.25,SUB,n,$n,1,,
+0,JMPG,2,$n,0,,
+0,EndAttack,,,,,
+"
+        );
+    }
+
+    #[test]
+    fn test_errors_carry_spans() {
+        let error = compile("t.atk", "let x = 1\nrepeat 2 {\n    bone_v(1, 2, 3, 4, spead: 5)\n}\n").unwrap_err();
+        assert_eq!((error.line, error.column, error.span.clone()), (3, 24, 44..49));
+        assert_eq!(error.to_string(), "t.atk:3:24: no argument called spead");
+        let error = compile("t.atk", "wait 1\n\ngoto End").unwrap_err();
+        assert_eq!(error.to_string(), "t.atk:3:6: label End does not exist");
+        let error = compile("t.atk", "# é\nlet é = 1").unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (2, 5, CompileErrorKind::UnexpectedChar('é')));
+    }
+
+    // Names Rust reads as numbers are not labels to the assembler
+    #[test]
+    fn test_number_names() {
+        for source in ["inf:\nEndAttack()", "Infinity:\nEndAttack()", "NaN:", "let nan = 1", "goto INF\nINF:\nEndAttack()"] {
+            let error = compile("t", source).unwrap_err();
+            assert!(matches!(error.kind, CompileErrorKind::Expected { expected: "a name", .. }), "{source}: {error}");
+        }
+    }
+
+    // sans_randomblaster1.csv written in the language: the same blasters at
+    // the same times
+    #[test]
+    fn test_random_blasters() {
+        let source = "\
+CombatZoneResize(121, 186, 526, 391, \"TLResume\")
+HeartTeleport(320, 304)
+HeartMode(0)
+TLPause()
+wait 0.5
+repeat 15 {
+    let ang = rnd(360)
+    GetHeartPos(\"HeartX\", \"HeartY\")
+    let end_x = cos(ang) * 200 + HeartX
+    let end_y = sin(ang) * 200 + HeartY
+    let x = cos(ang) * 400 + HeartX
+    let y = sin(ang) * 300 + HeartY
+    if end_x < 50 { let end_x = 50 }
+    if end_x > 590 { let end_x = 590 }
+    if end_y < 40 { let end_y = 40 }
+    if end_y > 440 { let end_y = 440 }
+    blaster(size: 0, x: x, y: y, end_x: end_x, end_y: end_y,
+            angle: angle(end_x, end_y, HeartX, HeartY), aim: 0.46666, fire: 0.03333)
+    wait 0.53333
+}
+EndAttack()
+";
+        let (name, csv) = bundled().into_iter().find(|(name, _)| name == "sans_randomblaster1.csv").unwrap();
+        let compiled = compile("blasters.atk", source).unwrap();
+        let events = |csv: &str, seed| {
+            let script = AttackScript::parse(&name, csv).unwrap();
+            let timeline = Vm::new(&script).with_seed(seed).run().unwrap();
+            (timeline.events.into_iter().map(|e| (e.time, e.spawn)).collect::<Vec<_>>(), timeline.duration, timeline.ended)
+        };
+        for seed in 0..5 {
+            assert_eq!(events(&compiled, seed), events(&csv, seed));
+        }
+    }
+
+    #[test]
+    fn test_bundled_round_trip() {
+        for (name, csv) in bundled() {
+            let source = decompile(&name, &csv).unwrap_or_else(|e| panic!("{e}"));
+            let compiled = compile(&name, &source).unwrap_or_else(|e| panic!("{e}\n{source}"));
+            assert_eq!(compiled.lines().count(), csv.lines().count(), "{name}");
+            for seed in 0..3 {
+                assert_eq!(run(&name, &compiled, seed), run(&name, &csv, seed), "{name}");
+            }
+            assert_eq!(decompile(&name, &compiled).unwrap(), source, "{name}");
+        }
+    }
+}
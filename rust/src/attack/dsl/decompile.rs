@@ -0,0 +1,215 @@
+// CSV back to the language, one statement per row so that compiling the
+// result gives the same rows in the same places. Jumps go through the
+// disassembler first so they name labels; a computed JMPREL followed by
+// plain jumps becomes a `choose`, and rows the language has no statement
+// for stay raw opcode calls.
+
+use super::emit::{CONSTRUCTORS, FUNCTIONS};
+use super::syntax::is_identifier;
+use crate::attack::parse::{self, parse_row, parse_rows, ParseError, ParseErrorKind, Row};
+use crate::attack::{disassemble, BinaryOp, Command, UnaryOp};
+
+enum Item {
+    Label(String),
+    Row(Row),
+}
+
+pub fn decompile(file: &str, csv: &str) -> Result<String, ParseError> {
+    let original = parse_rows(file, csv)?;
+    let mut items = Vec::new();
+    let mut line = 0;
+    for text in disassemble(file, csv)?.lines() {
+        match text.strip_suffix(':') {
+            Some(name) if !text.contains(',') => items.push(Item::Label(name.to_string())),
+            _ => {
+                line += 1;
+                items.push(Item::Row(parse_row(file, line, text)?));
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < items.len() {
+        let row = match &items[i] {
+            Item::Label(name) => {
+                out.push(format!("{name}:"));
+                i += 1;
+                continue;
+            }
+            Item::Row(row) => row,
+        };
+        // Cell positions for errors come from the CSV, where jumps are
+        // still numbers
+        let d = Decompiler { file, row, original: &original[row.line - 1] };
+        if row.delay.text != "0" {
+            out.push(format!("wait {}", d.value(None)?));
+        }
+        let table = d.table(&items[i + 1..]);
+        match table {
+            Some(labels) => {
+                out.push(format!("choose {} {{ {} }}", d.value(Some(0))?, labels.join(", ")));
+                i += labels.len();
+            }
+            None => out.push(d.statement()?),
+        }
+        i += 1;
+    }
+    Ok(out.iter().map(|l| format!("{l}\n")).collect())
+}
+
+struct Decompiler<'a> {
+    file: &'a str,
+    row: &'a Row,
+    original: &'a Row,
+}
+
+impl Decompiler<'_> {
+    fn error<T>(&self, arg: Option<usize>, kind: ParseErrorKind) -> Result<T, ParseError> {
+        let column = arg.map_or(self.original.delay.column, |i| self.original.args[i].column);
+        Err(ParseError::new(self.file, self.row.line, column, kind))
+    }
+
+    fn text(&self, arg: Option<usize>) -> &str {
+        arg.map_or(&self.row.delay.text, |i| &self.row.args[i].text)
+    }
+
+    fn name(&self, arg: usize) -> Result<String, ParseError> {
+        let text = self.text(Some(arg));
+        match is_identifier(text) {
+            true => Ok(text.to_string()),
+            false => self.error(Some(arg), ParseErrorKind::ExpectedName(text.to_string())),
+        }
+    }
+
+    // The delay, or an argument, as an operand
+    fn value(&self, arg: Option<usize>) -> Result<String, ParseError> {
+        let text = self.text(arg);
+        match text.strip_prefix('$') {
+            Some(var) if is_identifier(var) => Ok(var.to_string()),
+            Some(var) => self.error(arg, ParseErrorKind::ExpectedName(var.to_string())),
+            None if is_number(text) => Ok(text.to_string()),
+            None => match text.parse::<f64>() {
+                Ok(n) => Ok(n.to_string()),
+                Err(_) => self.error(arg, ParseErrorKind::BadNumber(text.to_string())),
+            },
+        }
+    }
+
+    fn values(&self, from: usize) -> Result<Vec<String>, ParseError> {
+        (from..self.row.args.len()).map(|i| self.value(Some(i))).collect()
+    }
+
+    // A computed JMPREL and the plain jumps right after it
+    fn table(&self, rest: &[Item]) -> Option<Vec<String>> {
+        if self.row.opcode.text != "JMPREL" || !self.text(Some(0)).starts_with('$') {
+            return None;
+        }
+        let labels: Vec<String> = rest
+            .iter()
+            .map_while(|item| match item {
+                Item::Row(r)
+                    if r.delay.text == "0"
+                        && matches!(r.opcode.text.as_str(), "JMPABS" | "JMPREL")
+                        && r.args.len() == 1
+                        && is_identifier(&r.args[0].text) =>
+                {
+                    Some(r.args[0].text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        (!labels.is_empty()).then_some(labels)
+    }
+
+    fn statement(&self) -> Result<String, ParseError> {
+        let opcode_text = self.row.opcode.text.as_str();
+        if opcode_text.starts_with("JMP") {
+            // The disassembler left only computed and out of range jumps as
+            // they were
+            let computed = self.row.args.first().is_none_or(|c| c.text.starts_with('$') || c.text.parse::<f64>().is_ok());
+            return if computed { self.raw() } else { self.goto() };
+        }
+        let step = parse::step(self.file, self.row)?;
+        Ok(match &step.command {
+            Command::Label(_) => {
+                let name = &opcode_text[1..];
+                if !is_identifier(name) {
+                    let kind = ParseErrorKind::ExpectedName(name.to_string());
+                    return Err(ParseError::new(self.file, self.row.line, self.original.opcode.column, kind));
+                }
+                format!(":{name}")
+            }
+            Command::Set { .. } => format!("let {} = {}", self.name(0)?, self.value(Some(1))?),
+            Command::Binary { op, .. } => {
+                let symbol = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Div => "/",
+                    BinaryOp::Mod => "%",
+                };
+                format!("let {} = {} {symbol} {}", self.name(0)?, self.value(Some(1))?, self.value(Some(2))?)
+            }
+            Command::Unary { op, .. } => {
+                let function = match op {
+                    UnaryOp::Floor => "floor",
+                    UnaryOp::Sin => "sin",
+                    UnaryOp::Cos => "cos",
+                    UnaryOp::Deg => "deg",
+                    UnaryOp::Rad => "rad",
+                };
+                format!("let {} = {function}({})", self.name(0)?, self.value(Some(1))?)
+            }
+            Command::Angle { .. } | Command::Rnd { .. } => {
+                let function = FUNCTIONS.iter().find(|(_, op, _)| *op == opcode_text).expect("ANGLE and RND are functions").0;
+                format!("let {} = {function}({})", self.name(0)?, self.values(1)?.join(", "))
+            }
+            _ => match CONSTRUCTORS.iter().find(|(_, op, ..)| *op == opcode_text) {
+                Some((name, ..)) => format!("{name}({})", self.values(0)?.join(", ")),
+                None => self.raw()?,
+            },
+        })
+    }
+
+    fn goto(&self) -> Result<String, ParseError> {
+        let label = self.name(0)?;
+        let cond = match self.row.opcode.text.as_str() {
+            "JMPABS" | "JMPREL" => return Ok(format!("goto {label}")),
+            "JMPZ" => return Ok(format!("goto {label} if not {}", self.value(Some(1))?)),
+            "JMPNZ" => return Ok(format!("goto {label} if {}", self.value(Some(1))?)),
+            "JMPE" => "==",
+            "JMPNE" => "!=",
+            "JMPL" => "<",
+            "JMPNL" => ">=",
+            "JMPG" => ">",
+            "JMPNG" => "<=",
+            op => return self.error(None, ParseErrorKind::UnknownOpcode(op.to_string())),
+        };
+        Ok(format!("goto {label} if {} {cond} {}", self.value(Some(1))?, self.value(Some(2))?))
+    }
+
+    // The row as an opcode call: numbers and variables as they are, the
+    // rest quoted
+    fn raw(&self) -> Result<String, ParseError> {
+        let mut args = Vec::new();
+        for (i, cell) in self.row.args.iter().enumerate() {
+            let text = &cell.text;
+            args.push(match text.strip_prefix('$') {
+                Some(_) => self.value(Some(i))?,
+                None if is_number(text) => text.clone(),
+                None if text.contains('"') => return self.error(Some(i), ParseErrorKind::BadNumber(text.clone())),
+                None => format!("\"{text}\""),
+            });
+        }
+        Ok(format!("{}({})", self.row.opcode.text, args.join(", ")))
+    }
+}
+
+// Whether the language reads the text back as the same number
+fn is_number(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
+        && text.parse::<f64>().is_ok_and(f64::is_finite)
+}
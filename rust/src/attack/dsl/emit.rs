@@ -0,0 +1,516 @@
+// Lowering to label source for the assembler. Expressions are taken apart
+// into one opcode per row through temporaries, conditions into chains of
+// conditional jumps, and a wait becomes the delay of the next row.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use super::syntax::{Arg, Cmp, Cond, Expr, ExprKind, Name, Stmt, StmtKind};
+use super::{CompileErrorKind, Error};
+use crate::attack::parse::{self, Cell, Row};
+use crate::attack::{arity, BinaryOp};
+
+// Bones, blasters and platforms by name: the opcode, then its required and
+// optional parameters in CSV order
+pub const CONSTRUCTORS: &[(&str, &str, &[&str], &[&str])] = &[
+    ("bone_v", "BoneV", &["x", "y", "height", "direction", "speed"], &["color"]),
+    ("bone_h", "BoneH", &["x", "y", "width", "direction", "speed"], &["color"]),
+    ("bone_v_repeat", "BoneVRepeat", &["x", "y", "height", "direction", "speed", "count", "spacing"], &[]),
+    ("bone_h_repeat", "BoneHRepeat", &["x", "y", "width", "direction", "speed", "count", "spacing"], &[]),
+    ("bone_stab", "BoneStab", &["direction", "height", "warning", "duration"], &[]),
+    ("sine_bones", "SineBones", &["count", "spacing", "speed", "height"], &[]),
+    ("blaster", "GasterBlaster", &["size", "x", "y", "end_x", "end_y", "angle", "aim", "fire"], &[]),
+    ("platform", "Platform", &["x", "y", "width", "direction", "speed"], &["kind"]),
+    ("platform_repeat", "PlatformRepeat", &["x", "y", "width", "direction", "speed", "count", "spacing"], &[]),
+];
+
+// Functions usable in expressions, with the opcode that works them out and
+// their argument count
+pub const FUNCTIONS: &[(&str, &str, usize)] = &[
+    ("floor", "FLOOR", 1),
+    ("sin", "SIN", 1),
+    ("cos", "COS", 1),
+    ("deg", "DEG", 1),
+    ("rad", "RAD", 1),
+    ("rnd", "RND", 1),
+    ("angle", "ANGLE", 4),
+];
+
+pub fn opcode(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "ADD",
+        BinaryOp::Sub => "SUB",
+        BinaryOp::Mul => "MUL",
+        BinaryOp::Div => "DIV",
+        BinaryOp::Mod => "MOD",
+    }
+}
+
+fn jump(cmp: Cmp, when: bool) -> &'static str {
+    match (cmp, when) {
+        (Cmp::Eq, true) | (Cmp::Ne, false) => "JMPE",
+        (Cmp::Ne, true) | (Cmp::Eq, false) => "JMPNE",
+        (Cmp::Lt, true) | (Cmp::Ge, false) => "JMPL",
+        (Cmp::Ge, true) | (Cmp::Lt, false) => "JMPNL",
+        (Cmp::Gt, true) | (Cmp::Le, false) => "JMPG",
+        (Cmp::Le, true) | (Cmp::Gt, false) => "JMPNG",
+    }
+}
+
+// Value of an expression made only of numbers, if it is one
+fn fold(expr: &Expr) -> Option<f64> {
+    let value = match &expr.kind {
+        ExprKind::Number(n, _) => *n,
+        ExprKind::Neg(e) => -fold(e)?,
+        ExprKind::Binary(op, a, b) => {
+            let (a, b) = (fold(a)?, fold(b)?);
+            match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                BinaryOp::Mod => a % b,
+            }
+        }
+        _ => return None,
+    };
+    value.is_finite().then_some(value)
+}
+
+pub fn emit(file: &str, program: &[Stmt]) -> Result<String, Error> {
+    let mut labels = HashSet::new();
+    collect_labels(program, &mut labels)?;
+    let mut emitter = Emitter { file, labels, lines: Vec::new(), wait: None, temps: 0, depth: 0, next_label: 0 };
+    emitter.block(program)?;
+    // A wait or label at the very end still needs a row to sit on
+    if emitter.wait.is_some() || emitter.lines.last().is_some_and(|l| l.ends_with(':')) {
+        emitter.nop();
+    }
+    Ok(emitter.lines.iter().map(|l| format!("{l}\n")).collect())
+}
+
+fn collect_labels<'a>(stmts: &'a [Stmt], labels: &mut HashSet<&'a str>) -> Result<(), Error> {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Label(name) | StmtKind::LabelRow(name) if !labels.insert(&name.text) => {
+                return Err((name.span.clone(), CompileErrorKind::DuplicateLabel(name.text.clone())));
+            }
+            StmtKind::Repeat(_, body) => collect_labels(body, labels)?,
+            StmtKind::If(_, then, otherwise) => {
+                collect_labels(then, labels)?;
+                collect_labels(otherwise, labels)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+struct Emitter<'a> {
+    file: &'a str,
+    labels: HashSet<&'a str>,
+    lines: Vec<String>,
+    // Delay for the next row
+    wait: Option<String>,
+    temps: usize,
+    // Nesting of repeats, which picks their counter
+    depth: usize,
+    next_label: usize,
+}
+
+impl<'a> Emitter<'a> {
+    fn row(&mut self, opcode: &str, args: &[String]) {
+        let delay = self.wait.take().unwrap_or_else(|| "0".to_string());
+        self.lines.push([&[delay, opcode.to_string()], args].concat().join(","));
+    }
+
+    // A row that only carries a wait
+    fn nop(&mut self) {
+        self.row("SET", &["_".to_string(), "0".to_string()]);
+    }
+
+    fn label(&mut self, name: &str) {
+        // The wait belongs before the label, not to every jump that lands
+        // on it
+        if self.wait.is_some() {
+            self.nop();
+        }
+        self.lines.push(format!("{name}:"));
+    }
+
+    fn new_label(&mut self) -> String {
+        self.next_label += 1;
+        format!("_l{}", self.next_label - 1)
+    }
+
+    fn check_label(&self, name: &Name) -> Result<(), Error> {
+        match self.labels.contains(name.text.as_str()) {
+            true => Ok(()),
+            false => Err((name.span.clone(), CompileErrorKind::UnknownLabel(name.text.clone()))),
+        }
+    }
+
+    fn block(&mut self, stmts: &'a [Stmt]) -> Result<(), Error> {
+        for stmt in stmts {
+            // Temporaries only live within a statement
+            self.temps = 0;
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) -> Result<(), Error> {
+        match &stmt.kind {
+            StmtKind::Let(name, value) => self.assign(&name.text, value)?,
+            StmtKind::Wait(value) => {
+                let delay = self.atom(value)?;
+                self.wait = match self.wait.take() {
+                    None => Some(delay),
+                    Some(wait) => match (wait.parse::<f64>(), delay.parse::<f64>()) {
+                        (Ok(a), Ok(b)) => Some((a + b).to_string()),
+                        _ => {
+                            self.wait = Some(wait);
+                            self.nop();
+                            Some(delay)
+                        }
+                    },
+                };
+            }
+            StmtKind::Repeat(count, body) => {
+                let counter = format!("_r{}", self.depth);
+                let value = self.atom(count)?;
+                self.row("SET", &[counter.clone(), value]);
+                // Only a count known to be positive can skip the check
+                let exit = match fold(count) {
+                    Some(n) if n > 0.0 => None,
+                    _ => {
+                        let exit = self.new_label();
+                        self.row("JMPNG", &[exit.clone(), format!("${counter}"), "0".to_string()]);
+                        Some(exit)
+                    }
+                };
+                let top = self.new_label();
+                self.label(&top);
+                self.depth += 1;
+                self.block(body)?;
+                self.depth -= 1;
+                self.row("SUB", &[counter.clone(), format!("${counter}"), "1".to_string()]);
+                self.row("JMPG", &[top, format!("${counter}"), "0".to_string()]);
+                if let Some(exit) = exit {
+                    self.label(&exit);
+                }
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let skip = self.new_label();
+                self.cond_jump(cond, &skip, false)?;
+                self.block(then)?;
+                if otherwise.is_empty() {
+                    self.label(&skip);
+                } else {
+                    let end = self.new_label();
+                    self.row("JMPABS", std::slice::from_ref(&end));
+                    self.label(&skip);
+                    self.block(otherwise)?;
+                    self.label(&end);
+                }
+            }
+            StmtKind::Goto(label, cond) => {
+                self.check_label(label)?;
+                match cond {
+                    Some(cond) => self.cond_jump(cond, &label.text, true)?,
+                    None => self.row("JMPABS", std::slice::from_ref(&label.text)),
+                }
+            }
+            StmtKind::Choose(value, labels) => {
+                for label in labels {
+                    self.check_label(label)?;
+                }
+                let value = self.atom(value)?;
+                self.row("JMPREL", &[value]);
+                for label in labels {
+                    self.row("JMPABS", std::slice::from_ref(&label.text));
+                }
+            }
+            StmtKind::Label(name) => self.label(&name.text),
+            StmtKind::LabelRow(name) => self.row(&format!(":{}", name.text), &[]),
+            StmtKind::Call(name, args) => self.call(name, args, &stmt.span)?,
+        }
+        Ok(())
+    }
+
+    // A number or variable as it goes in a cell, working the expression out
+    // into a temporary first if it has to be
+    fn atom(&mut self, expr: &Expr) -> Result<String, Error> {
+        match &expr.kind {
+            ExprKind::Number(_, text) => Ok(text.clone()),
+            ExprKind::Var(name) => Ok(format!("${name}")),
+            ExprKind::Str(_) => Err((expr.span.clone(), CompileErrorKind::NotANumber)),
+            _ => match fold(expr) {
+                Some(n) => Ok(n.to_string()),
+                None => {
+                    let temp = format!("_t{}", self.temps);
+                    self.temps += 1;
+                    self.assign(&temp, expr)?;
+                    Ok(format!("${temp}"))
+                }
+            },
+        }
+    }
+
+    fn assign(&mut self, var: &str, expr: &Expr) -> Result<(), Error> {
+        if let Some(n) = fold(expr).filter(|_| !matches!(expr.kind, ExprKind::Number(..))) {
+            self.row("SET", &[var.to_string(), n.to_string()]);
+            return Ok(());
+        }
+        match &expr.kind {
+            ExprKind::Number(..) | ExprKind::Var(_) | ExprKind::Str(_) => {
+                let value = self.atom(expr)?;
+                self.row("SET", &[var.to_string(), value]);
+            }
+            ExprKind::Binary(op, a, b) => {
+                let (a, b) = (self.atom(a)?, self.atom(b)?);
+                self.row(opcode(*op), &[var.to_string(), a, b]);
+            }
+            ExprKind::Neg(a) => {
+                let a = self.atom(a)?;
+                self.row("SUB", &[var.to_string(), "0".to_string(), a]);
+            }
+            ExprKind::Call(function, args) => {
+                let Some(&(_, opcode, count)) = FUNCTIONS.iter().find(|(name, ..)| name == function) else {
+                    return Err((expr.span.clone(), CompileErrorKind::UnknownFunction(function.clone())));
+                };
+                if args.len() != count {
+                    let kind = CompileErrorKind::ArgCount { function: function.clone(), min: count, max: count, found: args.len() };
+                    return Err((expr.span.clone(), kind));
+                }
+                let mut cells = vec![var.to_string()];
+                for arg in args {
+                    cells.push(self.atom(arg)?);
+                }
+                self.row(opcode, &cells);
+            }
+        }
+        Ok(())
+    }
+
+    // Jumps to `label` when the condition comes out as `when`
+    fn cond_jump(&mut self, cond: &Cond, label: &str, when: bool) -> Result<(), Error> {
+        match cond {
+            Cond::Truthy(value) => {
+                let value = self.atom(value)?;
+                self.row(if when { "JMPNZ" } else { "JMPZ" }, &[label.to_string(), value]);
+            }
+            Cond::Compare(cmp, a, b) => {
+                let (a, b) = (self.atom(a)?, self.atom(b)?);
+                self.row(jump(*cmp, when), &[label.to_string(), a, b]);
+            }
+            Cond::Not(cond) => self.cond_jump(cond, label, !when)?,
+            // Both have to hold to jump, either fails to fall through
+            Cond::And(a, b) if when => {
+                let skip = self.new_label();
+                self.cond_jump(a, &skip, false)?;
+                self.cond_jump(b, label, true)?;
+                self.label(&skip);
+            }
+            Cond::And(a, b) => {
+                self.cond_jump(a, label, false)?;
+                self.cond_jump(b, label, false)?;
+            }
+            Cond::Or(a, b) if when => {
+                self.cond_jump(a, label, true)?;
+                self.cond_jump(b, label, true)?;
+            }
+            Cond::Or(a, b) => {
+                let skip = self.new_label();
+                self.cond_jump(a, &skip, true)?;
+                self.cond_jump(b, label, false)?;
+                self.label(&skip);
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &Name, args: &[Arg], span: &Range<usize>) -> Result<(), Error> {
+        if let Some(&(_, opcode, required, optional)) = CONSTRUCTORS.iter().find(|(n, ..)| *n == name.text) {
+            let params = [required, optional].concat();
+            let mut values: Vec<Option<&Expr>> = vec![None; params.len()];
+            let (mut positional, mut named) = (0, false);
+            for arg in args {
+                let i = match &arg.name {
+                    Some(param) => {
+                        named = true;
+                        match params.iter().position(|p| *p == param.text) {
+                            Some(i) if values[i].is_some() => {
+                                return Err((param.span.clone(), CompileErrorKind::DuplicateArg(param.text.clone())));
+                            }
+                            Some(i) => i,
+                            None => return Err((param.span.clone(), CompileErrorKind::UnknownArg(param.text.clone()))),
+                        }
+                    }
+                    None if named => {
+                        let kind = CompileErrorKind::Expected { expected: "a named argument", found: "a positional one".to_string() };
+                        return Err((arg.value.span.clone(), kind));
+                    }
+                    None if positional == params.len() => {
+                        let kind = CompileErrorKind::ArgCount {
+                            function: name.text.clone(),
+                            min: required.len(),
+                            max: params.len(),
+                            found: args.len(),
+                        };
+                        return Err((span.clone(), kind));
+                    }
+                    None => {
+                        positional += 1;
+                        positional - 1
+                    }
+                };
+                values[i] = Some(&arg.value);
+            }
+            while values.len() > required.len() && values.last().is_some_and(Option::is_none) {
+                values.pop();
+            }
+            let mut cells = Vec::new();
+            for (value, param) in values.iter().zip(&params) {
+                match value {
+                    Some(value) => cells.push(self.atom(value)?),
+                    None => return Err((span.clone(), CompileErrorKind::MissingArg(param))),
+                }
+            }
+            self.row(opcode, &cells);
+            return Ok(());
+        }
+
+        if arity(&name.text).is_none() {
+            return Err((name.span.clone(), CompileErrorKind::UnknownFunction(name.text.clone())));
+        }
+        let mut cells = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            if let Some(param) = &arg.name {
+                let kind = CompileErrorKind::Expected { expected: "a positional argument", found: format!("{:?}", param.text) };
+                return Err((param.span.clone(), kind));
+            }
+            match &arg.value.kind {
+                ExprKind::Str(_) if i == 0 && name.text.starts_with("JMP") => {
+                    return Err((arg.value.span.clone(), CompileErrorKind::JumpByName));
+                }
+                ExprKind::Str(text) if text.contains(',') => {
+                    return Err((arg.value.span.clone(), CompileErrorKind::BadText(text.clone())));
+                }
+                ExprKind::Str(text) => cells.push(text.clone()),
+                _ => cells.push(self.atom(&arg.value)?),
+            }
+        }
+        // Typed the way the game page would read the row
+        let cell = |text: &str, column| Cell { text: text.to_string(), column };
+        let mut row = Row {
+            line: 0,
+            delay: cell("0", 0),
+            opcode: cell(&name.text, 0),
+            args: cells.iter().enumerate().map(|(i, text)| cell(text, i + 1)).collect(),
+        };
+        while row.args.last().is_some_and(|c| c.text.is_empty()) {
+            row.args.pop();
+        }
+        if let Err(e) = parse::step(self.file, &row) {
+            let span = args.get(e.column.wrapping_sub(1)).map_or(span.clone(), |a| a.value.span.clone());
+            return Err((span, CompileErrorKind::BadRow(e.kind)));
+        }
+        self.row(&name.text, &cells);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::syntax::parse;
+    use super::*;
+
+    fn emit_source(source: &str) -> Result<String, Error> {
+        emit("t", &parse(source).unwrap())
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(emit_source("let x = a + 2 * -b").unwrap(), "0,SUB,_t1,0,$b\n0,MUL,_t0,2,$_t1\n0,ADD,x,$a,$_t0\n");
+        assert_eq!(emit_source("let x = 1 / 4 + 1").unwrap(), "0,SET,x,1.25\n");
+        assert_eq!(emit_source("let x = -0.50").unwrap(), "0,SET,x,-0.50\n");
+        assert_eq!(emit_source("let x = angle(a, b, c, d) % 360").unwrap(), "0,ANGLE,_t0,$a,$b,$c,$d\n0,MOD,x,$_t0,360\n");
+        assert_eq!(emit_source("let x = floor(rnd(5))").unwrap(), "0,RND,_t0,5\n0,FLOOR,x,$_t0\n");
+    }
+
+    #[test]
+    fn test_waits() {
+        assert_eq!(emit_source("wait 0.5\nwait 0.25\nTLPause()").unwrap(), "0.75,TLPause\n");
+        assert_eq!(emit_source("wait 1\nwait t\nTLPause()").unwrap(), "1,SET,_,0\n$t,TLPause\n");
+        assert_eq!(emit_source("wait 1\nEnd:\nEndAttack()").unwrap(), "1,SET,_,0\nEnd:\n0,EndAttack\n");
+        assert_eq!(emit_source("wait a * 2\nTLPause()\nwait 1").unwrap(), "0,MUL,_t0,$a,2\n$_t0,TLPause\n1,SET,_,0\n");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(
+            emit_source("repeat 3 {\n    Sound(\"Flash\")\n    wait 0.5\n}").unwrap(),
+            "0,SET,_r0,3\n_l0:\n0,Sound,Flash\n0.5,SUB,_r0,$_r0,1\n0,JMPG,_l0,$_r0,0\n"
+        );
+        assert_eq!(
+            emit_source("repeat n { repeat 2 { TLPause() } }").unwrap(),
+            "0,SET,_r0,$n\n0,JMPNG,_l0,$_r0,0\n_l1:\n0,SET,_r1,2\n_l2:\n0,TLPause\n0,SUB,_r1,$_r1,1\n0,JMPG,_l2,$_r1,0\n\
+             0,SUB,_r0,$_r0,1\n0,JMPG,_l1,$_r0,0\n_l0:\n0,SET,_,0\n"
+        );
+        assert_eq!(
+            emit_source("if x < 1 and y { TLPause() } else { TLResume() }").unwrap(),
+            "0,JMPNL,_l0,$x,1\n0,JMPZ,_l0,$y\n0,TLPause\n0,JMPABS,_l1\n_l0:\n0,TLResume\n_l1:\n0,SET,_,0\n"
+        );
+        assert_eq!(
+            emit_source("Top:\ngoto Top if x >= 1 and not y or z == 2").unwrap(),
+            "Top:\n0,JMPL,_l0,$x,1\n0,JMPZ,Top,$y\n_l0:\n0,JMPE,Top,$z,2\n"
+        );
+        assert_eq!(
+            emit_source("A:\n:B\nchoose n + 1 { A, B }").unwrap(),
+            "A:\n0,:B\n0,ADD,_t0,$n,1\n0,JMPREL,$_t0\n0,JMPABS,A\n0,JMPABS,B\n"
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_eq!(
+            emit_source("bone_v(1, 2, speed: 240, direction: 0, height: h * 2)").unwrap(),
+            "0,MUL,_t0,$h,2\n0,BoneV,1,2,$_t0,0,240\n"
+        );
+        assert_eq!(emit_source("platform(1, 2, 3, 0, 100, kind: 1)").unwrap(), "0,Platform,1,2,3,0,100,1\n");
+        assert_eq!(emit_source("SansText(\"Hey there.\")\nSansAnimation()").unwrap(), "0,SansText,Hey there.\n0,SansAnimation\n");
+        assert_eq!(emit_source("JMPREL(n)").unwrap(), "0,JMPREL,$n\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| emit_source(source).unwrap_err();
+        assert_eq!(error("goto Nowhere"), (5..12, CompileErrorKind::UnknownLabel("Nowhere".into())));
+        assert_eq!(error("A:\nrepeat 2 { :A }"), (15..16, CompileErrorKind::DuplicateLabel("A".into())));
+        assert_eq!(error("let x = cosine(1)"), (8..17, CompileErrorKind::UnknownFunction("cosine".into())));
+        assert_eq!(
+            error("let x = angle(1)"),
+            (8..16, CompileErrorKind::ArgCount { function: "angle".into(), min: 4, max: 4, found: 1 })
+        );
+        assert_eq!(error("wait \"soon\""), (5..11, CompileErrorKind::NotANumber));
+        assert_eq!(error("bone_v(1, 2, 3, 4)"), (0..18, CompileErrorKind::MissingArg("speed")));
+        assert_eq!(error("bone_v(1, colour: 2)"), (10..16, CompileErrorKind::UnknownArg("colour".into())));
+        assert_eq!(error("bone_v(1, x: 2)"), (10..11, CompileErrorKind::DuplicateArg("x".into())));
+        assert_eq!(
+            error("bone_v(x: 1, 2)"),
+            (13..14, CompileErrorKind::Expected { expected: "a named argument", found: "a positional one".into() })
+        );
+        assert_eq!(
+            error("sine_bones(1, 2, 3, 4, 5)"),
+            (0..25, CompileErrorKind::ArgCount { function: "sine_bones".into(), min: 4, max: 4, found: 5 })
+        );
+        assert_eq!(error("Bones(1)"), (0..5, CompileErrorKind::UnknownFunction("Bones".into())));
+        assert_eq!(error("JMPABS(\"Top\")"), (7..12, CompileErrorKind::JumpByName));
+        assert_eq!(error("SansText(\"a, b\")"), (9..15, CompileErrorKind::BadText("a, b".into())));
+        assert_eq!(
+            error("Sound(\"Flash\", \"fast\")"),
+            (15..21, CompileErrorKind::BadRow(crate::attack::ParseErrorKind::BadNumber("fast".into())))
+        );
+    }
+}
@@ -0,0 +1,632 @@
+// Tokens and the syntax tree. Newlines end statements except inside
+// parentheses, so long calls can be wrapped.
+
+use std::ops::Range;
+
+use super::{CompileErrorKind, Error};
+use crate::attack::BinaryOp;
+
+const KEYWORDS: &[&str] = &["let", "wait", "repeat", "if", "else", "goto", "choose", "and", "or", "not"];
+
+// Longest first, so <= is not read as < and =
+const SYMBOLS: &[&str] = &["==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "/", "%", "(", ")", "{", "}", ",", ":"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Sym(&'static str),
+    Newline,
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    span: Range<usize>,
+}
+
+impl Tok {
+    fn describe(&self) -> String {
+        match self {
+            Tok::Ident(s) | Tok::Number(s) => format!("{s:?}"),
+            Tok::Str(_) => "a string".to_string(),
+            Tok::Sym(s) => format!("{s:?}"),
+            Tok::Newline => "end of line".to_string(),
+            Tok::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+// A name the language lets a script use for a variable or label. inf, nan
+// and infinity read as numbers, so the assembler would take them for a row
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&s)
+        && s.parse::<f64>().is_err()
+}
+
+fn lex(source: &str) -> Result<Vec<Token>, Error> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let digit_at = |j: usize| bytes.get(j).is_some_and(u8::is_ascii_digit);
+        let tok = match c {
+            b'\n' => {
+                i += 1;
+                if depth > 0 {
+                    continue;
+                }
+                Tok::Newline
+            }
+            b' ' | b'\t' | b'\r' => {
+                i += 1;
+                continue;
+            }
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'"' => {
+                let end = source[i + 1..].find(['"', '\n']).map(|n| i + 1 + n);
+                match end {
+                    Some(end) if bytes[end] == b'"' => {
+                        i = end + 1;
+                        Tok::Str(source[start + 1..end].to_string())
+                    }
+                    _ => return Err((start..end.unwrap_or(bytes.len()), CompileErrorKind::UnterminatedString)),
+                }
+            }
+            _ if c.is_ascii_digit() || (c == b'.' && digit_at(i + 1)) => {
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                if matches!(bytes.get(i), Some(b'e' | b'E')) {
+                    let sign = usize::from(matches!(bytes.get(i + 1), Some(b'+' | b'-')));
+                    if digit_at(i + 1 + sign) {
+                        i += 1 + sign;
+                        while digit_at(i) {
+                            i += 1;
+                        }
+                    }
+                }
+                let text = &source[start..i];
+                if text.parse::<f64>().is_err() {
+                    return Err((start..i, CompileErrorKind::Expected { expected: "a number", found: format!("{text:?}") }));
+                }
+                Tok::Number(text.to_string())
+            }
+            _ if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                Tok::Ident(source[start..i].to_string())
+            }
+            _ => {
+                let Some(&sym) = SYMBOLS.iter().find(|s| source[i..].starts_with(**s)) else {
+                    let c = source[i..].chars().next().expect("i is on a char boundary");
+                    return Err((i..i + c.len_utf8(), CompileErrorKind::UnexpectedChar(c)));
+                };
+                i += sym.len();
+                match sym {
+                    "(" => depth += 1,
+                    ")" => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                Tok::Sym(sym)
+            }
+        };
+        tokens.push(Token { tok, span: start..i });
+    }
+    tokens.push(Token { tok: Tok::Eof, span: bytes.len()..bytes.len() });
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    // The text as written, so numbers reach the CSV unchanged
+    Number(f64, String),
+    Var(String),
+    Str(String),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cond {
+    // Not zero
+    Truthy(Expr),
+    Compare(Cmp, Expr, Expr),
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+    pub text: String,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    pub name: Option<Name>,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Let(Name, Expr),
+    Wait(Expr),
+    Repeat(Expr, Vec<Stmt>),
+    If(Cond, Vec<Stmt>, Vec<Stmt>),
+    Goto(Name, Option<Cond>),
+    Choose(Expr, Vec<Name>),
+    // `Name:` names the next row, `:Name` is a row of its own
+    Label(Name),
+    LabelRow(Name),
+    Call(Name, Vec<Arg>),
+}
+
+pub fn parse(source: &str) -> Result<Vec<Stmt>, Error> {
+    let mut parser = Parser { tokens: lex(source)?, pos: 0 };
+    parser.statements(false)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    fn peek_at(&self, ahead: usize) -> &Tok {
+        &self.tokens[(self.pos + ahead).min(self.tokens.len() - 1)].tok
+    }
+
+    fn span(&self) -> Range<usize> {
+        self.tokens[self.pos].span.clone()
+    }
+
+    // End of the last token taken
+    fn end(&self) -> usize {
+        self.pos.checked_sub(1).map_or(0, |i| self.tokens[i].span.end)
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.tok != Tok::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        matches!(self.peek(), Tok::Sym(s) if *s == sym)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(s) if s == keyword)
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        let found = self.is_sym(sym);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn error<T>(&self, expected: &'static str) -> Result<T, Error> {
+        Err((self.span(), CompileErrorKind::Expected { expected, found: self.peek().describe() }))
+    }
+
+    fn expect(&mut self, sym: &'static str) -> Result<(), Error> {
+        match self.eat(sym) {
+            true => Ok(()),
+            false => self.error(sym),
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while *self.peek() == Tok::Newline {
+            self.pos += 1;
+        }
+    }
+
+    // A variable or label name
+    fn name(&mut self) -> Result<Name, Error> {
+        let span = self.span();
+        match self.peek().clone() {
+            Tok::Ident(text) if text.starts_with('_') => Err((span, CompileErrorKind::ReservedName(text))),
+            Tok::Ident(text) if is_identifier(&text) => {
+                self.pos += 1;
+                Ok(Name { text, span })
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn statements(&mut self, block: bool) -> Result<Vec<Stmt>, Error> {
+        let mut stmts = Vec::new();
+        loop {
+            self.skip_newlines();
+            if block && self.eat("}") {
+                return Ok(stmts);
+            }
+            if *self.peek() == Tok::Eof {
+                return match block {
+                    true => self.error("\"}\""),
+                    false => Ok(stmts),
+                };
+            }
+            stmts.push(self.statement()?);
+            match self.peek() {
+                Tok::Newline | Tok::Eof => {}
+                Tok::Sym("}") if block => {}
+                _ => return self.error("end of line"),
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, Error> {
+        self.expect("{")?;
+        self.statements(true)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, Error> {
+        let start = self.span().start;
+        let kind = match self.peek().clone() {
+            Tok::Ident(k) if k == "let" => {
+                self.pos += 1;
+                let name = self.name()?;
+                self.expect("=")?;
+                StmtKind::Let(name, self.expr()?)
+            }
+            Tok::Ident(k) if k == "wait" => {
+                self.pos += 1;
+                StmtKind::Wait(self.expr()?)
+            }
+            Tok::Ident(k) if k == "repeat" => {
+                self.pos += 1;
+                let count = self.expr()?;
+                StmtKind::Repeat(count, self.block()?)
+            }
+            Tok::Ident(k) if k == "if" => {
+                self.pos += 1;
+                let cond = self.cond()?;
+                let then = self.block()?;
+                let otherwise = match self.eat_keyword("else") {
+                    true if self.is_keyword("if") => vec![self.statement()?],
+                    true => self.block()?,
+                    false => vec![],
+                };
+                StmtKind::If(cond, then, otherwise)
+            }
+            Tok::Ident(k) if k == "goto" => {
+                self.pos += 1;
+                let label = self.name()?;
+                let cond = match self.eat_keyword("if") {
+                    true => Some(self.cond()?),
+                    false => None,
+                };
+                StmtKind::Goto(label, cond)
+            }
+            Tok::Ident(k) if k == "choose" => {
+                self.pos += 1;
+                let value = self.expr()?;
+                self.expect("{")?;
+                let mut labels = Vec::new();
+                loop {
+                    self.skip_newlines();
+                    if self.eat("}") {
+                        break;
+                    }
+                    labels.push(self.name()?);
+                    self.skip_newlines();
+                    if !self.eat(",") {
+                        self.skip_newlines();
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                StmtKind::Choose(value, labels)
+            }
+            Tok::Sym(":") => {
+                self.pos += 1;
+                StmtKind::LabelRow(self.name()?)
+            }
+            Tok::Ident(_) if *self.peek_at(1) == Tok::Sym(":") => {
+                let name = self.name()?;
+                self.pos += 1;
+                StmtKind::Label(name)
+            }
+            Tok::Ident(text) if *self.peek_at(1) == Tok::Sym("(") => {
+                let name = Name { text, span: self.next().span };
+                StmtKind::Call(name, self.args()?)
+            }
+            _ => return self.error("a statement"),
+        };
+        Ok(Stmt { kind, span: start..self.end() })
+    }
+
+    fn args(&mut self) -> Result<Vec<Arg>, Error> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        while !self.eat(")") {
+            let name = match (self.peek().clone(), self.peek_at(1)) {
+                (Tok::Ident(text), Tok::Sym(":")) => {
+                    let name = Name { text, span: self.next().span };
+                    self.pos += 1;
+                    Some(name)
+                }
+                _ => None,
+            };
+            args.push(Arg { name, value: self.expr()? });
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    fn cond(&mut self) -> Result<Cond, Error> {
+        let mut cond = self.and()?;
+        while self.eat_keyword("or") {
+            cond = Cond::Or(Box::new(cond), Box::new(self.and()?));
+        }
+        Ok(cond)
+    }
+
+    fn and(&mut self) -> Result<Cond, Error> {
+        let mut cond = self.not()?;
+        while self.eat_keyword("and") {
+            cond = Cond::And(Box::new(cond), Box::new(self.not()?));
+        }
+        Ok(cond)
+    }
+
+    fn not(&mut self) -> Result<Cond, Error> {
+        if self.eat_keyword("not") {
+            return Ok(Cond::Not(Box::new(self.not()?)));
+        }
+        // A parenthesised condition, unless the parentheses only group the
+        // left side of an expression
+        if self.is_sym("(") {
+            let start = self.pos;
+            self.pos += 1;
+            if let Ok(cond) = self.cond() {
+                let closed = self.eat(")");
+                let end = matches!(self.peek(), Tok::Newline | Tok::Eof | Tok::Sym("{" | ")"));
+                if closed && (end || self.is_keyword("and") || self.is_keyword("or")) {
+                    return Ok(cond);
+                }
+            }
+            self.pos = start;
+        }
+        let lhs = self.expr()?;
+        let cmp = match self.peek() {
+            Tok::Sym("==") => Cmp::Eq,
+            Tok::Sym("!=") => Cmp::Ne,
+            Tok::Sym("<") => Cmp::Lt,
+            Tok::Sym("<=") => Cmp::Le,
+            Tok::Sym(">") => Cmp::Gt,
+            Tok::Sym(">=") => Cmp::Ge,
+            _ => return Ok(Cond::Truthy(lhs)),
+        };
+        self.pos += 1;
+        Ok(Cond::Compare(cmp, lhs, self.expr()?))
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Sym("+") => BinaryOp::Add,
+                Tok::Sym("-") => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = binary(op, lhs, self.term()?);
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, Error> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Sym("*") => BinaryOp::Mul,
+                Tok::Sym("/") => BinaryOp::Div,
+                Tok::Sym("%") => BinaryOp::Mod,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = binary(op, lhs, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let start = self.span().start;
+        if !self.eat("-") {
+            return self.primary();
+        }
+        let operand = self.unary()?;
+        let span = start..operand.span.end;
+        // Negative literals stay literals, as they are written in the CSV
+        let kind = match operand.kind {
+            ExprKind::Number(n, text) => ExprKind::Number(-n, format!("-{text}")),
+            _ => ExprKind::Neg(Box::new(operand)),
+        };
+        Ok(Expr { kind, span })
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let span = self.span();
+        let kind = match self.peek().clone() {
+            Tok::Number(text) => {
+                self.pos += 1;
+                ExprKind::Number(text.parse().expect("the lexer checked it"), text)
+            }
+            Tok::Str(text) => {
+                self.pos += 1;
+                ExprKind::Str(text)
+            }
+            Tok::Ident(text) if *self.peek_at(1) == Tok::Sym("(") => {
+                self.pos += 1;
+                self.expect("(")?;
+                let mut args = Vec::new();
+                while !self.eat(")") {
+                    args.push(self.expr()?);
+                    if !self.eat(",") {
+                        self.expect(")")?;
+                        break;
+                    }
+                }
+                ExprKind::Call(text, args)
+            }
+            Tok::Ident(_) => ExprKind::Var(self.name()?.text),
+            Tok::Sym("(") => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                self.expect(")")?;
+                return Ok(Expr { kind: inner.kind, span: span.start..self.end() });
+            }
+            _ => return self.error("a number, variable or call"),
+        };
+        Ok(Expr { kind, span: span.start..self.end() })
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+    let span = lhs.span.start..rhs.span.end;
+    Expr { kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(text: &str, span: Range<usize>) -> Expr {
+        Expr { kind: ExprKind::Number(text.parse().unwrap(), text.to_string()), span }
+    }
+
+    fn var(name: &str, span: Range<usize>) -> Expr {
+        Expr { kind: ExprKind::Var(name.to_string()), span }
+    }
+
+    #[test]
+    fn test_precedence_and_spans() {
+        let stmts = parse("let x = a + 2 * -3.5").unwrap();
+        let product = binary(BinaryOp::Mul, number("2", 12..13), number("-3.5", 16..20));
+        let sum = binary(BinaryOp::Add, var("a", 8..9), product);
+        assert_eq!(stmts[0].kind, StmtKind::Let(Name { text: "x".into(), span: 4..5 }, sum));
+        assert_eq!(stmts[0].span, 0..20);
+
+        let stmts = parse("let y = (a - 1) * -b\n").unwrap();
+        let StmtKind::Let(_, Expr { kind: ExprKind::Binary(BinaryOp::Mul, lhs, rhs), .. }) = &stmts[0].kind else {
+            panic!("{stmts:?}")
+        };
+        assert_eq!(lhs.span, 8..15);
+        assert!(matches!(&lhs.kind, ExprKind::Binary(BinaryOp::Sub, ..)));
+        assert_eq!(rhs.kind, ExprKind::Neg(Box::new(var("b", 19..20))));
+    }
+
+    #[test]
+    fn test_conditions() {
+        let cond = |source: &str| match parse(&format!("goto L if {source}")).unwrap().remove(0).kind {
+            StmtKind::Goto(_, Some(cond)) => cond,
+            kind => panic!("{kind:?}"),
+        };
+        assert!(matches!(cond("a"), Cond::Truthy(_)));
+        assert!(matches!(cond("a < b or c and not d"), Cond::Or(_, c) if matches!(*c, Cond::And(_, ref d) if matches!(**d, Cond::Not(_)))));
+        assert!(matches!(cond("(a or b) and c"), Cond::And(ab, _) if matches!(*ab, Cond::Or(..))));
+        // Parentheses around the left side of a comparison
+        assert!(matches!(cond("(a + 1) * 2 >= b"), Cond::Compare(Cmp::Ge, ..)));
+    }
+
+    #[test]
+    fn test_statements_and_blocks() {
+        let source = "\
+# comment
+repeat 3 {
+    bone_v(1, 2,
+           speed: 3)  # wrapped
+    wait 0.5
+}
+if x == 1 { goto End } else if x { :Top } else {
+}
+choose n {
+    A, B,
+}
+End:
+";
+        let stmts = parse(source).unwrap();
+        assert_eq!(stmts.len(), 4);
+        let StmtKind::Repeat(_, body) = &stmts[0].kind else { panic!() };
+        let StmtKind::Call(name, args) = &body[0].kind else { panic!() };
+        assert_eq!((name.text.as_str(), args.len()), ("bone_v", 3));
+        assert_eq!(args[2].name.as_ref().unwrap().text, "speed");
+        let StmtKind::If(_, then, otherwise) = &stmts[1].kind else { panic!() };
+        assert!(matches!(then[0].kind, StmtKind::Goto(_, None)));
+        assert!(matches!(&otherwise[0].kind, StmtKind::If(_, top, e) if matches!(top[0].kind, StmtKind::LabelRow(_)) && e.is_empty()));
+        assert!(matches!(&stmts[2].kind, StmtKind::Choose(_, labels) if labels.len() == 2));
+        assert!(matches!(&stmts[3].kind, StmtKind::Label(name) if name.text == "End"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| parse(source).unwrap_err();
+        assert_eq!(error("let x = 1 +"), (11..11, CompileErrorKind::Expected { expected: "a number, variable or call", found: "end of file".into() }));
+        assert_eq!(error("wait 1 2"), (7..8, CompileErrorKind::Expected { expected: "end of line", found: "\"2\"".into() }));
+        assert_eq!(error("let _t0 = 1"), (4..7, CompileErrorKind::ReservedName("_t0".into())));
+        assert_eq!(error("let if = 1"), (4..6, CompileErrorKind::Expected { expected: "a name", found: "\"if\"".into() }));
+        assert_eq!(error("SansText(\"hi)\n"), (9..13, CompileErrorKind::UnterminatedString));
+        assert_eq!(error("wait 1 ^ 2"), (7..8, CompileErrorKind::UnexpectedChar('^')));
+        assert_eq!(error("wait 1.2.3"), (5..10, CompileErrorKind::Expected { expected: "a number", found: "\"1.2.3\"".into() }));
+        assert_eq!(error("repeat 2 {\nwait 1\n"), (18..18, CompileErrorKind::Expected { expected: "\"}\"", found: "end of file".into() }));
+    }
+}
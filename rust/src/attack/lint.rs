@@ -0,0 +1,421 @@
+// Static checks for attack scripts. Rows that do not parse are reported and
+// then treated as falling through to the next row, so one bad row does not
+// hide the rest.
+//
+// The flow checks need to know where jumps can go. A computed JMPABS may go
+// to any label (any row if there are none); a computed JMPREL is taken to
+// index the table of jump rows right after it, which is how the bundled
+// scripts use it. In a script with labels, a jump by line number should land
+// on a label: anywhere else is inside a block, and inserting a row above it
+// silently moves the target.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use super::parse::{self, parse_rows, ParseErrorKind, Row};
+use super::{Command, Step, Target, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    UnknownOpcode(String),
+    ArgCount { opcode: String, min: usize, max: usize, found: usize },
+    // Any other row the game cannot make sense of
+    Syntax(ParseErrorKind),
+    JumpOutOfRange(i64),
+    JumpIntoBlock(usize),
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    UnusedLabel(String),
+    // The game reads a variable that was never set as 0
+    ReadBeforeSet(String),
+    NoEndAttack,
+}
+
+impl LintKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintKind::JumpIntoBlock(_) | LintKind::DuplicateLabel(_) | LintKind::UnusedLabel(_) | LintKind::ReadBeforeSet(_) => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl From<ParseErrorKind> for LintKind {
+    fn from(kind: ParseErrorKind) -> Self {
+        match kind {
+            ParseErrorKind::UnknownOpcode(op) => LintKind::UnknownOpcode(op),
+            ParseErrorKind::ArgCount { opcode, min, max, found } => LintKind::ArgCount { opcode, min, max, found },
+            kind => LintKind::Syntax(kind),
+        }
+    }
+}
+
+impl std::fmt::Display for LintKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintKind::UnknownOpcode(op) => write!(f, "{}", ParseErrorKind::UnknownOpcode(op.clone())),
+            LintKind::ArgCount { opcode, min, max, found } => {
+                let kind = ParseErrorKind::ArgCount { opcode: opcode.clone(), min: *min, max: *max, found: *found };
+                write!(f, "{kind}")
+            }
+            LintKind::Syntax(kind) => write!(f, "{kind}"),
+            LintKind::JumpOutOfRange(line) => write!(f, "jump to line {line}, outside the script"),
+            LintKind::JumpIntoBlock(line) => write!(f, "jump to line {line}, which is not a label"),
+            LintKind::UnknownLabel(l) => write!(f, "jump to unknown label {l}"),
+            LintKind::DuplicateLabel(l) => write!(f, "label {l} is defined again; jumps go to the last one"),
+            LintKind::UnusedLabel(l) => write!(f, "label {l} is never jumped to"),
+            LintKind::ReadBeforeSet(v) => write!(f, "${v} may be read before it is set"),
+            LintKind::NoEndAttack => write!(f, "no path from the first row reaches EndAttack"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: LintKind,
+}
+
+impl Lint {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}:{}:{}: {severity}: {}", self.file, self.line, self.column, self.kind)
+    }
+}
+
+pub fn lint_file(path: &Path) -> Vec<Lint> {
+    let file = path.display().to_string();
+    match std::fs::read_to_string(path) {
+        Ok(source) => lint(&file, &source),
+        Err(e) => vec![Lint { file, line: 0, column: 0, kind: LintKind::Syntax(ParseErrorKind::Io(e.to_string())) }],
+    }
+}
+
+pub fn lint(file: &str, source: &str) -> Vec<Lint> {
+    let rows = match parse_rows(file, source) {
+        Ok(rows) => rows,
+        Err(e) => return vec![Lint { file: e.file, line: e.line, column: e.column, kind: e.kind.into() }],
+    };
+    let mut linter = Linter { file, rows: &rows, steps: Vec::new(), labels: HashMap::new(), lints: Vec::new() };
+    linter.run();
+    let mut lints = linter.lints;
+    lints.sort_by_key(|l| (l.line, l.column));
+    lints
+}
+
+struct Linter<'a> {
+    file: &'a str,
+    rows: &'a [Row],
+    // None for rows that did not parse
+    steps: Vec<Option<Step>>,
+    labels: HashMap<String, usize>,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, line: usize, column: usize, kind: LintKind) {
+        self.lints.push(Lint { file: self.file.to_string(), line, column, kind });
+    }
+
+    fn step(&self, line: usize) -> Option<&Step> {
+        self.steps.get(line.wrapping_sub(1)).and_then(Option::as_ref)
+    }
+
+    fn arg_column(&self, line: usize) -> usize {
+        let row = &self.rows[line - 1];
+        row.args.first().map_or(row.opcode.column, |c| c.column)
+    }
+
+    fn run(&mut self) {
+        for row in self.rows {
+            match parse::step(self.file, row) {
+                Ok(step) => self.steps.push(Some(step)),
+                Err(e) => {
+                    self.report(e.line, e.column, e.kind.into());
+                    self.steps.push(None);
+                }
+            }
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if let Some(Step { command: Command::Label(label), .. }) = step {
+                if self.labels.insert(label.clone(), i + 1).is_some() {
+                    let column = self.rows[i].opcode.column;
+                    self.lints.push(Lint { file: self.file.to_string(), line: i + 1, column, kind: LintKind::DuplicateLabel(label.clone()) });
+                }
+            }
+        }
+        let successors: Vec<Vec<usize>> = (1..=self.rows.len()).map(|line| self.successors(line)).collect();
+        self.unused_labels();
+        let reachable = self.reachable(&successors);
+        if !self.rows.is_empty() && !reachable.iter().any(|&line| matches!(self.step(line), Some(s) if s.command == Command::EndAttack)) {
+            self.report(1, 1, LintKind::NoEndAttack);
+        }
+        self.read_before_set(&successors, &reachable);
+    }
+
+    // Lines a row can continue at, checking its jump on the way
+    fn successors(&mut self, line: usize) -> Vec<usize> {
+        let n = self.rows.len();
+        let next = vec![line + 1];
+        let Some(step) = self.step(line).cloned() else {
+            return next;
+        };
+        let labelled = !self.labels.is_empty();
+        let mut lines = match &step.command {
+            Command::EndAttack => vec![],
+            Command::Jump { target, condition } => {
+                let mut lines = match target {
+                    Target::Line(t) => self.literal_jump(line, *t as i64, labelled),
+                    Target::Label(l) => match self.labels.get(l) {
+                        Some(&t) => vec![t],
+                        None => {
+                            self.report(line, self.arg_column(line), LintKind::UnknownLabel(l.clone()));
+                            vec![]
+                        }
+                    },
+                    Target::Var(_) if labelled => self.labels.values().copied().collect(),
+                    Target::Var(_) => (1..=n).collect(),
+                };
+                if condition.is_some() {
+                    lines.push(line + 1);
+                }
+                lines
+            }
+            Command::JumpRel { offset: Value::Number(k) } if k.fract() == 0.0 => {
+                self.literal_jump(line, line as i64 + *k as i64, labelled)
+            }
+            Command::JumpRel { offset: Value::Number(k) } => {
+                self.report(line, self.arg_column(line), LintKind::Syntax(ParseErrorKind::BadTarget(k.to_string())));
+                vec![]
+            }
+            Command::JumpRel { offset: Value::Var(_) } => {
+                let table: Vec<usize> = (line + 1..=n)
+                    .take_while(|&l| {
+                        matches!(self.step(l).map(|s| &s.command), Some(Command::Jump { condition: None, .. } | Command::JumpRel { .. }))
+                    })
+                    .collect();
+                if table.is_empty() { next } else { table }
+            }
+            _ => next,
+        };
+        lines.retain(|&l| (1..=n).contains(&l));
+        lines
+    }
+
+    fn literal_jump(&mut self, line: usize, target: i64, labelled: bool) -> Vec<usize> {
+        let column = self.arg_column(line);
+        if target < 1 || target > self.rows.len() as i64 {
+            self.report(line, column, LintKind::JumpOutOfRange(target));
+            return vec![];
+        }
+        let target = target as usize;
+        if labelled && !matches!(self.step(target), Some(Step { command: Command::Label(_), .. })) {
+            self.report(line, column, LintKind::JumpIntoBlock(target));
+        }
+        vec![target]
+    }
+
+    fn unused_labels(&mut self) {
+        let used: HashSet<&str> = self
+            .steps
+            .iter()
+            .flatten()
+            .filter_map(|s| match &s.command {
+                Command::Jump { target: Target::Label(l), .. } => Some(l.as_str()),
+                _ => None,
+            })
+            .collect();
+        let mut unused = Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            if let Some(Step { command: Command::Label(label), .. }) = step {
+                if !used.contains(label.as_str()) {
+                    unused.push((i + 1, self.rows[i].opcode.column, LintKind::UnusedLabel(label.clone())));
+                }
+            }
+        }
+        for (line, column, kind) in unused {
+            self.report(line, column, kind);
+        }
+    }
+
+    fn reachable(&self, successors: &[Vec<usize>]) -> Vec<usize> {
+        let mut seen = vec![false; self.rows.len() + 1];
+        let mut queue = VecDeque::from([1]);
+        let mut lines = Vec::new();
+        while let Some(line) = queue.pop_front() {
+            if line > self.rows.len() || seen[line] {
+                continue;
+            }
+            seen[line] = true;
+            lines.push(line);
+            queue.extend(&successors[line - 1]);
+        }
+        lines
+    }
+
+    // A variable counts as set at a row only if every path from the first
+    // row to it sets it
+    fn read_before_set(&mut self, successors: &[Vec<usize>], reachable: &[usize]) {
+        let n = self.rows.len();
+        // None stands for "every variable" until a path reaches the row
+        let mut set_before: Vec<Option<HashSet<String>>> = vec![None; n + 1];
+        if n == 0 {
+            return;
+        }
+        set_before[1] = Some(HashSet::new());
+        let mut queue: VecDeque<usize> = VecDeque::from([1]);
+        while let Some(line) = queue.pop_front() {
+            let mut after = set_before[line].clone().expect("queued rows have a state");
+            if let Some(step) = self.step(line) {
+                after.extend(step.command.writes().into_iter().map(String::from));
+            }
+            for &next in &successors[line - 1] {
+                let merged = match &set_before[next] {
+                    None => after.clone(),
+                    Some(before) => before.intersection(&after).cloned().collect(),
+                };
+                if set_before[next].as_ref() != Some(&merged) {
+                    set_before[next] = Some(merged);
+                    queue.push_back(next);
+                }
+            }
+        }
+        let mut found = Vec::new();
+        for &line in reachable {
+            let (Some(step), Some(set)) = (self.step(line), &set_before[line]) else {
+                continue;
+            };
+            let delay = match &step.delay {
+                Value::Var(v) => Some(v.as_str()),
+                Value::Number(_) => None,
+            };
+            let mut reported = HashSet::new();
+            for var in delay.into_iter().chain(step.command.reads()) {
+                if !set.contains(var) && reported.insert(var) {
+                    found.push((line, self.var_column(line, var), LintKind::ReadBeforeSet(var.to_string())));
+                }
+            }
+        }
+        for (line, column, kind) in found {
+            self.report(line, column, kind);
+        }
+    }
+
+    fn var_column(&self, line: usize, var: &str) -> usize {
+        let row = &self.rows[line - 1];
+        let name = format!("${var}");
+        std::iter::once(&row.delay).chain(&row.args).find(|c| c.text == name).map_or(row.opcode.column, |c| c.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(usize, LintKind)> {
+        lint("t.csv", source).into_iter().map(|l| (l.line, l.kind)).collect()
+    }
+
+    #[test]
+    fn test_clean_script() {
+        assert_eq!(kinds("0,SET,Loop,3\n0,:Top\n0.5,SUB,Loop,$Loop,1\n0,JMPNZ,Top,$Loop\n0,EndAttack"), []);
+    }
+
+    #[test]
+    fn test_bad_rows() {
+        let lints = lint("t.csv", "0,BoneZ,1\n0,HeartMode\n0,SET,X,1O\n0,EndAttack");
+        let kinds: Vec<&LintKind> = lints.iter().map(|l| &l.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                &LintKind::UnknownOpcode("BoneZ".into()),
+                &LintKind::ArgCount { opcode: "HeartMode".into(), min: 1, max: 1, found: 0 },
+                &LintKind::Syntax(ParseErrorKind::BadNumber("1O".into())),
+            ]
+        );
+        assert_eq!(lints[0].to_string(), "t.csv:1:3: error: unknown opcode \"BoneZ\"");
+        assert!(lints.iter().all(|l| l.severity() == Severity::Error));
+    }
+
+    #[test]
+    fn test_jumps() {
+        assert_eq!(kinds("0,JMPZ,9,0\n0,EndAttack"), [(1, LintKind::JumpOutOfRange(9))]);
+        // Nothing runs after a jump off the script
+        assert_eq!(kinds("0,JMPREL,-1\n0,EndAttack"), [(1, LintKind::NoEndAttack), (1, LintKind::JumpOutOfRange(0))]);
+        assert_eq!(kinds("0,JMPZ,Nowhere,0\n0,EndAttack"), [(1, LintKind::UnknownLabel("Nowhere".into()))]);
+        // With labels about, a numeric jump into the middle of a block is suspect
+        assert_eq!(
+            kinds("0,JMPABS,4\n0,:Skip\n0,TLPause\n0,JMPABS,Skip\n0,EndAttack"),
+            [(1, LintKind::NoEndAttack), (1, LintKind::JumpIntoBlock(4))]
+        );
+        assert_eq!(
+            kinds("0,JMPABS,End\n0,:Unused\n0,:End\n0,:End\n0,EndAttack"),
+            [(2, LintKind::UnusedLabel("Unused".into())), (4, LintKind::DuplicateLabel("End".into()))]
+        );
+    }
+
+    #[test]
+    fn test_read_before_set() {
+        let lints = lint("t.csv", "0,JMPZ,4,$Flag\n0,SET,X,1\n0,SET,Y,1\n$Wait,ADD,Z,$X,$Y\n0,EndAttack");
+        let found: Vec<(usize, usize, &LintKind)> = lints.iter().map(|l| (l.line, l.column, &l.kind)).collect();
+        assert_eq!(
+            found,
+            [
+                (1, 10, &LintKind::ReadBeforeSet("Flag".into())),
+                (4, 1, &LintKind::ReadBeforeSet("Wait".into())),
+                (4, 13, &LintKind::ReadBeforeSet("X".into())),
+                (4, 16, &LintKind::ReadBeforeSet("Y".into())),
+            ]
+        );
+        assert!(lints.iter().all(|l| l.severity() == Severity::Warning));
+        // Set on every way round the loop
+        assert_eq!(kinds("0,SET,I,3\n0.1,SUB,I,$I,1\n0,JMPNZ,2,$I\n0,EndAttack"), []);
+    }
+
+    #[test]
+    fn test_no_end_attack() {
+        assert_eq!(kinds("1,BoneV,1,2,3,0,240"), [(1, LintKind::NoEndAttack)]);
+        assert_eq!(kinds("1,JMPABS,1\n0,EndAttack"), [(1, LintKind::NoEndAttack)]);
+        // The jump table after a computed JMPREL leads to the end
+        assert_eq!(kinds("0,RND,J,2\n0,ADD,J,$J,1\n0,JMPREL,$J\n0,JMPABS,1\n0,JMPREL,1\n0,EndAttack"), []);
+    }
+
+    #[test]
+    fn test_bundled_scripts() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../gh-page");
+        let mut warnings = HashMap::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            if !(name.starts_with("sans_") && name.ends_with(".csv")) {
+                continue;
+            }
+            for lint in lint_file(&path) {
+                assert_eq!(lint.severity(), Severity::Warning, "{lint}");
+                warnings.entry(name.clone()).or_insert_with(Vec::new).push(lint.kind);
+            }
+        }
+        // $pi is never set, so the game divides by zero
+        assert!(warnings["sans_final.csv"].contains(&LintKind::ReadBeforeSet("pi".into())));
+        assert!(warnings["sans_bonegap2.csv"].contains(&LintKind::JumpIntoBlock(23)));
+    }
+}
@@ -0,0 +1,456 @@
+// Reading a script in two passes: `parse_rows` splits the CSV into cells and
+// keeps their positions, `step` types one row. The linter works on the rows
+// directly so it can report every bad row instead of stopping at the first.
+
+use super::{arity, BinaryOp, Command, Condition, Step, Target, UnaryOp, Value};
+
+pub const MAX_ARGS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub text: String,
+    // 1-based, in characters
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub line: usize,
+    pub delay: Cell,
+    pub opcode: Cell,
+    // Trailing empty cells are dropped
+    pub args: Vec<Cell>,
+}
+
+impl Row {
+    pub fn is_label(&self) -> bool {
+        self.opcode.text.starts_with(':')
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Io(String),
+    EmptyRow,
+    TooManyArgs(usize),
+    // A label row with something after the label
+    LabelArgs(String),
+    UnknownOpcode(String),
+    ArgCount { opcode: String, min: usize, max: usize, found: usize },
+    MissingArg,
+    BadNumber(String),
+    BadTarget(String),
+    // A variable or label name was expected, without the $
+    ExpectedName(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn new(file: &str, line: usize, column: usize, kind: ParseErrorKind) -> Self {
+        ParseError { file: file.to_string(), line, column, kind }
+    }
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Io(e) => write!(f, "{e}"),
+            ParseErrorKind::EmptyRow => write!(f, "empty row"),
+            ParseErrorKind::TooManyArgs(n) => write!(f, "{n} arguments, at most {MAX_ARGS} fit in a row"),
+            ParseErrorKind::LabelArgs(label) => write!(f, "label :{label} takes no arguments"),
+            ParseErrorKind::UnknownOpcode(op) => write!(f, "unknown opcode {op:?}"),
+            ParseErrorKind::ArgCount { opcode, min, max, found } if min == max => {
+                write!(f, "{opcode} takes {min} arguments, got {found}")
+            }
+            ParseErrorKind::ArgCount { opcode, min, max, found } => {
+                write!(f, "{opcode} takes {min} to {max} arguments, got {found}")
+            }
+            ParseErrorKind::MissingArg => write!(f, "missing argument"),
+            ParseErrorKind::BadNumber(s) => write!(f, "expected a number or $variable, got {s:?}"),
+            ParseErrorKind::BadTarget(s) => write!(f, "expected a line number, label or $variable, got {s:?}"),
+            ParseErrorKind::ExpectedName(s) => write!(f, "expected a name, got {s:?}"),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse_rows(file: &str, source: &str) -> Result<Vec<Row>, ParseError> {
+    let lines: Vec<&str> = source.lines().collect();
+    // The game drops nothing but the newline at the end of the file
+    let end = lines.iter().rposition(|l| !l.trim().is_empty()).map_or(0, |i| i + 1);
+    let mut rows = Vec::with_capacity(end);
+    for (i, text) in lines[..end].iter().enumerate() {
+        let line = i + 1;
+        if text.trim().is_empty() {
+            return Err(ParseError::new(file, line, 1, ParseErrorKind::EmptyRow));
+        }
+        rows.push(parse_row(file, line, text)?);
+    }
+    Ok(rows)
+}
+
+// One non-empty line of cells
+pub fn parse_row(file: &str, line: usize, text: &str) -> Result<Row, ParseError> {
+    let mut column = 1;
+    let mut cells = Vec::new();
+    for cell in text.split(',') {
+        cells.push(Cell { text: cell.to_string(), column });
+        column += cell.chars().count() + 1;
+    }
+    while cells.len() > 2 && cells.last().is_some_and(|c| c.text.is_empty()) {
+        cells.pop();
+    }
+    let mut cells = cells.into_iter();
+    let delay = cells.next().expect("split yields a cell");
+    let opcode = cells.next().unwrap_or(Cell { text: String::new(), column });
+    let args: Vec<Cell> = cells.collect();
+    if args.len() > MAX_ARGS {
+        return Err(ParseError::new(file, line, args[MAX_ARGS].column, ParseErrorKind::TooManyArgs(args.len())));
+    }
+    Ok(Row { line, delay, opcode, args })
+}
+
+pub fn value(file: &str, line: usize, cell: &Cell) -> Result<Value, ParseError> {
+    let error = |kind| Err(ParseError::new(file, line, cell.column, kind));
+    if let Some(var) = cell.text.strip_prefix('$') {
+        if !is_name(var) {
+            return error(ParseErrorKind::ExpectedName(var.to_string()));
+        }
+        return Ok(Value::Var(var.to_string()));
+    }
+    match cell.text.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+        _ if cell.text.is_empty() => error(ParseErrorKind::MissingArg),
+        _ => error(ParseErrorKind::BadNumber(cell.text.clone())),
+    }
+}
+
+pub fn is_name(s: &str) -> bool {
+    !s.is_empty() && !s.starts_with('$') && !s.contains(char::is_whitespace)
+}
+
+// Typed arguments of one row
+struct Args<'a> {
+    file: &'a str,
+    row: &'a Row,
+}
+
+impl Args<'_> {
+    fn error<T>(&self, column: usize, kind: ParseErrorKind) -> Result<T, ParseError> {
+        Err(ParseError::new(self.file, self.row.line, column, kind))
+    }
+
+    fn cell(&self, i: usize) -> Result<&Cell, ParseError> {
+        match self.row.args.get(i) {
+            Some(cell) if !cell.text.is_empty() => Ok(cell),
+            Some(cell) => self.error(cell.column, ParseErrorKind::MissingArg),
+            None => self.error(self.row.opcode.column, ParseErrorKind::MissingArg),
+        }
+    }
+
+    fn present(&self, i: usize) -> bool {
+        self.row.args.get(i).is_some_and(|c| !c.text.is_empty())
+    }
+
+    fn value(&self, i: usize) -> Result<Value, ParseError> {
+        value(self.file, self.row.line, self.cell(i)?)
+    }
+
+    fn opt_value(&self, i: usize) -> Result<Option<Value>, ParseError> {
+        self.present(i).then(|| self.value(i)).transpose()
+    }
+
+    fn name(&self, i: usize) -> Result<String, ParseError> {
+        let cell = self.cell(i)?;
+        if !is_name(&cell.text) {
+            return self.error(cell.column, ParseErrorKind::ExpectedName(cell.text.clone()));
+        }
+        Ok(cell.text.clone())
+    }
+
+    fn opt_name(&self, i: usize) -> Result<Option<String>, ParseError> {
+        self.present(i).then(|| self.name(i)).transpose()
+    }
+
+    // Free text, spaces and all
+    fn text(&self, i: usize) -> Result<String, ParseError> {
+        Ok(self.cell(i)?.text.clone())
+    }
+
+    fn target(&self, i: usize) -> Result<Target, ParseError> {
+        let cell = self.cell(i)?;
+        let text = &cell.text;
+        if let Some(var) = text.strip_prefix('$') {
+            return match is_name(var) {
+                true => Ok(Target::Var(var.to_string())),
+                false => self.error(cell.column, ParseErrorKind::ExpectedName(var.to_string())),
+            };
+        }
+        if text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+            return match text.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Target::Line(n)),
+                _ => self.error(cell.column, ParseErrorKind::BadTarget(text.clone())),
+            };
+        }
+        match is_name(text) {
+            true => Ok(Target::Label(text.clone())),
+            false => self.error(cell.column, ParseErrorKind::BadTarget(text.clone())),
+        }
+    }
+}
+
+pub fn step(file: &str, row: &Row) -> Result<Step, ParseError> {
+    let a = Args { file, row };
+    let delay = value(file, row.line, &row.delay)?;
+    let opcode = row.opcode.text.as_str();
+    if let Some(label) = opcode.strip_prefix(':') {
+        if !is_name(label) {
+            return a.error(row.opcode.column, ParseErrorKind::ExpectedName(label.to_string()));
+        }
+        if let Some(arg) = row.args.iter().find(|c| !c.text.is_empty()) {
+            return a.error(arg.column, ParseErrorKind::LabelArgs(label.to_string()));
+        }
+        return Ok(Step { line: row.line, delay, command: Command::Label(label.to_string()) });
+    }
+    let Some((min, max)) = arity(opcode) else {
+        return a.error(row.opcode.column, ParseErrorKind::UnknownOpcode(opcode.to_string()));
+    };
+    let found = row.args.len();
+    if found < min || found > max {
+        let column = row.args.get(max).map_or(row.opcode.column, |c| c.column);
+        return a.error(column, ParseErrorKind::ArgCount { opcode: opcode.to_string(), min, max, found });
+    }
+
+    let binary = |op| Ok(Command::Binary { op, var: a.name(0)?, lhs: a.value(1)?, rhs: a.value(2)? });
+    let unary = |op| Ok(Command::Unary { op, var: a.name(0)?, value: a.value(1)? });
+    let jump = |condition: Option<Condition>| Ok(Command::Jump { target: a.target(0)?, condition });
+    let pair = || Ok::<_, ParseError>((a.value(1)?, a.value(2)?));
+    let command = match opcode {
+        "SET" => Command::Set { var: a.name(0)?, value: a.value(1)? },
+        "ADD" => binary(BinaryOp::Add)?,
+        "SUB" => binary(BinaryOp::Sub)?,
+        "MUL" => binary(BinaryOp::Mul)?,
+        "DIV" => binary(BinaryOp::Div)?,
+        "MOD" => binary(BinaryOp::Mod)?,
+        "FLOOR" => unary(UnaryOp::Floor)?,
+        "SIN" => unary(UnaryOp::Sin)?,
+        "COS" => unary(UnaryOp::Cos)?,
+        "DEG" => unary(UnaryOp::Deg)?,
+        "RAD" => unary(UnaryOp::Rad)?,
+        "ANGLE" => Command::Angle { var: a.name(0)?, x1: a.value(1)?, y1: a.value(2)?, x2: a.value(3)?, y2: a.value(4)? },
+        "RND" => Command::Rnd { var: a.name(0)?, max: a.value(1)? },
+        "GetHeartPos" => Command::GetHeartPos { x: a.name(0)?, y: a.name(1)? },
+        "JMPABS" => jump(None)?,
+        "JMPREL" => Command::JumpRel { offset: a.value(0)? },
+        "JMPZ" => jump(Some(Condition::Zero(a.value(1)?)))?,
+        "JMPNZ" => jump(Some(Condition::NotZero(a.value(1)?)))?,
+        "JMPE" => jump(Some(pair().map(|(l, r)| Condition::Equal(l, r))?))?,
+        "JMPNE" => jump(Some(pair().map(|(l, r)| Condition::NotEqual(l, r))?))?,
+        "JMPL" => jump(Some(pair().map(|(l, r)| Condition::Less(l, r))?))?,
+        "JMPNL" => jump(Some(pair().map(|(l, r)| Condition::NotLess(l, r))?))?,
+        "JMPG" => jump(Some(pair().map(|(l, r)| Condition::Greater(l, r))?))?,
+        "JMPNG" => jump(Some(pair().map(|(l, r)| Condition::NotGreater(l, r))?))?,
+        "BoneV" => Command::BoneV {
+            x: a.value(0)?,
+            y: a.value(1)?,
+            height: a.value(2)?,
+            direction: a.value(3)?,
+            speed: a.value(4)?,
+            color: a.opt_value(5)?,
+        },
+        "BoneH" => Command::BoneH {
+            x: a.value(0)?,
+            y: a.value(1)?,
+            width: a.value(2)?,
+            direction: a.value(3)?,
+            speed: a.value(4)?,
+            color: a.opt_value(5)?,
+        },
+        "BoneVRepeat" => Command::BoneVRepeat {
+            x: a.value(0)?,
+            y: a.value(1)?,
+            height: a.value(2)?,
+            direction: a.value(3)?,
+            speed: a.value(4)?,
+            count: a.value(5)?,
+            spacing: a.value(6)?,
+        },
+        "BoneHRepeat" => Command::BoneHRepeat {
+            x: a.value(0)?,
+            y: a.value(1)?,
+            width: a.value(2)?,
+            direction: a.value(3)?,
+            speed: a.value(4)?,
+            count: a.value(5)?,
+            spacing: a.value(6)?,
+        },
+        "BoneStab" => Command::BoneStab { direction: a.value(0)?, height: a.value(1)?, warning: a.value(2)?, duration: a.value(3)? },
+        "SineBones" => Command::SineBones { count: a.value(0)?, spacing: a.value(1)?, speed: a.value(2)?, height: a.value(3)? },
+        "GasterBlaster" => Command::GasterBlaster {
+            size: a.value(0)?,
+            x: a.value(1)?,
+            y: a.value(2)?,
+            end_x: a.value(3)?,
+            end_y: a.value(4)?,
+            angle: a.value(5)?,
+            aim: a.value(6)?,
+            fire: a.value(7)?,
+        },
+        "Platform" => Command::Platform {
+            x: a.value(0)?,
+            y: a.value(1)?,
+            width: a.value(2)?,
+            direction: a.value(3)?,
+            speed: a.value(4)?,
+            kind: a.opt_value(5)?,
+        },
+        "PlatformRepeat" => Command::PlatformRepeat {
+            x: a.value(0)?,
+            y: a.value(1)?,
+            width: a.value(2)?,
+            direction: a.value(3)?,
+            speed: a.value(4)?,
+            count: a.value(5)?,
+            spacing: a.value(6)?,
+        },
+        "CombatZoneResize" => Command::CombatZoneResize {
+            x1: a.value(0)?,
+            y1: a.value(1)?,
+            x2: a.value(2)?,
+            y2: a.value(3)?,
+            then: a.opt_name(4)?,
+        },
+        "CombatZoneResizeInstant" => {
+            Command::CombatZoneResizeInstant { x1: a.value(0)?, y1: a.value(1)?, x2: a.value(2)?, y2: a.value(3)? }
+        }
+        "CombatZoneSpeed" => Command::CombatZoneSpeed(a.value(0)?),
+        "HeartTeleport" => Command::HeartTeleport { x: a.value(0)?, y: a.value(1)? },
+        "HeartMode" => Command::HeartMode(a.value(0)?),
+        "HeartMaxFallSpeed" => Command::HeartMaxFallSpeed(a.value(0)?),
+        "Sound" => Command::Sound { name: a.name(0)?, rate: a.opt_value(1)? },
+        "Music" => Command::Music(a.name(0)?),
+        "BlackScreen" => Command::BlackScreen(a.value(0)?),
+        "SansBody" => Command::SansBody(a.name(0)?),
+        "SansHead" => Command::SansHead(a.name(0)?),
+        "SansTorso" => Command::SansTorso(a.name(0)?),
+        "SansAnimation" => Command::SansAnimation(a.opt_name(0)?),
+        "SansSweat" => Command::SansSweat(a.value(0)?),
+        "SansSlam" => Command::SansSlam(a.value(0)?),
+        "SansSlamDamage" => Command::SansSlamDamage(a.value(0)?),
+        "SansShake" => Command::SansShake(a.value(0)?),
+        "SansText" => Command::SansText(a.text(0)?),
+        "SansX" => Command::SansX(a.value(0)?),
+        "SansRepeat" => Command::SansRepeat,
+        "SansEndRepeat" => Command::SansEndRepeat,
+        "TLPause" => Command::TLPause,
+        "TLResume" => Command::TLResume,
+        "EndAttack" => Command::EndAttack,
+        _ => unreachable!("{opcode} is in OPCODES"),
+    };
+    Ok(Step { line: row.line, delay, command })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AttackScript;
+    use super::*;
+
+    #[test]
+    fn test_rows_keep_columns() {
+        let rows = parse_rows("t.csv", "0.5,SET,Loop,15,,,,,,\n0,:Top,,\n").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].delay, Cell { text: "0.5".into(), column: 1 });
+        assert_eq!(rows[0].opcode.column, 5);
+        assert_eq!(rows[0].args, [Cell { text: "Loop".into(), column: 9 }, Cell { text: "15".into(), column: 14 }]);
+        assert!(rows[1].is_label());
+        assert!(rows[1].args.is_empty());
+    }
+
+    #[test]
+    fn test_vars_and_literals() {
+        let script = AttackScript::parse("t.csv", "$Wait,ADD,X,$X,-2.5\n0,JMPNZ,6,$Loop\n0,JMPREL,$Jump\n0,JMPZ,End,0\n0,:End").unwrap();
+        let commands: Vec<&Command> = script.steps.iter().map(|s| &s.command).collect();
+        assert_eq!(script.steps[0].delay, Value::Var("Wait".into()));
+        assert_eq!(
+            commands[0],
+            &Command::Binary { op: BinaryOp::Add, var: "X".into(), lhs: Value::Var("X".into()), rhs: Value::Number(-2.5) }
+        );
+        assert_eq!(
+            commands[1],
+            &Command::Jump { target: Target::Line(6), condition: Some(Condition::NotZero(Value::Var("Loop".into()))) }
+        );
+        assert_eq!(commands[2], &Command::JumpRel { offset: Value::Var("Jump".into()) });
+        assert_eq!(
+            commands[3],
+            &Command::Jump { target: Target::Label("End".into()), condition: Some(Condition::Zero(Value::Number(0.0))) }
+        );
+        assert_eq!(script.label("End"), Some(5));
+    }
+
+    #[test]
+    fn test_optional_and_text_args() {
+        let script = AttackScript::parse(
+            "t.csv",
+            "0,CombatZoneResize,133,251,508,391,TLResume,,\n0,SansText,here we go.\n0,SansAnimation,,\n0,BoneV,1,2,3,0,240,,\n",
+        )
+        .unwrap();
+        let commands: Vec<&Command> = script.steps.iter().map(|s| &s.command).collect();
+        assert!(matches!(commands[0], Command::CombatZoneResize { then: Some(t), .. } if t == "TLResume"));
+        assert_eq!(commands[1], &Command::SansText("here we go.".into()));
+        assert_eq!(commands[2], &Command::SansAnimation(None));
+        assert!(matches!(commands[3], Command::BoneV { color: None, .. }));
+    }
+
+    #[test]
+    fn test_errors_carry_position() {
+        let err = |source| AttackScript::parse("bad.csv", source).unwrap_err();
+        assert_eq!(err("0,TLPause\n0,BoneZ,1"), ParseError::new("bad.csv", 2, 3, ParseErrorKind::UnknownOpcode("BoneZ".into())));
+        assert_eq!(err("0,SET,X,1O").kind, ParseErrorKind::BadNumber("1O".into()));
+        assert_eq!(err("0,SET,X,1O").column, 9);
+        assert_eq!(err("0,SET,$X,1").kind, ParseErrorKind::ExpectedName("$X".into()));
+        assert_eq!(
+            err("0,HeartMode,1,2"),
+            ParseError::new("bad.csv", 1, 15, ParseErrorKind::ArgCount { opcode: "HeartMode".into(), min: 1, max: 1, found: 2 })
+        );
+        assert_eq!(err("0,BoneV,1,,3,0,240").kind, ParseErrorKind::MissingArg);
+        assert_eq!(err("0,BoneV,1,,3,0,240").column, 11);
+        assert_eq!(err("0,JMPABS,-3").kind, ParseErrorKind::BadTarget("-3".into()));
+        assert_eq!(err("x,EndAttack").kind, ParseErrorKind::BadNumber("x".into()));
+        assert_eq!(err("0,TLPause\n\n0,EndAttack").line, 2);
+        assert_eq!(err("0,SET,A,1,2,3,4,5,6,7,8").kind, ParseErrorKind::TooManyArgs(9));
+        assert_eq!(err("0,:Top,1").kind, ParseErrorKind::LabelArgs("Top".into()));
+        assert_eq!(err("0,:Top,1").to_string(), "bad.csv:1:8: label :Top takes no arguments");
+        assert_eq!(err("0,BoneV,1").to_string(), "bad.csv:1:3: BoneV takes 5 to 6 arguments, got 1");
+    }
+
+    #[test]
+    fn test_bundled_scripts_parse() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../gh-page");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy();
+            if !(name.starts_with("sans_") && name.ends_with(".csv")) {
+                continue;
+            }
+            let script = AttackScript::load(&path).unwrap_or_else(|e| panic!("{e}"));
+            assert!(script.steps.iter().any(|s| s.command == Command::EndAttack), "{name}");
+            count += 1;
+        }
+        assert_eq!(count, 24);
+    }
+}
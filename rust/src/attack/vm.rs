@@ -0,0 +1,484 @@
+// Runs an attack script without the game: the control opcodes are executed,
+// delays add up to a clock and everything that puts something in the box
+// comes out as a timestamped event with its arguments worked out.
+//
+// It follows the game page where that matters for the timeline: variables
+// that were never set read as 0, RND N gives a whole number below N, angles
+// are in degrees, and more than 1000 rows without any time passing is an
+// infinite loop. The game waits for the box to finish moving after TLPause;
+// here the pause takes no time. Sans' poses, sounds and the black screen are
+// left out.
+
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{AttackScript, BinaryOp, Command, Condition, Step, Target, UnaryOp, Value};
+
+// The game's panic threshold for rows run in one frame
+const ZERO_TIME_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spawn {
+    BoneV { x: f64, y: f64, height: f64, direction: f64, speed: f64, color: f64 },
+    BoneH { x: f64, y: f64, width: f64, direction: f64, speed: f64, color: f64 },
+    BoneVRepeat { x: f64, y: f64, height: f64, direction: f64, speed: f64, count: f64, spacing: f64 },
+    BoneHRepeat { x: f64, y: f64, width: f64, direction: f64, speed: f64, count: f64, spacing: f64 },
+    BoneStab { direction: f64, height: f64, warning: f64, duration: f64 },
+    SineBones { count: f64, spacing: f64, speed: f64, height: f64 },
+    GasterBlaster { size: f64, x: f64, y: f64, end_x: f64, end_y: f64, angle: f64, aim: f64, fire: f64 },
+    Platform { x: f64, y: f64, width: f64, direction: f64, speed: f64, kind: f64 },
+    PlatformRepeat { x: f64, y: f64, width: f64, direction: f64, speed: f64, count: f64, spacing: f64 },
+    CombatZoneResize { x1: f64, y1: f64, x2: f64, y2: f64, instant: bool },
+    CombatZoneSpeed(f64),
+    HeartTeleport { x: f64, y: f64 },
+    HeartMode(f64),
+    HeartMaxFallSpeed(f64),
+    // Throws the heart against a side of the box
+    SansSlam(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    // Seconds from the start of the script
+    pub time: f64,
+    pub line: usize,
+    pub spawn: Spawn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub events: Vec<Event>,
+    // Clock when the script stopped
+    pub duration: f64,
+    // False if it ran off the end of the file instead of reaching EndAttack
+    pub ended: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmErrorKind {
+    UnknownLabel(String),
+    // A computed jump that is not a whole line number
+    BadJump(f64),
+    InfiniteLoop,
+    StepLimit(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+    pub file: String,
+    pub line: usize,
+    pub kind: VmErrorKind,
+}
+
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: ", self.file, self.line)?;
+        match &self.kind {
+            VmErrorKind::UnknownLabel(l) => write!(f, "label {l} does not exist"),
+            VmErrorKind::BadJump(n) => write!(f, "cannot jump to line {n}"),
+            VmErrorKind::InfiniteLoop => write!(f, "infinite loop: {ZERO_TIME_ROWS} rows without a delay"),
+            VmErrorKind::StepLimit(n) => write!(f, "still running after {n} rows"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+pub struct Vm<'a> {
+    script: &'a AttackScript,
+    labels: HashMap<&'a str, usize>,
+    vars: HashMap<String, f64>,
+    rng: StdRng,
+    heart: (f64, f64),
+    max_steps: usize,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(script: &'a AttackScript) -> Self {
+        // A label defined twice jumps to the later one, as in the game
+        let labels = script
+            .steps
+            .iter()
+            .filter_map(|s| match &s.command {
+                Command::Label(l) => Some((l.as_str(), s.line)),
+                _ => None,
+            })
+            .collect();
+        Vm {
+            script,
+            labels,
+            vars: HashMap::new(),
+            rng: StdRng::from_entropy(),
+            heart: (320.0, 240.0),
+            max_steps: 1_000_000,
+        }
+    }
+
+    // Fixes RND for reproducible timelines
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    // Where GetHeartPos finds the heart until a HeartTeleport moves it. The
+    // player never moves it here.
+    pub fn with_heart(mut self, x: f64, y: f64) -> Self {
+        self.heart = (x, y);
+        self
+    }
+
+    pub fn with_max_steps(mut self, steps: usize) -> Self {
+        self.max_steps = steps;
+        self
+    }
+
+    pub fn var(&self, name: &str) -> Option<f64> {
+        self.vars.get(name).copied()
+    }
+
+    fn get(&self, value: &Value) -> f64 {
+        match value {
+            Value::Number(n) => *n,
+            Value::Var(name) => self.vars.get(name).copied().unwrap_or(0.0),
+        }
+    }
+
+    fn set(&mut self, var: &str, value: f64) {
+        self.vars.insert(var.to_string(), value);
+    }
+
+    fn error(&self, line: usize, kind: VmErrorKind) -> VmError {
+        VmError { file: self.script.name.clone(), line, kind }
+    }
+
+    pub fn run(&mut self) -> Result<Timeline, VmError> {
+        let script = self.script;
+        let steps = &script.steps;
+        let mut events = Vec::new();
+        let mut time = 0.0;
+        let mut line: usize = 1;
+        let mut zero_time_rows = 0;
+        for _ in 0..self.max_steps {
+            // Line numbers and step indices agree: the parser allows no
+            // blank rows
+            let Some(step) = line.checked_sub(1).and_then(|i| steps.get(i)) else {
+                return Ok(Timeline { events, duration: time, ended: false });
+            };
+            let delay = self.get(&step.delay);
+            if delay > 0.0 {
+                time += delay;
+                zero_time_rows = 0;
+            } else {
+                zero_time_rows += 1;
+                if zero_time_rows > ZERO_TIME_ROWS {
+                    return Err(self.error(step.line, VmErrorKind::InfiniteLoop));
+                }
+            }
+            if step.command == Command::EndAttack {
+                return Ok(Timeline { events, duration: time, ended: true });
+            }
+            if let Some(spawn) = self.spawn(&step.command) {
+                events.push(Event { time, line: step.line, spawn });
+            }
+            line = self.execute(step)?;
+        }
+        Err(self.error(line, VmErrorKind::StepLimit(self.max_steps)))
+    }
+
+    // Runs a control row and returns the next line
+    fn execute(&mut self, step: &Step) -> Result<usize, VmError> {
+        let next = step.line + 1;
+        match &step.command {
+            Command::Set { var, value } => self.set(var, self.get(value)),
+            Command::Binary { op, var, lhs, rhs } => {
+                let (a, b) = (self.get(lhs), self.get(rhs));
+                let value = match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Mod => a % b,
+                };
+                self.set(var, value);
+            }
+            Command::Unary { op, var, value } => {
+                let x = self.get(value);
+                let value = match op {
+                    UnaryOp::Floor => x.floor(),
+                    UnaryOp::Sin => x.to_radians().sin(),
+                    UnaryOp::Cos => x.to_radians().cos(),
+                    UnaryOp::Deg => x.to_degrees(),
+                    UnaryOp::Rad => x.to_radians(),
+                };
+                self.set(var, value);
+            }
+            Command::Angle { var, x1, y1, x2, y2 } => {
+                let (dx, dy) = (self.get(x2) - self.get(x1), self.get(y2) - self.get(y1));
+                self.set(var, dy.atan2(dx).to_degrees());
+            }
+            Command::Rnd { var, max } => {
+                let max = self.get(max).trunc();
+                let value = (self.rng.gen::<f64>() * max).floor();
+                self.set(var, value);
+            }
+            Command::GetHeartPos { x, y } => {
+                let (hx, hy) = self.heart;
+                self.set(x, hx);
+                self.set(y, hy);
+            }
+            Command::HeartTeleport { x, y } => self.heart = (self.get(x), self.get(y)),
+            Command::Jump { target, condition } if condition.as_ref().is_none_or(|c| self.holds(c)) => {
+                return self.target(step.line, target);
+            }
+            Command::JumpRel { offset } => {
+                let line = step.line as f64 + self.get(offset).trunc();
+                return self.line(step.line, line);
+            }
+            _ => {}
+        }
+        Ok(next)
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Zero(v) => self.get(v) == 0.0,
+            Condition::NotZero(v) => self.get(v) != 0.0,
+            Condition::Equal(a, b) => self.get(a) == self.get(b),
+            Condition::NotEqual(a, b) => self.get(a) != self.get(b),
+            Condition::Less(a, b) => self.get(a) < self.get(b),
+            Condition::NotLess(a, b) => self.get(a) >= self.get(b),
+            Condition::Greater(a, b) => self.get(a) > self.get(b),
+            Condition::NotGreater(a, b) => self.get(a) <= self.get(b),
+        }
+    }
+
+    fn target(&self, from: usize, target: &Target) -> Result<usize, VmError> {
+        match target {
+            Target::Line(n) => Ok(*n),
+            Target::Label(l) => {
+                self.labels.get(l.as_str()).copied().ok_or_else(|| self.error(from, VmErrorKind::UnknownLabel(l.clone())))
+            }
+            Target::Var(v) => self.line(from, self.get(&Value::Var(v.clone()))),
+        }
+    }
+
+    fn line(&self, from: usize, n: f64) -> Result<usize, VmError> {
+        if n.fract() != 0.0 || n < 1.0 {
+            return Err(self.error(from, VmErrorKind::BadJump(n)));
+        }
+        Ok(n as usize)
+    }
+
+    fn spawn(&self, command: &Command) -> Option<Spawn> {
+        let v = |value: &Value| self.get(value);
+        let or_zero = |value: &Option<Value>| value.as_ref().map_or(0.0, v);
+        Some(match command {
+            Command::BoneV { x, y, height, direction, speed, color } => Spawn::BoneV {
+                x: v(x),
+                y: v(y),
+                height: v(height),
+                direction: v(direction),
+                speed: v(speed),
+                color: or_zero(color),
+            },
+            Command::BoneH { x, y, width, direction, speed, color } => Spawn::BoneH {
+                x: v(x),
+                y: v(y),
+                width: v(width),
+                direction: v(direction),
+                speed: v(speed),
+                color: or_zero(color),
+            },
+            Command::BoneVRepeat { x, y, height, direction, speed, count, spacing } => Spawn::BoneVRepeat {
+                x: v(x),
+                y: v(y),
+                height: v(height),
+                direction: v(direction),
+                speed: v(speed),
+                count: v(count),
+                spacing: v(spacing),
+            },
+            Command::BoneHRepeat { x, y, width, direction, speed, count, spacing } => Spawn::BoneHRepeat {
+                x: v(x),
+                y: v(y),
+                width: v(width),
+                direction: v(direction),
+                speed: v(speed),
+                count: v(count),
+                spacing: v(spacing),
+            },
+            Command::BoneStab { direction, height, warning, duration } => Spawn::BoneStab {
+                direction: v(direction),
+                height: v(height),
+                warning: v(warning),
+                duration: v(duration),
+            },
+            Command::SineBones { count, spacing, speed, height } => {
+                Spawn::SineBones { count: v(count), spacing: v(spacing), speed: v(speed), height: v(height) }
+            }
+            Command::GasterBlaster { size, x, y, end_x, end_y, angle, aim, fire } => Spawn::GasterBlaster {
+                size: v(size),
+                x: v(x),
+                y: v(y),
+                end_x: v(end_x),
+                end_y: v(end_y),
+                angle: v(angle),
+                aim: v(aim),
+                fire: v(fire),
+            },
+            Command::Platform { x, y, width, direction, speed, kind } => Spawn::Platform {
+                x: v(x),
+                y: v(y),
+                width: v(width),
+                direction: v(direction),
+                speed: v(speed),
+                kind: or_zero(kind),
+            },
+            Command::PlatformRepeat { x, y, width, direction, speed, count, spacing } => Spawn::PlatformRepeat {
+                x: v(x),
+                y: v(y),
+                width: v(width),
+                direction: v(direction),
+                speed: v(speed),
+                count: v(count),
+                spacing: v(spacing),
+            },
+            Command::CombatZoneResize { x1, y1, x2, y2, .. } => {
+                Spawn::CombatZoneResize { x1: v(x1), y1: v(y1), x2: v(x2), y2: v(y2), instant: false }
+            }
+            Command::CombatZoneResizeInstant { x1, y1, x2, y2 } => {
+                Spawn::CombatZoneResize { x1: v(x1), y1: v(y1), x2: v(x2), y2: v(y2), instant: true }
+            }
+            Command::CombatZoneSpeed(speed) => Spawn::CombatZoneSpeed(v(speed)),
+            Command::HeartTeleport { x, y } => Spawn::HeartTeleport { x: v(x), y: v(y) },
+            Command::HeartMode(mode) => Spawn::HeartMode(v(mode)),
+            Command::HeartMaxFallSpeed(speed) => Spawn::HeartMaxFallSpeed(v(speed)),
+            Command::SansSlam(direction) => Spawn::SansSlam(v(direction)),
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled(name: &str) -> AttackScript {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../gh-page").join(name);
+        AttackScript::load(&path).unwrap()
+    }
+
+    fn run(source: &str) -> Result<Timeline, VmError> {
+        let script = AttackScript::parse("t.csv", source).unwrap();
+        Vm::new(&script).with_seed(1).run()
+    }
+
+    #[test]
+    fn test_delays_accumulate() {
+        let timeline = Vm::new(&bundled("sans_bonegap1.csv")).run().unwrap();
+        assert!(timeline.ended);
+        assert!((timeline.duration - 6.6).abs() < 1e-9);
+        let times: Vec<f64> = timeline.events.iter().map(|e| e.time).collect();
+        assert_eq!(times, [0.0, 0.0, 0.0, 0.2, 0.2, 0.2, 0.2]);
+        assert_eq!(timeline.events[1], Event { time: 0.0, line: 2, spawn: Spawn::HeartTeleport { x: 320.0, y: 376.0 } });
+        assert_eq!(
+            timeline.events[3].spawn,
+            Spawn::BoneVRepeat { x: 128.0, y: 257.0, height: 95.0, direction: 0.0, speed: 180.0, count: 8.0, spacing: 120.0 }
+        );
+    }
+
+    #[test]
+    fn test_random_blasters_loop_and_clamp() {
+        let script = bundled("sans_randomblaster1.csv");
+        for seed in 0..20 {
+            let timeline = Vm::new(&script).with_seed(seed).with_heart(320.0, 304.0).run().unwrap();
+            assert!(timeline.ended);
+            let blasters: Vec<&Event> = timeline.events.iter().filter(|e| matches!(e.spawn, Spawn::GasterBlaster { .. })).collect();
+            assert_eq!(blasters.len(), 15);
+            for (k, event) in blasters.iter().enumerate() {
+                assert!((event.time - (0.5 + k as f64 * 0.53333)).abs() < 1e-9);
+                let Spawn::GasterBlaster { x, y, end_x, end_y, angle, .. } = event.spawn else { unreachable!() };
+                assert!((50.0..=590.0).contains(&end_x) && (40.0..=440.0).contains(&end_y), "{end_x} {end_y}");
+                // Aimed from where it stops at the heart
+                let aim = (304.0 - end_y).atan2(320.0 - end_x).to_degrees();
+                assert!((angle - aim).abs() < 1e-9);
+                // and flies in from an ellipse round it
+                assert!((((x - 320.0) / 400.0).powi(2) + ((y - 304.0) / 300.0).powi(2) - 1.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let script = bundled("sans_multi1.csv");
+        let a = Vm::new(&script).with_seed(7).run().unwrap();
+        let b = Vm::new(&script).with_seed(7).run().unwrap();
+        assert_eq!(a, b);
+        let mut vm = Vm::new(&script).with_seed(7);
+        assert!(vm.run().unwrap().ended);
+        assert_eq!(vm.var("Loop"), Some(0.0));
+    }
+
+    #[test]
+    fn test_control_opcodes() {
+        let source = "\
+0,SET,A,7
+0,MOD,B,$A,4
+0,DIV,C,$A,2
+0,FLOOR,C,$C
+0,SIN,S,90
+0,COS,K,180
+0,ANGLE,G,0,0,0,10
+0,MUL,D,$Unset,5
+0,JMPREL,2
+0,SET,A,100
+0,JMPL,13,$A,$B
+0,SET,E,1
+0,EndAttack";
+        let script = AttackScript::parse("t.csv", source).unwrap();
+        let mut vm = Vm::new(&script);
+        assert!(vm.run().unwrap().ended);
+        let vars: Vec<Option<f64>> = ["A", "B", "C", "S", "K", "G", "D", "E"].iter().map(|v| vm.var(v)).collect();
+        assert_eq!(vars, [Some(7.0), Some(3.0), Some(3.0), Some(1.0), Some(-1.0), Some(90.0), Some(0.0), Some(1.0)]);
+    }
+
+    #[test]
+    fn test_rnd_range() {
+        let script = AttackScript::parse("t.csv", "0,RND,R,5\n0.1,JMPNE,1,$R,4\n0,EndAttack").unwrap();
+        let mut vm = Vm::new(&script).with_seed(3);
+        let timeline = vm.run().unwrap();
+        assert!(timeline.ended);
+        assert_eq!(vm.var("R"), Some(4.0));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(run("0,JMPABS,1").unwrap_err().kind, VmErrorKind::InfiniteLoop);
+        assert_eq!(run("0,JMPZ,Nowhere,0").unwrap_err().kind, VmErrorKind::UnknownLabel("Nowhere".into()));
+        assert_eq!(run("0,SET,L,2.5\n0,JMPABS,$L").unwrap_err(), VmError { file: "t.csv".into(), line: 2, kind: VmErrorKind::BadJump(2.5) });
+        let script = AttackScript::parse("t.csv", "1,JMPABS,1").unwrap();
+        assert_eq!(Vm::new(&script).with_max_steps(50).run().unwrap_err().kind, VmErrorKind::StepLimit(50));
+        // Jumping past the last row stops the script without EndAttack
+        let timeline = run("1,BoneV,1,2,3,0,240\n0,JMPREL,5").unwrap();
+        assert!(!timeline.ended);
+        assert_eq!(timeline.events.len(), 1);
+    }
+
+    #[test]
+    fn test_bundled_scripts_end() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../gh-page");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let name = entry.unwrap().file_name().to_string_lossy().into_owned();
+            if !(name.starts_with("sans_") && name.ends_with(".csv")) {
+                continue;
+            }
+            let script = bundled(&name);
+            for seed in 0..5 {
+                let timeline = Vm::new(&script).with_seed(seed).run().unwrap_or_else(|e| panic!("{e}"));
+                assert!(timeline.ended, "{name}");
+                assert!(timeline.events.windows(2).all(|w| w[0].time <= w[1].time), "{name}");
+            }
+        }
+    }
+}
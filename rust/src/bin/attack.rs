@@ -0,0 +1,175 @@
+// Command-line tools for the attack scripts in gh-page: lint them before the
+// game page gets to run them, move them to and from label source, and
+// compile the attack language to them.
+
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use sans_guard::attack::{self, Severity};
+
+#[derive(Parser)]
+#[command(name = "attack", about = "Tools for the Sans fight attack scripts")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check scripts for bad rows, broken jumps and unset variables
+    Lint {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Fail on warnings too
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// Turn label source into the CSV the game plays
+    Asm(Convert),
+    /// Turn a CSV script into label source
+    Disasm(Convert),
+    /// Compile the attack language to the CSV the game plays
+    Compile(Convert),
+    /// Turn a CSV script into the attack language
+    Decompile(Convert),
+}
+
+#[derive(Args)]
+struct Convert {
+    input: PathBuf,
+    /// Output file, stdout if left out
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("attack: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Whether every script passed
+fn run(cli: Cli) -> Result<bool, Box<dyn std::error::Error>> {
+    match cli.command {
+        Command::Lint { files, deny_warnings } => {
+            let mut passed = true;
+            for path in &files {
+                for lint in attack::lint_file(path) {
+                    println!("{lint}");
+                    passed &= lint.severity() == Severity::Warning && !deny_warnings;
+                }
+            }
+            Ok(passed)
+        }
+        Command::Asm(c) => {
+            let source = std::fs::read_to_string(&c.input)?;
+            write_output(c.output.as_deref(), &attack::assemble(&c.input.display().to_string(), &source)?)?;
+            Ok(true)
+        }
+        Command::Disasm(c) => {
+            let source = std::fs::read_to_string(&c.input)?;
+            write_output(c.output.as_deref(), &attack::disassemble(&c.input.display().to_string(), &source)?)?;
+            Ok(true)
+        }
+        Command::Compile(c) => {
+            let source = std::fs::read_to_string(&c.input)?;
+            write_output(c.output.as_deref(), &attack::compile(&c.input.display().to_string(), &source)?)?;
+            Ok(true)
+        }
+        Command::Decompile(c) => {
+            let source = std::fs::read_to_string(&c.input)?;
+            write_output(c.output.as_deref(), &attack::decompile(&c.input.display().to_string(), &source)?)?;
+            Ok(true)
+        }
+    }
+}
+
+fn write_output(path: Option<&Path>, text: &str) -> std::io::Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, text),
+        None => std::io::stdout().lock().write_all(text.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn run_args(args: &[&str]) -> Result<bool, Box<dyn std::error::Error>> {
+        run(Cli::try_parse_from([&["attack"], args].concat())?)
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("attack-cli-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_lint_exit_status() {
+        let dir = scratch("lint");
+        std::fs::write(dir.join("clean.csv"), "0,SET,Loop,3\n0,:Top\n0.5,SUB,Loop,$Loop,1\n0,JMPNZ,Top,$Loop\n0,EndAttack").unwrap();
+        std::fs::write(dir.join("warning.csv"), "0,JMPZ,2,$Flag\n0,EndAttack").unwrap();
+        std::fs::write(dir.join("error.csv"), "0,BoneZ,1\n0,EndAttack").unwrap();
+        let (clean, warning, error) = (path(&dir, "clean.csv"), path(&dir, "warning.csv"), path(&dir, "error.csv"));
+        assert!(run_args(&["lint", &clean]).unwrap());
+        assert!(run_args(&["lint", &clean, "--deny-warnings"]).unwrap());
+        assert!(run_args(&["lint", &warning]).unwrap());
+        assert!(!run_args(&["lint", &warning, "--deny-warnings"]).unwrap());
+        assert!(!run_args(&["lint", &error]).unwrap());
+        // One failing file fails the run
+        assert!(!run_args(&["lint", &clean, &error]).unwrap());
+        assert!(!run_args(&["lint", &path(&dir, "missing.csv")]).unwrap());
+        assert!(run_args(&["lint"]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_asm_disasm() {
+        let dir = scratch("asm");
+        std::fs::write(dir.join("loop.src"), "0,SET,Loop,3\nTop:\n0.5,SUB,Loop,$Loop,1\n0,JMPNZ,Top,$Loop\n0,EndAttack\n").unwrap();
+        assert!(run_args(&["asm", &path(&dir, "loop.src"), "-o", &path(&dir, "loop.csv")]).unwrap());
+        let csv = std::fs::read_to_string(dir.join("loop.csv")).unwrap();
+        assert_eq!(csv, "0,SET,Loop,3,\n0.5,SUB,Loop,$Loop,1\n0,JMPNZ,2,$Loop,\n0,EndAttack,,,\n");
+        assert!(run_args(&["disasm", &path(&dir, "loop.csv"), "-o", &path(&dir, "back.src")]).unwrap());
+        assert!(run_args(&["asm", &path(&dir, "back.src"), "-o", &path(&dir, "again.csv")]).unwrap());
+        assert_eq!(std::fs::read_to_string(dir.join("again.csv")).unwrap(), csv);
+        std::fs::write(dir.join("bad.src"), "0,JMPABS,Nowhere\n").unwrap();
+        assert!(run_args(&["asm", &path(&dir, "bad.src"), "-o", &path(&dir, "bad.csv")]).is_err());
+        assert!(!dir.join("bad.csv").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_decompile() {
+        let dir = scratch("compile");
+        std::fs::write(dir.join("loop.atk"), "let n = 3\nTop:\nwait 0.25\nlet n = n - 1\ngoto Top if n > 0\nEndAttack()\n").unwrap();
+        assert!(run_args(&["compile", &path(&dir, "loop.atk"), "-o", &path(&dir, "loop.csv")]).unwrap());
+        let csv = std::fs::read_to_string(dir.join("loop.csv")).unwrap();
+        assert_eq!(csv, "0,SET,n,3,\n0.25,SUB,n,$n,1\n0,JMPG,2,$n,0\n0,EndAttack,,,\n");
+        assert!(run_args(&["decompile", &path(&dir, "loop.csv"), "-o", &path(&dir, "back.atk")]).unwrap());
+        assert!(run_args(&["compile", &path(&dir, "back.atk"), "-o", &path(&dir, "again.csv")]).unwrap());
+        assert_eq!(std::fs::read_to_string(dir.join("again.csv")).unwrap(), csv);
+        std::fs::write(dir.join("bad.atk"), "bone_v(1, 2, 3, 4, spead: 5)\n").unwrap();
+        assert!(run_args(&["compile", &path(&dir, "bad.atk"), "-o", &path(&dir, "bad.csv")]).is_err());
+        assert!(!dir.join("bad.csv").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+// Command-line front end to the Enigma library: encrypt and decrypt files or
+// stdin with a settings file, trace the machine keypress by keypress, generate
+// key sheets and run the cryptanalysis routines.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use rand::{rngs::StdRng, SeedableRng};
+use sans_guard::enigma::analysis::{self, AttackConfig, NgramModel};
+use sans_guard::enigma::bombe::{self, Bombe, Menu};
+use sans_guard::enigma::procedure::KeySheet;
+use sans_guard::enigma::{EnigmaSettings, EnigmaWriter, ReflectorType, RotorType};
+
+#[derive(Parser)]
+#[command(name = "enigma", about = "Enigma machine simulator and cryptanalysis tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt a file or stdin
+    Encrypt(Transform),
+    /// Decrypt a file or stdin (the same operation, Enigma is reciprocal)
+    Decrypt(Transform),
+    /// Print the rotor windows and the letter mapping for every keypress
+    Trace {
+        #[arg(short, long)]
+        settings: PathBuf,
+        text: String,
+    },
+    /// Print a month of random daily keys in key sheet format
+    Keysheet {
+        #[arg(long, default_value_t = 31)]
+        days: u8,
+        /// Seed for a reproducible sheet
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Print the settings file for the default machine, to start from
+    Settings {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Ciphertext-only attack: rotor order, rings and plugboard
+    Analyse {
+        input: Option<PathBuf>,
+        #[command(flatten)]
+        space: SearchSpace,
+        /// Rotor order/position candidates carried past the first stage
+        #[arg(long, default_value = "10")]
+        keep: NonZeroUsize,
+        #[arg(long, default_value_t = 10)]
+        max_plugs: usize,
+        /// How many ranked candidates to print
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+    },
+    /// Run the bombe with a crib against a ciphertext
+    Bombe {
+        #[arg(short, long)]
+        crib: String,
+        /// Letter offset of the crib; every possible offset if left out
+        #[arg(long)]
+        offset: Option<usize>,
+        input: Option<PathBuf>,
+        #[command(flatten)]
+        space: SearchSpace,
+    },
+}
+
+#[derive(Args)]
+struct Transform {
+    #[arg(short, long)]
+    settings: PathBuf,
+    /// Input file, stdin if left out
+    input: Option<PathBuf>,
+    /// Output file, stdout if left out
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SearchSpace {
+    /// Rotors to try, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "I,II,III,IV,V", value_parser = parse_rotor)]
+    rotors: Vec<RotorType>,
+    #[arg(long, default_value = "B", value_parser = parse_reflector)]
+    reflector: ReflectorType,
+}
+
+fn parse_rotor(s: &str) -> Result<RotorType, String> {
+    s.parse().map_err(|_| format!("unknown rotor {s:?}"))
+}
+
+fn parse_reflector(s: &str) -> Result<ReflectorType, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => ReflectorType::try_from(c).map_err(|e| e.to_string()),
+        _ => Err(format!("unknown reflector {s:?}, expected A, B, C, b or c")),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("enigma: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    match cli.command {
+        Command::Encrypt(t) | Command::Decrypt(t) => transform(&t),
+        Command::Trace { settings, text } => {
+            let mut machine = load_settings(&settings)?.machine()?;
+            println!("start  {}", machine.window());
+            for c in text.chars() {
+                let out = machine.process_char(c);
+                if out == c {
+                    println!("{c:?} passes through");
+                } else {
+                    println!("{}  {c} -> {out}", machine.window());
+                }
+            }
+            Ok(())
+        }
+        Command::Keysheet { days, seed } => {
+            let mut rng = match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            print!("{}", KeySheet::random(days, &mut rng));
+            Ok(())
+        }
+        Command::Settings { json } => {
+            let settings = EnigmaSettings::default();
+            print!("{}", if json { settings.to_json() } else { settings.to_toml() });
+            Ok(())
+        }
+        Command::Analyse { input, space, keep, max_plugs, top } => {
+            let ciphertext = read_input(input.as_deref())?;
+            println!("index of coincidence {:.4}", analysis::index_of_coincidence(&ciphertext));
+            println!("trigram fitness {:.1}", NgramModel::english_trigrams().score(&ciphertext));
+            let config = AttackConfig { rotor_pool: space.rotors, reflector: space.reflector, keep: keep.get(), max_plugs };
+            for (i, c) in analysis::attack(&ciphertext, &config).iter().take(top).enumerate() {
+                let rotors: Vec<String> = c.rotors.iter().map(|r| r.to_string()).collect();
+                println!(
+                    "{}. {:.1}  {} {} rings {} start {} plugs [{}]",
+                    i + 1,
+                    c.score,
+                    c.reflector,
+                    rotors.join(" "),
+                    c.rings.iter().collect::<String>(),
+                    c.positions.iter().collect::<String>(),
+                    c.plugboard
+                );
+                println!("   {}", c.decrypt(&ciphertext)?);
+            }
+            Ok(())
+        }
+        Command::Bombe { crib, offset, input, space } => {
+            let ciphertext = read_input(input.as_deref())?;
+            let offsets = match offset {
+                Some(o) => vec![o],
+                None => bombe::crib_offsets(&ciphertext, &crib),
+            };
+            let bombe = Bombe { reflector: space.reflector, ..Bombe::default() };
+            let orders = Bombe::orders(&space.rotors);
+            for offset in offsets {
+                let menu = Menu::new(&ciphertext, &crib, offset)?;
+                println!("offset {offset}: {} closures", menu.closures());
+                for stop in bombe.run(&menu, &orders) {
+                    let rotors: Vec<String> = stop.rotors.iter().map(|r| r.to_string()).collect();
+                    println!(
+                        "  stop {} {} steckers [{}]",
+                        rotors.join(" "),
+                        stop.positions.iter().collect::<String>(),
+                        stop.plugboard()
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn transform(t: &Transform) -> Result<(), Box<dyn std::error::Error>> {
+    let machine = load_settings(&t.settings)?.machine()?;
+    let mut input: Box<dyn Read> = match &t.input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let output: Box<dyn Write> = match &t.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = EnigmaWriter::new(output, machine);
+    io::copy(&mut input, &mut writer)?;
+    let (mut output, _) = writer.finish()?;
+    output.flush()?;
+    Ok(())
+}
+
+fn load_settings(path: &Path) -> Result<EnigmaSettings, String> {
+    EnigmaSettings::load(path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn read_input(path: Option<&Path>) -> io::Result<String> {
+    let mut text = String::new();
+    match path {
+        Some(path) => {
+            File::open(path)?.read_to_string(&mut text)?;
+        }
+        None => {
+            io::stdin().read_to_string(&mut text)?;
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn run_args(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        run(Cli::try_parse_from([&["enigma"], args].concat())?)
+    }
+
+    // A scratch directory with the default settings in it
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("enigma-cli-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("settings.toml"), EnigmaSettings::default().to_toml()).unwrap();
+        dir
+    }
+
+    fn path(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let dir = scratch("transform");
+        std::fs::write(dir.join("plain.txt"), "HELLO WORLD").unwrap();
+        let settings = path(&dir, "settings.toml");
+        run_args(&["encrypt", "-s", &settings, &path(&dir, "plain.txt"), "-o", &path(&dir, "cipher.txt")]).unwrap();
+        run_args(&["decrypt", "-s", &settings, &path(&dir, "cipher.txt"), "-o", &path(&dir, "back.txt")]).unwrap();
+        let cipher = std::fs::read_to_string(dir.join("cipher.txt")).unwrap();
+        assert_ne!(cipher, "HELLO WORLD");
+        assert_eq!(std::fs::read_to_string(dir.join("back.txt")).unwrap(), "HELLO WORLD");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_trace_keysheet_settings() {
+        let dir = scratch("trace");
+        run_args(&["trace", "-s", &path(&dir, "settings.toml"), "AB C"]).unwrap();
+        run_args(&["keysheet", "--days", "3", "--seed", "7"]).unwrap();
+        run_args(&["settings", "--json"]).unwrap();
+        assert!(run_args(&["trace", "-s", &path(&dir, "missing.toml"), "A"]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_analyse() {
+        let dir = scratch("analyse");
+        std::fs::write(dir.join("ct.txt"), "QMJIDOMZWZJFJRWFGDPOBFTVXRQZKT").unwrap();
+        let input = path(&dir, "ct.txt");
+        run_args(&["analyse", &input, "--rotors", "I,II,III", "--keep", "1", "--max-plugs", "0", "--top", "1"]).unwrap();
+        assert!(Cli::try_parse_from(["enigma", "analyse", &input, "--keep", "0"]).is_err());
+        assert!(Cli::try_parse_from(["enigma", "analyse", &input, "--rotors", "I,IX"]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_bombe() {
+        let dir = scratch("bombe");
+        std::fs::write(dir.join("ct.txt"), "QWERTYUIOPASDFGHJKLZ").unwrap();
+        let input = path(&dir, "ct.txt");
+        run_args(&["bombe", &input, "--crib", "WETTER", "--offset", "0", "--rotors", "I,II,III"]).unwrap();
+        // The crib lines up with W under W at offset 1
+        assert!(run_args(&["bombe", &input, "--crib", "WETTER", "--offset", "1", "--rotors", "I,II,III"]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
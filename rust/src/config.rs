@@ -0,0 +1,176 @@
+// Per-user settings on disk (%APPDATA%\SansGate on Windows,
+// $XDG_CONFIG_HOME/SansGate elsewhere) and the Windows autostart entry.
+
+pub fn config_dir() -> Option<std::path::PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        return std::env::var_os("APPDATA").map(std::path::PathBuf::from).map(|mut p| { p.push("SansGate"); p });
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|h| {
+                    let mut p = std::path::PathBuf::from(h);
+                    p.push(".config");
+                    p
+                })
+            });
+        base.map(|mut p| { p.push("SansGate"); p })
+    }
+}
+
+pub fn autostart_cfg_path() -> Option<std::path::PathBuf> {
+    let mut dir = config_dir()?;
+    std::fs::create_dir_all(&dir).ok()?;
+    dir.push("autostart.txt");
+    Some(dir)
+}
+
+pub fn read_autostart_config() -> Option<bool> {
+    let p = autostart_cfg_path()?;
+    let s = std::fs::read_to_string(p).ok()?;
+    parse_autostart(&s)
+}
+
+pub fn parse_autostart(s: &str) -> Option<bool> {
+    let v = s.trim().to_ascii_lowercase();
+    match v.as_str() { "true" | "1" | "yes" | "y" => Some(true), "false" | "0" | "no" | "n" => Some(false), _ => None }
+}
+
+pub fn write_autostart_config(val: bool) -> std::io::Result<()> {
+    if let Some(p) = autostart_cfg_path() { std::fs::write(p, if val { "true" } else { "false" })?; }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub fn ensure_autostart_config_file() {
+    use std::fs;
+    if let Some(p) = autostart_cfg_path() {
+        if !p.exists() {
+            let _ = fs::write(&p, b"true");
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn apply_autostart_from_config() {
+    match read_autostart_config() {
+        Some(true) => { let _ = set_autostart("SansGate"); }
+        Some(false) => { let _ = remove_autostart("SansGate"); }
+        None => {}
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn ensure_autostart_prompt_once() {
+    use std::fs;
+    use std::path::PathBuf;
+
+    const RUN_VALUE: &str = "SansGate";
+    let mut prompted = false;
+    if let Some(appdata) = std::env::var_os("APPDATA") {
+        let mut p = PathBuf::from(appdata);
+        p.push("SansGate");
+        let _ = fs::create_dir_all(&p);
+        p.push("autostart_prompted.flag");
+        if p.exists() {
+            prompted = true;
+        } else {
+            // First run: enable autostart silently
+            if !is_autostart_configured(RUN_VALUE).unwrap_or(false) {
+                let _ = set_autostart(RUN_VALUE);
+            }
+            let _ = fs::write(&p, b"1");
+            prompted = true;
+        }
+    }
+    if !prompted {
+        if !is_autostart_configured("SansGate").unwrap_or(true) {
+            let _ = set_autostart("SansGate");
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn is_autostart_configured(value_name: &str) -> windows::core::Result<bool> {
+    use windows::core::PCWSTR;
+    use windows::Win32::System::Registry::{RegCloseKey, RegGetValueW, RegOpenKeyExW, HKEY, HKEY_CURRENT_USER, RRF_RT_REG_SZ, KEY_READ};
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+
+    let subkey = to_wide("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+    let name = to_wide(value_name);
+    unsafe {
+        let mut hkey: HKEY = HKEY::default();
+        let open = RegOpenKeyExW(HKEY_CURRENT_USER, PCWSTR(subkey.as_ptr()), 0, KEY_READ, &mut hkey);
+        if open != ERROR_SUCCESS { return Ok(false); }
+        let mut size: u32 = 0;
+        let status = RegGetValueW(hkey, PCWSTR(std::ptr::null()), PCWSTR(name.as_ptr()), RRF_RT_REG_SZ, None, None, Some(&mut size));
+        let _ = RegCloseKey(hkey);
+        Ok(status == ERROR_SUCCESS)
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn set_autostart(value_name: &str) -> windows::core::Result<()> {
+    use windows::core::PCWSTR;
+    use windows::Win32::System::Registry::{RegCloseKey, RegOpenKeyExW, RegSetValueExW, HKEY, HKEY_CURRENT_USER, KEY_SET_VALUE, REG_SZ};
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+
+    let subkey = to_wide("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+    let name = to_wide(value_name);
+    let exe = std::env::current_exe().unwrap_or_default();
+    let exe_str = format!("\"{}\"", exe.display());
+    let data = to_wide(&exe_str);
+    unsafe {
+        let mut hkey: HKEY = HKEY::default();
+        let open = RegOpenKeyExW(HKEY_CURRENT_USER, PCWSTR(subkey.as_ptr()), 0, KEY_SET_VALUE, &mut hkey);
+        if open != ERROR_SUCCESS { return Ok(()); }
+        let bytes = std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 2);
+        let _ = RegSetValueExW(hkey, PCWSTR(name.as_ptr()), 0, REG_SZ, Some(bytes));
+        let _ = RegCloseKey(hkey);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub fn remove_autostart(value_name: &str) -> windows::core::Result<()> {
+    use windows::core::PCWSTR;
+    use windows::Win32::System::Registry::{RegCloseKey, RegOpenKeyExW, RegDeleteValueW, HKEY, HKEY_CURRENT_USER, KEY_SET_VALUE};
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+
+    let subkey = to_wide("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+    let name = to_wide(value_name);
+    unsafe {
+        let mut hkey: HKEY = HKEY::default();
+        let open = RegOpenKeyExW(HKEY_CURRENT_USER, PCWSTR(subkey.as_ptr()), 0, KEY_SET_VALUE, &mut hkey);
+        if open != ERROR_SUCCESS { return Ok(()); }
+        let _ = RegDeleteValueW(hkey, PCWSTR(name.as_ptr()));
+        let _ = RegCloseKey(hkey);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub fn to_wide(s: &str) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    std::ffi::OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_autostart() {
+        for on in ["true", "1", "Yes", " y\n"] {
+            assert_eq!(parse_autostart(on), Some(true), "{on:?}");
+        }
+        for off in ["false", "0", "NO", "n"] {
+            assert_eq!(parse_autostart(off), Some(false), "{off:?}");
+        }
+        assert_eq!(parse_autostart("maybe"), None);
+        assert_eq!(parse_autostart(""), None);
+    }
+}
@@ -1,147 +1,641 @@
+pub mod analysis;
+pub mod bombe;
+mod extended;
+mod plugboard;
+pub mod procedure;
+#[cfg(test)]
+mod properties;
+mod settings;
+mod stream;
+
+use extended::ExtendedWiring;
+pub use plugboard::{Plugboard, Uhr, UkwD};
+use serde::{Deserialize, Serialize};
+pub use settings::{EnigmaSettings, RotorSetting, SettingsError};
+pub use stream::{EnigmaReader, EnigmaWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RotorType {
+    I,
+    II,
+    III,
+    IV,
+    V,
+    VI,
+    VII,
+    VIII,
+    // Thin greek rotors, only used in the fourth slot of the M4
+    Beta,
+    Gamma,
+}
+
+impl RotorType {
+    pub const ALL: [RotorType; 10] = [
+        RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V,
+        RotorType::VI, RotorType::VII, RotorType::VIII, RotorType::Beta, RotorType::Gamma,
+    ];
+
+    pub fn wiring(self) -> &'static str {
+        match self {
+            RotorType::I => "EKMFLGDQVZNTOWYHXUSPAIBRCJ",
+            RotorType::II => "AJDKSIRUXBLHWTMCQGZNPYFVOE",
+            RotorType::III => "BDFHJLCPRTXVZNYEIWGAKMUSQO",
+            RotorType::IV => "ESOVPZJAYQUIRHXLNFTGKDCMWB",
+            RotorType::V => "VZBRGITYUPSDNHLXAWMJQOFECK",
+            RotorType::VI => "JPGVOUMFYQBENHZRDKASXLICTW",
+            RotorType::VII => "NZJHGRCXMYSWBOUFAIVLPEKQDT",
+            RotorType::VIII => "FKQHTLXOCBJSPDZRAMEWNIUYGV",
+            RotorType::Beta => "LEYJVCNIXWPBQMDRTAKZGFUHOS",
+            RotorType::Gamma => "FSOKANUERHMBTJCLGWPDIQZVXY",
+        }
+    }
+
+    // Window letters at which this rotor carries its neighbour along.
+    // VI-VIII have two notches; the greek rotors never step anything.
+    pub fn notches(self) -> &'static str {
+        match self {
+            RotorType::I => "Q",
+            RotorType::II => "E",
+            RotorType::III => "V",
+            RotorType::IV => "J",
+            RotorType::V => "Z",
+            RotorType::VI | RotorType::VII | RotorType::VIII => "ZM",
+            RotorType::Beta | RotorType::Gamma => "",
+        }
+    }
+
+    pub fn is_thin(self) -> bool {
+        matches!(self, RotorType::Beta | RotorType::Gamma)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RotorType::I => "I",
+            RotorType::II => "II",
+            RotorType::III => "III",
+            RotorType::IV => "IV",
+            RotorType::V => "V",
+            RotorType::VI => "VI",
+            RotorType::VII => "VII",
+            RotorType::VIII => "VIII",
+            RotorType::Beta => "Beta",
+            RotorType::Gamma => "Gamma",
+        }
+    }
+}
+
+impl std::fmt::Display for RotorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for RotorType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RotorType::ALL
+            .iter()
+            .copied()
+            .find(|r| r.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ReflectorType {
+    A,
+    B,
+    C,
+    // Thin reflectors of the M4, paired with a greek rotor
+    #[serde(rename = "B-thin")]
+    BThin,
+    #[serde(rename = "C-thin")]
+    CThin,
+}
+
+impl ReflectorType {
+    pub const ALL: [ReflectorType; 5] = [
+        ReflectorType::A, ReflectorType::B, ReflectorType::C, ReflectorType::BThin, ReflectorType::CThin,
+    ];
+
+    pub fn wiring(self) -> &'static str {
+        match self {
+            ReflectorType::A => "EJMZALYXVBWFCRQUONTSPIKHGD",
+            ReflectorType::B => "YRUHQSLDPXNGOKMIEBFZCWVJAT",
+            ReflectorType::C => "FVPJIAOYEDRZXWGCTKUQSBNMHL",
+            ReflectorType::BThin => "ENKQAUYWJICOPBLMDXZVFTHRGS",
+            ReflectorType::CThin => "RDOBJNTKVEHMLFCWZAXGYIPSUQ",
+        }
+    }
+
+    pub fn is_thin(self) -> bool {
+        matches!(self, ReflectorType::BThin | ReflectorType::CThin)
+    }
+
+    // Conventional single-letter name: upper case for the wide UKWs,
+    // lower case for the thin ones.
+    pub fn letter(self) -> char {
+        match self {
+            ReflectorType::A => 'A',
+            ReflectorType::B => 'B',
+            ReflectorType::C => 'C',
+            ReflectorType::BThin => 'b',
+            ReflectorType::CThin => 'c',
+        }
+    }
+}
+
+impl TryFrom<char> for ReflectorType {
+    type Error = EnigmaError;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        ReflectorType::ALL
+            .iter()
+            .copied()
+            .find(|r| r.letter() == c)
+            .ok_or(EnigmaError::UnknownReflector(c))
+    }
+}
+
+impl std::fmt::Display for ReflectorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReflectorType::BThin => f.write_str("B-thin"),
+            ReflectorType::CThin => f.write_str("C-thin"),
+            r => write!(f, "{}", r.letter()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnigmaError {
+    UnknownReflector(char),
+    // A rotor that cannot go in the given slot: greek rotors only fit the
+    // fourth slot of an M4, and only greek rotors fit there.
+    InvalidRotor(RotorType),
+    DuplicateRotor(RotorType),
+    // Thin reflector on a three-rotor machine or a wide one on an M4
+    ReflectorMismatch(ReflectorType),
+    InvalidRing(char),
+    InvalidPosition(char),
+    MalformedPlug(String),
+    SelfPair(char),
+    ReusedPlug(char),
+    UhrPairs(usize),
+    InvalidUhrPosition(u8),
+    UkwDPairs(usize),
+    // J and Y are wired together inside UKW-D
+    UkwDFixedPair(char),
+    // UKW-D only fits the three-rotor machines
+    UkwDOnM4,
+}
+
+impl std::fmt::Display for EnigmaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnigmaError::UnknownReflector(c) => write!(f, "unknown reflector '{c}'"),
+            EnigmaError::InvalidRotor(r) => write!(f, "rotor {r} cannot be used in this slot"),
+            EnigmaError::DuplicateRotor(r) => write!(f, "rotor {r} is used more than once"),
+            EnigmaError::ReflectorMismatch(r) => write!(f, "reflector {r} does not fit this machine"),
+            EnigmaError::InvalidRing(c) => write!(f, "invalid ring setting {c:?}, expected A-Z"),
+            EnigmaError::InvalidPosition(c) => write!(f, "invalid rotor position {c:?}, expected A-Z"),
+            EnigmaError::MalformedPlug(p) => write!(f, "malformed plugboard pair {p:?}"),
+            EnigmaError::SelfPair(c) => write!(f, "plugboard pair connects {c} to itself"),
+            EnigmaError::ReusedPlug(c) => write!(f, "plugboard letter {c} is used more than once"),
+            EnigmaError::UhrPairs(n) => write!(f, "the Uhr takes exactly 10 plug pairs, got {n}"),
+            EnigmaError::InvalidUhrPosition(p) => write!(f, "invalid Uhr position {p}, expected 0-39"),
+            EnigmaError::UkwDPairs(n) => write!(f, "UKW-D takes exactly 12 plug pairs, got {n}"),
+            EnigmaError::UkwDFixedPair(c) => write!(f, "{c} is wired to its partner inside UKW-D and cannot be plugged"),
+            EnigmaError::UkwDOnM4 => write!(f, "UKW-D does not fit the M4"),
+        }
+    }
+}
+
+impl std::error::Error for EnigmaError {}
+
+// Which characters the rotors work on. `Classic` only enciphers A-Z and
+// passes everything else through, so punctuation and layout stay readable.
+// `Extended` runs every printable ASCII character, tab and newline through
+// wider rotors and substitutes any other non-ASCII scalar as well; only
+// ASCII control characters are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Alphabet {
+    #[default]
+    Classic,
+    Extended,
+}
+
+impl Alphabet {
+    pub fn size(self) -> usize {
+        match self {
+            Alphabet::Classic => 26,
+            Alphabet::Extended => extended::SIZE,
+        }
+    }
+}
+
+// Everything that changes while a machine is in use: the rotor positions.
+// Wiring, rings, reflector and plugboard are fixed at construction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnigmaState {
+    greek: Option<u8>,
+    positions: [u8; 3],
+}
+
+impl EnigmaState {
+    pub fn window(&self) -> String {
+        self.greek
+            .iter()
+            .chain(self.positions.iter())
+            .map(|&p| extended::symbol(p as usize))
+            .collect()
+    }
+}
+
+#[derive(Clone)]
 pub struct EnigmaMachine {
     rotors: [Rotor; 3],
+    // Fourth, non-stepping rotor of the M4, between rotors[0] and the reflector
+    greek: Option<Rotor>,
     reflector: Reflector,
-    plugboard: Vec<(char, char)>,
+    plugboard: Plugboard,
+    alphabet: Alphabet,
 }
 
 #[derive(Clone)]
 struct Rotor {
-    wiring: Vec<char>,
-    notch: char,
+    // Letter indices 0-25; backward is the inverse of forward
+    forward: [u8; 26],
+    backward: [u8; 26],
+    // Bit n is set if the rotor carries its neighbour on when leaving
+    // position n
+    notches: u32,
     position: usize,
     ring_setting: usize,
+    // Only present in `Alphabet::Extended` mode
+    extended: Option<ExtendedWiring>,
 }
 
+impl Rotor {
+    fn from_setting((kind, ring, start): (RotorType, char, char)) -> Result<Self, EnigmaError> {
+        let forward = table(kind.wiring());
+        let mut backward = [0; 26];
+        for (i, &f) in forward.iter().enumerate() {
+            backward[f as usize] = i as u8;
+        }
+        Ok(Rotor {
+            forward,
+            backward,
+            notches: kind.notches().bytes().fold(0, |bits, n| bits | 1 << (n - b'A')),
+            position: letter_index(start).ok_or(EnigmaError::InvalidPosition(start))?,
+            ring_setting: letter_index(ring).ok_or(EnigmaError::InvalidRing(ring))?,
+            extended: None,
+        })
+    }
+
+    fn at_notch(&self) -> bool {
+        self.position < 26 && self.notches & 1 << self.position != 0
+    }
+
+    // Contact offset of the core against the entry wheel
+    fn offset(&self) -> usize {
+        wrap(self.position + 26 - self.ring_setting)
+    }
+}
+
+#[derive(Clone)]
 struct Reflector {
-    wiring: Vec<char>,
+    wiring: [u8; 26],
+    extended: Option<ExtendedWiring>,
+}
+
+// i % 26 for i < 52, without the division
+fn wrap(i: usize) -> usize {
+    if i >= 26 { i - 26 } else { i }
+}
+
+// "EKMF..." to letter indices
+fn table(wiring: &str) -> [u8; 26] {
+    let mut out = [0; 26];
+    for (slot, b) in out.iter_mut().zip(wiring.bytes()) {
+        *slot = b - b'A';
+    }
+    out
+}
+
+// Accepts either case, anything outside A-Z is rejected
+fn letter_index(c: char) -> Option<usize> {
+    c.is_ascii_alphabetic().then(|| (c.to_ascii_uppercase() as u8 - b'A') as usize)
 }
 
 impl EnigmaMachine {
-    pub fn new(rotors: [(usize, char, char); 3], reflector_type: char, plugboard_pairs: &str) -> Self {
-        let rotor_configs = [
-            ("EKMFLGDQVZNTOWYHXUSPAIBRCJ", 'Q'), // I
-            ("AJDKSIRUXBLHWTMCQGZNPYFVOE", 'E'), // II
-            ("BDFHJLCPRTXVZNYEIWGAKMUSQO", 'V'), // III
-        ];
-
-        let created_rotors = rotors.map(|(idx, ring, start)| {
-            let (wiring_str, notch) = rotor_configs[idx]; // 0-indexed rotor choice
-            Rotor {
-                wiring: wiring_str.chars().collect(),
-                notch,
-                position: (start as u8 - b'A') as usize,
-                ring_setting: (ring as u8 - b'A') as usize,
+    // Panics on invalid settings; use `try_new` for anything user supplied.
+    pub fn new(rotors: [(RotorType, char, char); 3], reflector_type: ReflectorType, plugboard_pairs: &str) -> Self {
+        Self::try_new(rotors, reflector_type, plugboard_pairs).expect("invalid Enigma settings")
+    }
+
+    // Four-rotor Kriegsmarine M4: `greek` sits left of `rotors` and never steps.
+    pub fn new_m4(
+        greek: (RotorType, char, char),
+        rotors: [(RotorType, char, char); 3],
+        reflector_type: ReflectorType,
+        plugboard_pairs: &str,
+    ) -> Self {
+        Self::try_new_m4(greek, rotors, reflector_type, plugboard_pairs).expect("invalid Enigma settings")
+    }
+
+    pub fn try_new(
+        rotors: [(RotorType, char, char); 3],
+        reflector_type: ReflectorType,
+        plugboard_pairs: &str,
+    ) -> Result<Self, EnigmaError> {
+        if reflector_type.is_thin() {
+            return Err(EnigmaError::ReflectorMismatch(reflector_type));
+        }
+        Self::build(None, rotors, reflector_type, plugboard_pairs)
+    }
+
+    pub fn try_new_m4(
+        greek: (RotorType, char, char),
+        rotors: [(RotorType, char, char); 3],
+        reflector_type: ReflectorType,
+        plugboard_pairs: &str,
+    ) -> Result<Self, EnigmaError> {
+        if !greek.0.is_thin() {
+            return Err(EnigmaError::InvalidRotor(greek.0));
+        }
+        if !reflector_type.is_thin() {
+            return Err(EnigmaError::ReflectorMismatch(reflector_type));
+        }
+        Self::build(Some(greek), rotors, reflector_type, plugboard_pairs)
+    }
+
+    fn build(
+        greek: Option<(RotorType, char, char)>,
+        rotors: [(RotorType, char, char); 3],
+        reflector_type: ReflectorType,
+        plugboard_pairs: &str,
+    ) -> Result<Self, EnigmaError> {
+        for (i, &(kind, _, _)) in rotors.iter().enumerate() {
+            if kind.is_thin() {
+                return Err(EnigmaError::InvalidRotor(kind));
             }
-        });
-
-        let reflector_wiring = match reflector_type {
-            'B' => "YRUHQSLDPXNGOKMIEBFZCWVJAT",
-            _ => "YRUHQSLDPXNGOKMIEBFZCWVJAT", // Default to B
-        };
-
-        let mut pb = Vec::new();
-        for pair in plugboard_pairs.split_whitespace() {
-            let chars: Vec<char> = pair.chars().collect();
-            if chars.len() == 2 {
-                pb.push((chars[0], chars[1]));
+            if rotors[..i].iter().any(|&(other, _, _)| other == kind) {
+                return Err(EnigmaError::DuplicateRotor(kind));
             }
         }
 
-        EnigmaMachine {
-            rotors: created_rotors,
-            reflector: Reflector { wiring: reflector_wiring.chars().collect() },
-            plugboard: pb,
+        let [left, middle, right] = rotors;
+        Ok(EnigmaMachine {
+            rotors: [Rotor::from_setting(left)?, Rotor::from_setting(middle)?, Rotor::from_setting(right)?],
+            greek: greek.map(Rotor::from_setting).transpose()?,
+            reflector: Reflector { wiring: table(reflector_type.wiring()), extended: None },
+            plugboard: Plugboard::parse(plugboard_pairs)?,
+            alphabet: Alphabet::Classic,
+        })
+    }
+
+    // Switches the rotor alphabet. Rotor positions and ring settings keep
+    // their letter values; both sides of a message must use the same mode.
+    pub fn with_alphabet(mut self, alphabet: Alphabet) -> Self {
+        let extended = alphabet == Alphabet::Extended;
+        for rotor in self.rotors.iter_mut().chain(self.greek.iter_mut()) {
+            rotor.extended = extended.then(|| ExtendedWiring::rotor(&rotor.forward));
         }
+        self.reflector.extended = extended.then(|| ExtendedWiring::reflector(&self.reflector.wiring));
+        self.alphabet = alphabet;
+        self
+    }
+
+    pub fn alphabet(&self) -> Alphabet {
+        self.alphabet
+    }
+
+    // Replaces the plugboard, e.g. with the connections of an Uhr.
+    pub fn with_plugboard(mut self, plugboard: Plugboard) -> Self {
+        self.plugboard = plugboard;
+        self
     }
 
-    fn map_char(c: char, map: &[(char, char)]) -> char {
-        for &(a, b) in map {
-            if c == a { return b; }
-            if c == b { return a; }
+    pub fn plugboard(&self) -> &Plugboard {
+        &self.plugboard
+    }
+
+    // Swaps the reflector for a UKW-D with the given wiring.
+    pub fn with_ukw_d(mut self, ukw: UkwD) -> Result<Self, EnigmaError> {
+        if self.greek.is_some() {
+            return Err(EnigmaError::UkwDOnM4);
         }
-        c
+        self.reflector.wiring = table(&ukw.wiring());
+        if self.alphabet == Alphabet::Extended {
+            self.reflector.extended = Some(ExtendedWiring::reflector(&self.reflector.wiring));
+        }
+        Ok(self)
+    }
+
+    fn rotor_forward(i: u8, rotor: &Rotor) -> u8 {
+        let offset = rotor.offset();
+        wrap(rotor.forward[wrap(i as usize + offset)] as usize + 26 - offset) as u8
+    }
+
+    fn rotor_backward(i: u8, rotor: &Rotor) -> u8 {
+        let offset = rotor.offset();
+        wrap(rotor.backward[wrap(i as usize + offset)] as usize + 26 - offset) as u8
+    }
+
+    fn extended_forward(idx: usize, rotor: &Rotor) -> usize {
+        let n = extended::SIZE;
+        let wiring = rotor.extended.as_ref().expect("extended wiring");
+        let offset = (rotor.position + n - rotor.ring_setting) % n;
+        (wiring.forward[(idx + offset) % n] as usize + n - offset) % n
+    }
+
+    fn extended_backward(idx: usize, rotor: &Rotor) -> usize {
+        let n = extended::SIZE;
+        let wiring = rotor.extended.as_ref().expect("extended wiring");
+        let offset = (rotor.position + n - rotor.ring_setting) % n;
+        (wiring.backward[(idx + offset) % n] as usize + n - offset) % n
+    }
+
+    // Rotors and reflector only, no plugboard
+    fn extended_scramble(&self, mut idx: usize) -> usize {
+        for rotor in self.rotors.iter().rev().chain(self.greek.iter()) {
+            idx = Self::extended_forward(idx, rotor);
+        }
+        idx = self.reflector.extended.as_ref().expect("extended wiring").forward[idx] as usize;
+        for rotor in self.greek.iter().chain(self.rotors.iter()) {
+            idx = Self::extended_backward(idx, rotor);
+        }
+        idx
+    }
+
+    fn process_extended(&mut self, c: char) -> char {
+        if extended::index(c).is_some() {
+            self.step_rotors();
+            let plugged = self.plugboard.map(c);
+            let idx = extended::index(plugged).expect("plugboard only swaps letters");
+            return self.plugboard.unmap(extended::symbol(self.extended_scramble(idx)));
+        }
+        if c.is_ascii() {
+            return c;
+        }
+        self.step_rotors();
+        // Key the scalar substitution on the rotor positions and on where
+        // the scrambler currently sends the first symbol.
+        let mut key = self
+            .greek
+            .iter()
+            .chain(self.rotors.iter())
+            .fold(self.extended_scramble(0) as u64, |acc, r| acc * extended::SIZE as u64 + r.position as u64);
+        extended::reflect_scalar(c, extended::splitmix(&mut key))
     }
 
-    fn rotor_forward(c: char, rotor: &Rotor) -> char {
-        let offset = (rotor.position + 26 - rotor.ring_setting) % 26;
-        let idx = (c as u8 - b'A') as usize;
-        let input_idx = (idx + offset) % 26;
-        let mapped_char = rotor.wiring[input_idx];
-        let output_idx = (mapped_char as u8 - b'A' as u8) as usize;
-        let final_idx = (output_idx + 26 - offset) % 26;
-        (final_idx as u8 + b'A') as char
+    // The rotor and reflector permutation at the current positions, without
+    // stepping and without the plugboard: what a bombe scrambler models.
+    fn scrambler(&self) -> [u8; 26] {
+        std::array::from_fn(|i| self.scramble(i as u8))
     }
 
-    fn rotor_backward(c: char, rotor: &Rotor) -> char {
-        let offset = (rotor.position + 26 - rotor.ring_setting) % 26;
-        let idx = (c as u8 - b'A') as usize;
-        let input_idx = (idx + offset) % 26;
-        
-        // Find which input maps to this output in the wiring
-        let mapped_char = (input_idx as u8 + b'A') as char;
-        let wiring_idx = rotor.wiring.iter().position(|&x| x == mapped_char).unwrap();
-        
-        let final_idx = (wiring_idx + 26 - offset) % 26;
-        (final_idx as u8 + b'A') as char
+    // One letter index through the rotors, the reflector and back
+    fn scramble(&self, mut i: u8) -> u8 {
+        // Forward through rotors (Right to Left: 2 -> 1 -> 0, then greek)
+        for rotor in self.rotors.iter().rev().chain(self.greek.iter()) {
+            i = Self::rotor_forward(i, rotor);
+        }
+        i = self.reflector.wiring[i as usize];
+        // Backward through rotors (Left to Right: greek -> 0 -> 1 -> 2)
+        for rotor in self.greek.iter().chain(self.rotors.iter()) {
+            i = Self::rotor_backward(i, rotor);
+        }
+        i
+    }
+
+    // A keypress on the classic alphabet, letter index in and out.
+    fn press(&mut self, i: u8) -> u8 {
+        self.step_rotors();
+        self.plugboard.backward(self.scramble(self.plugboard.forward(i)))
     }
 
+    // rotors[0] is the leftmost (slow) rotor and rotors[2] the rightmost
+    // (fast) one, matching the order of the window letters.
     fn step_rotors(&mut self) {
-        let r1_at_notch = self.rotors[2].position == (self.rotors[2].notch as u8 - b'A') as usize;
-        let r2_at_notch = self.rotors[1].position == (self.rotors[1].notch as u8 - b'A') as usize;
+        let right_at_notch = self.rotors[2].at_notch();
+        let middle_at_notch = self.rotors[1].at_notch();
+
+        // The right rotor steps on every keypress. The middle rotor steps when
+        // the right one is at its notch, and also steps itself again when it is
+        // at its own notch: the pawl that pushes the left rotor also pushes
+        // the middle one (the double-step anomaly).
+        let step_left = middle_at_notch;
+        let step_middle = right_at_notch || middle_at_notch;
+
+        let n = self.alphabet.size();
+        if step_left { self.rotors[0].position = (self.rotors[0].position + 1) % n; }
+        if step_middle { self.rotors[1].position = (self.rotors[1].position + 1) % n; }
+        self.rotors[2].position = (self.rotors[2].position + 1) % n;
+    }
+
+    // Letters currently showing in the windows, left to right (greek rotor
+    // first on an M4).
+    pub fn window(&self) -> String {
+        self.greek
+            .iter()
+            .chain(self.rotors.iter())
+            .map(|r| extended::symbol(r.position))
+            .collect()
+    }
+
+    pub fn state(&self) -> EnigmaState {
+        EnigmaState {
+            greek: self.greek.as_ref().map(|r| r.position as u8),
+            positions: self.rotors.each_ref().map(|r| r.position as u8),
+        }
+    }
+
+    // A state taken from a three-rotor machine leaves the greek rotor of an
+    // M4 where it is, and vice versa. A position past the end of this
+    // machine's alphabet, from a machine in extended mode, is an error and
+    // nothing is changed.
+    pub fn restore(&mut self, state: EnigmaState) -> Result<(), EnigmaError> {
+        let size = self.alphabet.size();
+        if let Some(&pos) = state.greek.iter().chain(&state.positions).find(|&&p| p as usize >= size) {
+            return Err(EnigmaError::InvalidPosition(extended::symbol(pos as usize)));
+        }
+        if let (Some(greek), Some(pos)) = (self.greek.as_mut(), state.greek) {
+            greek.position = pos as usize;
+        }
+        for (rotor, pos) in self.rotors.iter_mut().zip(state.positions) {
+            rotor.position = pos as usize;
+        }
+        Ok(())
+    }
 
-        // Rotor 3 (rightmost) always steps
-        let step_r3 = true;
-        // Rotor 2 steps if R3 is at notch OR if R2 is at notch (double stepping)
-        let step_r2 = r1_at_notch || r2_at_notch;
-        // Rotor 1 steps if R2 is at notch
-        let step_r1 = r2_at_notch;
+    // Steps the rotors as if `keypresses` letters had been typed, without
+    // transforming anything. The rotor positions repeat with a period of at
+    // most n^3 for an n-symbol alphabet, so huge offsets cost no more than one
+    // full cycle.
+    pub fn advance(&mut self, keypresses: u64) {
+        let n = self.alphabet.size();
+        let mut seen = std::collections::HashMap::new();
+        let mut done = 0;
+        while done < keypresses {
+            let key = self.rotors.iter().fold(0, |acc, r| acc * n + r.position);
+            if let Some(&first) = seen.get(&key) {
+                let cycle = done - first;
+                let remaining = (keypresses - done) % cycle;
+                for _ in 0..remaining {
+                    self.step_rotors();
+                }
+                return;
+            }
+            seen.insert(key, done);
+            self.step_rotors();
+            done += 1;
+        }
+    }
 
-        if step_r1 { self.rotors[0].position = (self.rotors[0].position + 1) % 26; }
-        if step_r2 { self.rotors[1].position = (self.rotors[1].position + 1) % 26; }
-        if step_r3 { self.rotors[2].position = (self.rotors[2].position + 1) % 26; }
+    // Positions the machine `offset` keypresses after `start`, e.g. to pick up
+    // decryption in the middle of a message encrypted from `start`.
+    pub fn seek(&mut self, start: EnigmaState, offset: u64) -> Result<(), EnigmaError> {
+        self.restore(start)?;
+        self.advance(offset);
+        Ok(())
+    }
+
+    // Number of keypresses `text` costs, i.e. how far it moves the rotors.
+    pub fn keypresses(&self, text: &str) -> u64 {
+        match self.alphabet {
+            Alphabet::Classic => text.chars().filter(|c| c.is_ascii_alphabetic()).count() as u64,
+            Alphabet::Extended => text.chars().filter(|&c| !c.is_ascii() || extended::index(c).is_some()).count() as u64,
+        }
     }
 
     pub fn process_char(&mut self, c: char) -> char {
+        if self.alphabet == Alphabet::Extended {
+            return self.process_extended(c);
+        }
+        // Only A-Z are enciphered, in either case; symbols pass through so
+        // the layout of the text survives.
         if !c.is_ascii_alphabetic() {
             return c;
         }
-        let upper = c.to_ascii_uppercase();
-        
-        self.step_rotors();
+        self.process_byte(c as u8) as char
+    }
 
-        let mut res = Self::map_char(upper, &self.plugboard);
-        
-        // Forward through rotors (Right to Left: 2 -> 1 -> 0)
-        res = Self::rotor_forward(res, &self.rotors[2]);
-        res = Self::rotor_forward(res, &self.rotors[1]);
-        res = Self::rotor_forward(res, &self.rotors[0]);
-
-        // Reflector
-        let idx = (res as u8 - b'A') as usize;
-        res = self.reflector.wiring[idx];
-
-        // Backward through rotors (Left to Right: 0 -> 1 -> 2)
-        res = Self::rotor_backward(res, &self.rotors[0]);
-        res = Self::rotor_backward(res, &self.rotors[1]);
-        res = Self::rotor_backward(res, &self.rotors[2]);
-
-        res = Self::map_char(res, &self.plugboard);
-        
-        // Preserve original case? Enigma is case-insensitive (outputs uppercase).
-        // But for code, we might need to handle case.
-        // Standard Enigma only does A-Z.
-        // For a "Code Runner", we need to support all characters.
-        // Strategy: Only encrypt A-Z, leave others alone? 
-        // Or map full ASCII?
-        // The user said "Enigma", which implies A-Z. 
-        // But JS code has symbols. 
-        // Let's stick to A-Z encryption for the "Enigma" vibe, and leave symbols as is.
-        // This is a "wrapper" after all.
-        
-        if c.is_ascii_lowercase() {
-            res.to_ascii_lowercase()
-        } else {
-            res
+    // Byte-level counterpart of `process_char` for the classic alphabet. Only
+    // ASCII letters are transformed, so UTF-8 sequences pass through
+    // untouched. In extended mode non-ASCII bytes are returned as-is; decode
+    // the text and use `process_char` instead.
+    pub fn process_byte(&mut self, b: u8) -> u8 {
+        match b {
+            _ if self.alphabet == Alphabet::Extended => {
+                if b.is_ascii() { self.process_extended(b as char) as u8 } else { b }
+            }
+            b'A'..=b'Z' => self.press(b - b'A') + b'A',
+            b'a'..=b'z' => self.press(b - b'a') + b'a',
+            _ => b,
         }
     }
 
@@ -156,8 +650,8 @@ mod tests {
 
     #[test]
     fn test_symmetry() {
-        let mut enigma_enc = EnigmaMachine::new([(0, 'A', 'A'), (1, 'A', 'A'), (2, 'A', 'A')], 'B', "");
-        let mut enigma_dec = EnigmaMachine::new([(0, 'A', 'A'), (1, 'A', 'A'), (2, 'A', 'A')], 'B', "");
+        let mut enigma_enc = EnigmaMachine::new([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')], ReflectorType::B, "");
+        let mut enigma_dec = EnigmaMachine::new([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')], ReflectorType::B, "");
 
         let input = "HELLOWORLD";
         let encrypted = enigma_enc.process_text(input);
@@ -167,5 +661,320 @@ mod tests {
         assert_ne!(input, encrypted);
     }
 
+    #[test]
+    fn test_rotor_catalogue() {
+        for kind in RotorType::ALL {
+            let mut letters: Vec<char> = kind.wiring().chars().collect();
+            letters.sort();
+            assert_eq!(letters.iter().collect::<String>(), "ABCDEFGHIJKLMNOPQRSTUVWXYZ", "{kind}");
+            assert_eq!(kind.name().parse::<RotorType>(), Ok(kind));
+        }
+        assert_eq!("viii".parse::<RotorType>(), Ok(RotorType::VIII));
+        assert!("IX".parse::<RotorType>().is_err());
+    }
+
+    #[test]
+    fn test_high_rotor_indices() {
+        // Used to panic: only I-III were known
+        let mut enc = EnigmaMachine::new([(RotorType::VI, 'A', 'A'), (RotorType::VII, 'A', 'A'), (RotorType::VIII, 'A', 'A')], ReflectorType::B, "");
+        let mut dec = EnigmaMachine::new([(RotorType::VI, 'A', 'A'), (RotorType::VII, 'A', 'A'), (RotorType::VIII, 'A', 'A')], ReflectorType::B, "");
+        let encrypted = enc.process_text("DOUBLENOTCHROTORS");
+        assert_eq!(dec.process_text(&encrypted), "DOUBLENOTCHROTORS");
+    }
+
+    #[test]
+    fn test_double_notch_turnover() {
+        // VI on the right carries the middle rotor at both M and Z
+        let mut m = EnigmaMachine::new([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::VI, 'A', 'L')], ReflectorType::B, "");
+        m.process_text("AA");
+        assert_eq!(m.rotors[1].position, 1);
+        let mut m = EnigmaMachine::new([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::VI, 'A', 'Y')], ReflectorType::B, "");
+        m.process_text("AA");
+        assert_eq!(m.rotors[1].position, 1);
+    }
+
+    #[test]
+    fn test_reflectors_are_involutions() {
+        for kind in ReflectorType::ALL {
+            let wiring: Vec<char> = kind.wiring().chars().collect();
+            for (i, &c) in wiring.iter().enumerate() {
+                let back = wiring[(c as u8 - b'A') as usize];
+                assert_eq!(back as u8, b'A' + i as u8, "{kind}");
+                assert_ne!(c as u8, b'A' + i as u8, "{kind}");
+            }
+            assert_eq!(ReflectorType::try_from(kind.letter()), Ok(kind));
+        }
+    }
+
+    #[test]
+    fn test_unknown_reflector() {
+        assert_eq!(ReflectorType::try_from('D'), Err(EnigmaError::UnknownReflector('D')));
+        assert_eq!(ReflectorType::try_from('a'), Err(EnigmaError::UnknownReflector('a')));
+    }
+
+    #[test]
+    fn test_reflector_changes_output() {
+        let rotors = [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')];
+        let mut b = EnigmaMachine::new(rotors, ReflectorType::B, "");
+        let mut c = EnigmaMachine::new(rotors, ReflectorType::C, "");
+        let mut a = EnigmaMachine::new(rotors, ReflectorType::A, "");
+        assert_eq!(b.process_text("AAAAA"), "BDZGO");
+        assert_ne!(c.process_text("AAAAA"), "BDZGO");
+        assert_ne!(a.process_text("AAAAA"), "BDZGO");
+    }
+
+    #[test]
+    fn test_m4_compatible_with_m3() {
+        // Beta at A with the thin B reflector behaves exactly like UKW-B
+        let rotors = [(RotorType::I, 'C', 'K'), (RotorType::IV, 'F', 'Q'), (RotorType::VII, 'R', 'Z')];
+        let mut m3 = EnigmaMachine::new(rotors, ReflectorType::B, "AZ BY CX");
+        let mut m4 = EnigmaMachine::new_m4((RotorType::Beta, 'A', 'A'), rotors, ReflectorType::BThin, "AZ BY CX");
+        let text = "THEGREEKROTORISTRANSPARENTINTHISPOSITION";
+        assert_eq!(m3.process_text(text), m4.process_text(text));
+    }
+
+    #[test]
+    fn test_m4_message() {
+        // Kriegsmarine M4 message decrypted by Stefan Krah's M4 project (1942)
+        let mut m4 = EnigmaMachine::new_m4(
+            (RotorType::Beta, 'A', 'V'),
+            [(RotorType::II, 'A', 'J'), (RotorType::IV, 'A', 'N'), (RotorType::I, 'V', 'A')],
+            ReflectorType::BThin,
+            "AT BL DF GJ HM NW OP QY RZ VX",
+        );
+        let ciphertext = "NCZWVUSXPNYMINHZXMQXSFWXWLKJAHSHNMCOCCAKUQPMKCSMHKSEINJUSBLKIOSXCKUBHMLLXCSJUSRRDVKOHULXWCCBGVLIYXEOAHXRHKKFVDREWEZLXOBAFGYUJQUKGRTVUKAMEURBVEKSUHHVOYHABCJWMAKLFKLMYFVNRIZRVVRTKOFDANJMOLBGFFLEOPRGTFLVRHOWOPBEKVWMUQFMPWPARMFHAGKXIIBG";
+        let plaintext = "VONVONJLOOKSJHFFTTTEINSEINSDREIZWOYYQNNSNEUNINHALTXXBEIANGRIFFUNTERWASSERGEDRUECKTYWABOSXLETZTERGEGNERSTANDNULACHTDREINULUHRMARQUANTONJOTANEUNACHTSEYHSDREIYZWOZWONULGRADYACHTSMYSTOSSENACHXEKNSVIERMBFAELLTYNNNNNNOOOVIERYSICHTEINSNULL";
+        assert_eq!(m4.process_text(ciphertext), plaintext);
+    }
+
+    #[test]
+    fn test_try_new_validation() {
+        let ok = [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')];
+        let b = ReflectorType::B;
+        assert!(EnigmaMachine::try_new(ok, b, "AB CD").is_ok());
+
+        let dup = [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::I, 'A', 'A')];
+        assert_eq!(EnigmaMachine::try_new(dup, b, "").err(), Some(EnigmaError::DuplicateRotor(RotorType::I)));
+
+        let greek = [(RotorType::Beta, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')];
+        assert_eq!(EnigmaMachine::try_new(greek, b, "").err(), Some(EnigmaError::InvalidRotor(RotorType::Beta)));
+
+        let ring = [(RotorType::I, '1', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')];
+        assert_eq!(EnigmaMachine::try_new(ring, b, "").err(), Some(EnigmaError::InvalidRing('1')));
+
+        let pos = [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'Ä'), (RotorType::III, 'A', 'A')];
+        assert_eq!(EnigmaMachine::try_new(pos, b, "").err(), Some(EnigmaError::InvalidPosition('Ä')));
+
+        assert_eq!(EnigmaMachine::try_new(ok, b, "AA").err(), Some(EnigmaError::SelfPair('A')));
+        assert_eq!(EnigmaMachine::try_new(ok, b, "AB CA").err(), Some(EnigmaError::ReusedPlug('A')));
+        assert_eq!(EnigmaMachine::try_new(ok, b, "ABC").err(), Some(EnigmaError::MalformedPlug("ABC".into())));
+        assert_eq!(EnigmaMachine::try_new(ok, b, "A1").err(), Some(EnigmaError::MalformedPlug("A1".into())));
+        assert_eq!(
+            EnigmaMachine::try_new(ok, ReflectorType::BThin, "").err(),
+            Some(EnigmaError::ReflectorMismatch(ReflectorType::BThin))
+        );
+    }
+
+    #[test]
+    fn test_try_new_m4_validation() {
+        let ok = [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')];
+        assert!(EnigmaMachine::try_new_m4((RotorType::Gamma, 'A', 'A'), ok, ReflectorType::CThin, "").is_ok());
+        assert_eq!(
+            EnigmaMachine::try_new_m4((RotorType::IV, 'A', 'A'), ok, ReflectorType::BThin, "").err(),
+            Some(EnigmaError::InvalidRotor(RotorType::IV))
+        );
+        assert_eq!(
+            EnigmaMachine::try_new_m4((RotorType::Beta, 'A', 'A'), ok, ReflectorType::B, "").err(),
+            Some(EnigmaError::ReflectorMismatch(ReflectorType::B))
+        );
+    }
+
+    #[test]
+    fn test_lowercase_settings() {
+        let upper = [(RotorType::I, 'B', 'X'), (RotorType::II, 'C', 'Y'), (RotorType::III, 'D', 'Z')];
+        let lower = [(RotorType::I, 'b', 'x'), (RotorType::II, 'c', 'y'), (RotorType::III, 'd', 'z')];
+        let mut a = EnigmaMachine::try_new(upper, ReflectorType::B, "AQ PL").unwrap();
+        let mut b = EnigmaMachine::try_new(lower, ReflectorType::B, "aq pl").unwrap();
+        assert_eq!(a.process_text("LOWERCASESETTINGS"), b.process_text("LOWERCASESETTINGS"));
+    }
+
+    fn barbarossa(start: char, start2: char, start3: char) -> EnigmaMachine {
+        EnigmaMachine::new(
+            [(RotorType::II, 'B', start), (RotorType::IV, 'U', start2), (RotorType::V, 'L', start3)],
+            ReflectorType::B,
+            "AV BS CG DL FU HZ IN KM OW RX",
+        )
+    }
+
+    #[test]
+    fn test_double_step_sequence() {
+        let mut m = EnigmaMachine::new(
+            [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'D'), (RotorType::III, 'A', 'U')],
+            ReflectorType::B,
+            "",
+        );
+        let mut seen = vec![m.window()];
+        for _ in 0..3 {
+            m.process_char('A');
+            seen.push(m.window());
+        }
+        assert_eq!(seen, ["ADU", "ADV", "AEW", "BFX"]);
+    }
+
+    #[test]
+    fn test_non_letters_do_not_step() {
+        let mut m = EnigmaMachine::new_m4(
+            (RotorType::Gamma, 'A', 'Q'),
+            [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')],
+            ReflectorType::CThin,
+            "",
+        );
+        m.process_text("12 -- !?");
+        assert_eq!(m.window(), "QAAA");
+        m.process_text("ab");
+        assert_eq!(m.window(), "QAAC");
+    }
+
+    #[test]
+    fn test_barbarossa_part_one() {
+        // Operation Barbarossa, 7 July 1941, message key BLA
+        let mut m = barbarossa('B', 'L', 'A');
+        let ciphertext = "EDPUDNRGYSZRCXNUYTPOMRMBOFKTBZREZKMLXLVEFGUEYSIOZVEQMIKUBPMMYLKLTTDEISMDICAGYKUACTCDOMOHWXMUUIAUBSTSLRNBZSZWNRFXWFYSSXJZVIJHIDISHPRKLKAYUPADTXQSPINQMATLPIFSVKDASCTACDPBOPVHJK";
+        let plaintext = "AUFKLXABTEILUNGXVONXKURTINOWAXKURTINOWAXNORDWESTLXSEBEZXSEBEZXUAFFLIEGERSTRASZERIQTUNGXDUBROWKIXDUBROWKIXOPOTSCHKAXOPOTSCHKAXUMXEINSAQTDREINULLXUHRANGETRETENXANGRIFFXINFXRGTX";
+        assert_eq!(m.process_text(ciphertext), plaintext);
+    }
 
+    #[test]
+    fn test_barbarossa_part_two() {
+        // Second part of the same message, message key LSD
+        let mut m = barbarossa('L', 'S', 'D');
+        let ciphertext = "SFBWDNJUSEGQOBHKRTAREEZMWKPPRBXOHDROEQGBBGTQVPGVKBVVGBIMHUSZYDAJQIROAXSSSNREHYGGRPISEZBOVMQIEMMZCYSGQDGRERVBILEKXYQIRGIRQNRDNVRXCYYTNJR";
+        let plaintext = "DREIGEHTLANGSAMABERSIQERVORWAERTSXEINSSIEBENNULLSEQSXUHRXROEMXEINSXINFRGTXDREIXAUFFLIEGERSTRASZEMITANFANGXEINSSEQSXKMXKMXOSTWXKAMENECXK";
+        assert_eq!(m.process_text(ciphertext), plaintext);
+    }
+
+    #[test]
+    fn test_state_restore() {
+        let mut m = barbarossa('B', 'L', 'A');
+        let start = m.state();
+        assert_eq!(start.window(), "BLA");
+        let first = m.process_text("SNAPSHOTS");
+        let after = m.state();
+        m.restore(start).unwrap();
+        assert_eq!(m.process_text("SNAPSHOTS"), first);
+        assert_eq!(m.state(), after);
+    }
+
+    #[test]
+    fn test_restore_across_alphabets() {
+        let mut extended = barbarossa('B', 'L', 'A').with_alphabet(Alphabet::Extended);
+        extended.advance(85);
+        let beyond_z = extended.state();
+        let mut classic = barbarossa('B', 'L', 'A');
+        let before = classic.state();
+        assert!(matches!(classic.restore(beyond_z), Err(EnigmaError::InvalidPosition(_))));
+        assert_eq!(classic.state(), before);
+
+        // Positions that exist in both are fine either way
+        let start = barbarossa('Q', 'E', 'V').state();
+        extended.restore(start).unwrap();
+        assert_eq!(extended.state(), start);
+        classic.restore(start).unwrap();
+        assert_eq!(classic.state(), start);
+    }
+
+    #[test]
+    fn test_advance_matches_typing() {
+        let mut typed = barbarossa('Q', 'E', 'V');
+        let mut skipped = typed.clone();
+        for n in [0u64, 1, 7, 26, 700, 17_000, 50_000] {
+            for _ in 0..n {
+                typed.process_char('X');
+            }
+            skipped.advance(n);
+            assert_eq!(typed.state(), skipped.state(), "after {n}");
+        }
+    }
+
+    #[test]
+    fn test_advance_large_offset() {
+        let mut a = barbarossa('A', 'A', 'A');
+        let mut b = a.clone();
+        a.advance(10_000_000_000);
+        let start = b.state();
+        b.advance(10_000_000_000 % 16_900);
+        // Single-notch rotors repeat every 26 * 25 * 26 keypresses
+        assert_eq!(a.state(), b.state());
+        b.seek(start, 16_900).unwrap();
+        assert_eq!(b.state(), start);
+    }
+
+    #[test]
+    fn test_seek_decrypts_middle_chunk() {
+        let plaintext = "function init() { return 'Sans gives you a bad time'; } // idle timer";
+        let mut enc = barbarossa('D', 'O', 'G');
+        let start = enc.state();
+        let ciphertext = enc.process_text(plaintext);
+
+        let (chunk_start, chunk_end) = (20, 45);
+        let mut dec = barbarossa('A', 'A', 'A');
+        dec.seek(start, dec.keypresses(&ciphertext[..chunk_start])).unwrap();
+        assert_eq!(dec.process_text(&ciphertext[chunk_start..chunk_end]), &plaintext[chunk_start..chunk_end]);
+    }
+
+    const INIT_JS: &str = "(() => {\n\tconst t = setInterval(() => { try { location.reload(); } catch (_) {} }, 10 * 60 * 1000);\n  // Ünïcödé → 😀\r\n})();";
+
+    #[test]
+    fn test_extended_round_trip() {
+        let mut enc = barbarossa('B', 'L', 'A').with_alphabet(Alphabet::Extended);
+        let mut dec = barbarossa('B', 'L', 'A').with_alphabet(Alphabet::Extended);
+        let encrypted = enc.process_text(INIT_JS);
+        assert_eq!(dec.process_text(&encrypted), INIT_JS);
+        assert_eq!(enc.state(), dec.state());
+    }
+
+    #[test]
+    fn test_extended_hides_structure() {
+        let mut m = barbarossa('B', 'L', 'A').with_alphabet(Alphabet::Extended);
+        let encrypted = m.process_text(INIT_JS);
+        assert_eq!(encrypted.chars().count(), INIT_JS.chars().count());
+        assert!(!encrypted.contains("(() => {"));
+        assert!(!encrypted.contains("1000"));
+        // Like the classic machine, nothing ever enciphers to itself
+        for (a, b) in INIT_JS.chars().zip(encrypted.chars()) {
+            assert_ne!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_extended_passes_ascii_controls() {
+        let mut m = barbarossa('A', 'A', 'A').with_alphabet(Alphabet::Extended);
+        assert_eq!(m.process_text("\0\x07\x7f"), "\0\x07\x7f");
+        assert_eq!(m.window(), "AAA");
+        assert_eq!(m.keypresses("a\0é "), 3);
+    }
+
+    #[test]
+    fn test_extended_positions_wrap_past_z() {
+        let mut m = EnigmaMachine::new(
+            [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'Y')],
+            ReflectorType::B,
+            "",
+        )
+        .with_alphabet(Alphabet::Extended);
+        m.process_text("xyz");
+        assert_eq!(m.window(), "AAb");
+        let mut skipped = m.clone();
+        m.process_text(&"-".repeat(500));
+        skipped.advance(500);
+        assert_eq!(m.state(), skipped.state());
+    }
+
+    #[test]
+    fn test_classic_is_default() {
+        let m = barbarossa('A', 'A', 'A');
+        assert_eq!(m.alphabet(), Alphabet::Classic);
+        let mut classic = barbarossa('A', 'A', 'A').with_alphabet(Alphabet::Classic);
+        assert_eq!(classic.process_text("a-B c"), barbarossa('A', 'A', 'A').process_text("a-B c"));
+    }
 }
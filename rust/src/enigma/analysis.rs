@@ -0,0 +1,478 @@
+// Ciphertext-only attack on the three-rotor Enigma, following Gillogly
+// ("Ciphertext-only Cryptanalysis of Enigma", 1995) with Weierud's n-gram
+// hill-climbing for the plugboard:
+//
+// 1. try every rotor order and start position with rings at AAA and keep the
+//    settings whose decrypt has the highest index of coincidence,
+// 2. hill-climb the plugboard, first on the index of coincidence and then on
+//    English trigram fitness,
+// 3. find the right and middle ring settings, moving the start position along
+//    so the rotor cores stay where step 1 found them, and climb the plugboard
+//    once more from there.
+//
+// Everything runs offline against the n-gram tables bundled in `data/`.
+
+use super::{EnigmaError, EnigmaMachine, EnigmaState, Plugboard, ReflectorType, RotorType};
+
+const BIGRAMS: &str = include_str!("data/english_bigrams.txt");
+const TRIGRAMS: &str = include_str!("data/english_trigrams.txt");
+
+pub fn index_of_coincidence(text: &str) -> f64 {
+    let mut counts = [0u64; 26];
+    for c in text.chars().filter(|c| c.is_ascii_alphabetic()) {
+        counts[(c.to_ascii_uppercase() as u8 - b'A') as usize] += 1;
+    }
+    ioc_from_counts(&counts)
+}
+
+fn ioc_from_counts(counts: &[u64; 26]) -> f64 {
+    let n: u64 = counts.iter().sum();
+    if n < 2 {
+        return 0.0;
+    }
+    let pairs: u64 = counts.iter().map(|&c| c * c.saturating_sub(1)).sum();
+    pairs as f64 / (n * (n - 1)) as f64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NgramError {
+    UnsupportedN(usize),
+    // Lines are counted from 1
+    MissingCount(usize),
+    BadNgram { line: usize, ngram: String },
+    BadCount { line: usize, count: String },
+    Empty,
+    // The counts add up to more than a u64 holds
+    Overflow,
+}
+
+impl std::fmt::Display for NgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NgramError::UnsupportedN(n) => write!(f, "{n}-grams are not supported, only 1 to 4"),
+            NgramError::MissingCount(line) => write!(f, "line {line}: expected `NGRAM COUNT`"),
+            NgramError::BadNgram { line, ngram } => write!(f, "line {line}: bad n-gram {ngram:?}"),
+            NgramError::BadCount { line, count } => write!(f, "line {line}: bad count {count:?}"),
+            NgramError::Empty => write!(f, "empty n-gram table"),
+            NgramError::Overflow => write!(f, "n-gram counts add up to more than 2^64"),
+        }
+    }
+}
+
+impl std::error::Error for NgramError {}
+
+// Log10 probabilities of every n-letter sequence; unseen ones get a floor
+// well below the rarest observed one.
+pub struct NgramModel {
+    n: usize,
+    log_probs: Vec<f64>,
+}
+
+impl NgramModel {
+    pub fn english_bigrams() -> Self {
+        Self::parse(2, BIGRAMS).expect("bundled bigram table")
+    }
+
+    pub fn english_trigrams() -> Self {
+        Self::parse(3, TRIGRAMS).expect("bundled trigram table")
+    }
+
+    // One `NGRAM COUNT` pair per line, e.g. `THE 13296`.
+    pub fn parse(n: usize, table: &str) -> Result<Self, NgramError> {
+        if !(1..=4).contains(&n) {
+            return Err(NgramError::UnsupportedN(n));
+        }
+        let mut counts = vec![0u64; 26usize.pow(n as u32)];
+        for (lineno, line) in table.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_no = lineno + 1;
+            let (gram, count) = line.split_once(char::is_whitespace).ok_or(NgramError::MissingCount(line_no))?;
+            let idx = Some(gram).filter(|g| g.len() == n).and_then(|g| ngram_index(g.as_bytes()));
+            let idx = idx.ok_or_else(|| NgramError::BadNgram { line: line_no, ngram: gram.to_string() })?;
+            let count = count.trim();
+            counts[idx] = count.parse().map_err(|_| NgramError::BadCount { line: line_no, count: count.to_string() })?;
+        }
+        let total = counts.iter().try_fold(0u64, |sum, &c| sum.checked_add(c)).ok_or(NgramError::Overflow)?;
+        if total == 0 {
+            return Err(NgramError::Empty);
+        }
+        let floor = (0.01 / total as f64).log10();
+        let log_probs = counts
+            .iter()
+            .map(|&c| if c == 0 { floor } else { (c as f64 / total as f64).log10() })
+            .collect();
+        Ok(NgramModel { n, log_probs })
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    // Higher is more English-like. Non-letters are ignored.
+    pub fn score(&self, text: &str) -> f64 {
+        let letters: Vec<u8> = text.bytes().filter(u8::is_ascii_alphabetic).map(|b| b.to_ascii_uppercase()).collect();
+        self.score_letters(&letters)
+    }
+
+    fn score_letters(&self, letters: &[u8]) -> f64 {
+        letters
+            .windows(self.n)
+            .map(|w| self.log_probs[ngram_index(w).expect("upper-case letters")])
+            .sum()
+    }
+}
+
+fn ngram_index(gram: &[u8]) -> Option<usize> {
+    gram.iter().try_fold(0, |acc, &b| {
+        b.is_ascii_uppercase().then(|| acc * 26 + (b - b'A') as usize)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub rotors: [RotorType; 3],
+    pub rings: [char; 3],
+    pub positions: [char; 3],
+    pub reflector: ReflectorType,
+    pub plugboard: String,
+    pub score: f64,
+}
+
+impl Candidate {
+    pub fn machine(&self) -> Result<EnigmaMachine, EnigmaError> {
+        let settings = [0, 1, 2].map(|i| (self.rotors[i], self.rings[i], self.positions[i]));
+        EnigmaMachine::try_new(settings, self.reflector, &self.plugboard)
+    }
+
+    pub fn decrypt(&self, ciphertext: &str) -> Result<String, EnigmaError> {
+        Ok(self.machine()?.process_text(ciphertext))
+    }
+
+    // The same settings with upper-case letters and the plugboard as sorted
+    // pairs, which is what the search steps work on. Fails if the machine
+    // can't be built or the plugboard doesn't swap in pairs.
+    fn checked(&self) -> Result<Candidate, EnigmaError> {
+        self.machine()?;
+        let plugboard = Plugboard::parse(&self.plugboard)?;
+        if !plugboard.is_reciprocal() {
+            return Err(EnigmaError::MalformedPlug(self.plugboard.clone()));
+        }
+        Ok(Candidate {
+            rings: self.rings.map(|c| c.to_ascii_uppercase()),
+            positions: self.positions.map(|c| c.to_ascii_uppercase()),
+            plugboard: plugboard.pairs().iter().map(|&(a, b)| format!("{a}{b}")).collect::<Vec<_>>().join(" "),
+            ..self.clone()
+        })
+    }
+
+    // Only for candidates built from checked ones
+    fn checked_machine(&self) -> EnigmaMachine {
+        self.machine().expect("checked candidate")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttackConfig {
+    // Rotors to draw the three-rotor order from, e.g. I-V for the Wehrmacht
+    pub rotor_pool: Vec<RotorType>,
+    pub reflector: ReflectorType,
+    // How many rotor order/position candidates survive step 1
+    pub keep: usize,
+    pub max_plugs: usize,
+}
+
+impl Default for AttackConfig {
+    fn default() -> Self {
+        AttackConfig {
+            rotor_pool: vec![RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V],
+            reflector: ReflectorType::B,
+            keep: 10,
+            max_plugs: 10,
+        }
+    }
+}
+
+// Runs the whole attack and returns candidates best first.
+pub fn attack(ciphertext: &str, config: &AttackConfig) -> Vec<Candidate> {
+    let trigrams = NgramModel::english_trigrams();
+    // Step 1 only puts out valid candidates, and the later steps keep them so
+    let mut ranked: Vec<Candidate> = search_rotor_orders(ciphertext, &config.rotor_pool, config.reflector, config.keep)
+        .into_iter()
+        .map(|c| climb_checked(ciphertext, c, &trigrams, config.max_plugs))
+        .map(|c| search_rings_checked(ciphertext, c, &trigrams))
+        .map(|c| climb_checked(ciphertext, c, &trigrams, config.max_plugs))
+        .collect();
+    sort_by_score(&mut ranked);
+    ranked
+}
+
+// Step 1: every order of three distinct rotors from `pool` and every start
+// position, rings at AAA, no plugs. Scored by index of coincidence.
+pub fn search_rotor_orders(ciphertext: &str, pool: &[RotorType], reflector: ReflectorType, keep: usize) -> Vec<Candidate> {
+    let letters = letters_of(ciphertext);
+    let mut best: Vec<Candidate> = Vec::new();
+    if keep == 0 {
+        return best;
+    }
+    for &left in pool {
+        for &middle in pool {
+            for &right in pool {
+                if left == middle || middle == right || left == right {
+                    continue;
+                }
+                let Ok(mut machine) =
+                    EnigmaMachine::try_new([(left, 'A', 'A'), (middle, 'A', 'A'), (right, 'A', 'A')], reflector, "")
+                else {
+                    continue;
+                };
+                for start in 0..26 * 26 * 26 {
+                    let positions = [(start / 676) as u8, (start / 26 % 26) as u8, (start % 26) as u8];
+                    machine.restore(EnigmaState { greek: None, positions }).expect("classic positions");
+                    let score = ioc_of_decrypt(&mut machine, &letters);
+                    if best.len() < keep || best.last().is_some_and(|worst| score > worst.score) {
+                        best.push(Candidate {
+                            rotors: [left, middle, right],
+                            rings: ['A'; 3],
+                            positions: positions.map(|p| (p + b'A') as char),
+                            reflector,
+                            plugboard: String::new(),
+                            score,
+                        });
+                        sort_by_score(&mut best);
+                        best.truncate(keep);
+                    }
+                }
+            }
+        }
+    }
+    best
+}
+
+// Step 3: turning a ring and the start position of the same rotor together
+// keeps its core in place and only moves the turnover point. The right ring
+// decides when the middle rotor steps and the middle ring when the left one
+// does; the left ring never matters. A wrong turnover only garbles a few
+// letters per rotor revolution, which the index of coincidence barely notices,
+// so this is scored on n-gram fitness with the plugboard found so far.
+pub fn search_rings(ciphertext: &str, candidate: &Candidate, model: &NgramModel) -> Result<Candidate, EnigmaError> {
+    Ok(search_rings_checked(ciphertext, candidate.checked()?, model))
+}
+
+fn search_rings_checked(ciphertext: &str, candidate: Candidate, model: &NgramModel) -> Candidate {
+    let letters = letters_of(ciphertext);
+    let mut best = candidate;
+    best.score = fitness_of_decrypt(&mut best.checked_machine(), &letters, model);
+    for slot in [2, 1] {
+        let base = best.clone();
+        for shift in 1..26u8 {
+            // Step 1 may have put the rotor to the left one position off to
+            // make up for the wrong turnover over most of the text.
+            for neighbour in [0, 1, 25] {
+                let mut trial = base.clone();
+                trial.rings[slot] = shift_letter(base.rings[slot], shift);
+                trial.positions[slot] = shift_letter(base.positions[slot], shift);
+                trial.positions[slot - 1] = shift_letter(base.positions[slot - 1], neighbour);
+                trial.score = fitness_of_decrypt(&mut trial.checked_machine(), &letters, model);
+                if trial.score > best.score {
+                    best = trial;
+                }
+            }
+        }
+    }
+    best
+}
+
+// Step 2: greedy plugboard hill-climb. Each round tries every new pair between
+// two free letters and every removal, and keeps the single best change. The
+// index of coincidence gets the first few plugs in, where trigrams are still
+// too noisy; the trigram model takes over once it stops improving.
+pub fn hill_climb_plugboard(
+    ciphertext: &str,
+    candidate: &Candidate,
+    model: &NgramModel,
+    max_plugs: usize,
+) -> Result<Candidate, EnigmaError> {
+    Ok(climb_checked(ciphertext, candidate.checked()?, model, max_plugs))
+}
+
+fn climb_checked(ciphertext: &str, candidate: Candidate, model: &NgramModel, max_plugs: usize) -> Candidate {
+    let letters = letters_of(ciphertext);
+    let ioc = |c: &Candidate| ioc_of_decrypt(&mut c.checked_machine(), &letters);
+    let fitness = |c: &Candidate| fitness_of_decrypt(&mut c.checked_machine(), &letters, model);
+    let best = climb(candidate, max_plugs, &ioc);
+    climb(best, max_plugs, &fitness)
+}
+
+fn climb(mut best: Candidate, max_plugs: usize, score: &dyn Fn(&Candidate) -> f64) -> Candidate {
+    best.score = score(&best);
+    loop {
+        let pairs: Vec<(u8, u8)> = best
+            .plugboard
+            .split_whitespace()
+            .map(|p| (p.as_bytes()[0], p.as_bytes()[1]))
+            .collect();
+        let mut used = [false; 26];
+        for &(a, b) in &pairs {
+            used[(a - b'A') as usize] = true;
+            used[(b - b'A') as usize] = true;
+        }
+
+        let mut trials = Vec::new();
+        if pairs.len() < max_plugs {
+            for a in 0..26u8 {
+                for b in a + 1..26 {
+                    if !used[a as usize] && !used[b as usize] {
+                        let mut next = pairs.clone();
+                        next.push((a + b'A', b + b'A'));
+                        trials.push(next);
+                    }
+                }
+            }
+        }
+        for i in 0..pairs.len() {
+            let mut next = pairs.clone();
+            next.remove(i);
+            trials.push(next);
+        }
+
+        let mut improved = None;
+        for trial in trials {
+            let mut cand = best.clone();
+            cand.plugboard = format_plugs(&trial);
+            cand.score = score(&cand);
+            if cand.score > improved.as_ref().map_or(best.score, |c: &Candidate| c.score) {
+                improved = Some(cand);
+            }
+        }
+        match improved {
+            Some(c) => best = c,
+            None => return best,
+        }
+    }
+}
+
+fn format_plugs(pairs: &[(u8, u8)]) -> String {
+    pairs.iter().map(|&(a, b)| format!("{}{}", a as char, b as char)).collect::<Vec<_>>().join(" ")
+}
+
+fn ioc_of_decrypt(machine: &mut EnigmaMachine, letters: &[u8]) -> f64 {
+    let mut counts = [0u64; 26];
+    for &b in letters {
+        counts[(machine.process_byte(b) - b'A') as usize] += 1;
+    }
+    ioc_from_counts(&counts)
+}
+
+fn fitness_of_decrypt(machine: &mut EnigmaMachine, letters: &[u8], model: &NgramModel) -> f64 {
+    let plain: Vec<u8> = letters.iter().map(|&b| machine.process_byte(b)).collect();
+    model.score_letters(&plain)
+}
+
+fn letters_of(text: &str) -> Vec<u8> {
+    text.bytes().filter(u8::is_ascii_alphabetic).map(|b| b.to_ascii_uppercase()).collect()
+}
+
+fn shift_letter(c: char, by: u8) -> char {
+    ((c as u8 - b'A' + by) % 26 + b'A') as char
+}
+
+fn sort_by_score(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &str = "THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG WHILE THE OPERATORS OF THE \
+        MACHINE SET THE ROTORS EACH MORNING FROM THE KEY SHEET AND THE CODEBREAKERS AT THE PARK \
+        TRIED EVERY ORDER OF THE WHEELS UNTIL THE TEXT STARTED TO LOOK LIKE ENGLISH AGAIN WHICH \
+        IS EXACTLY WHAT THIS SEARCH DOES WITH THE HELP OF THE INDEX OF COINCIDENCE AND A TABLE \
+        OF TRIGRAMS THAT WAS COUNTED FROM ORDINARY PROSE";
+
+    fn plain() -> String {
+        letters_of(PLAINTEXT).iter().map(|&b| b as char).collect()
+    }
+
+    fn encrypt(settings: [(RotorType, char, char); 3], plugs: &str) -> String {
+        EnigmaMachine::new(settings, ReflectorType::B, plugs).process_text(&plain())
+    }
+
+    #[test]
+    fn test_index_of_coincidence() {
+        assert!((index_of_coincidence("AAAA") - 1.0).abs() < 1e-9);
+        assert_eq!(index_of_coincidence("ABCDEFGHIJKLMNOPQRSTUVWXYZ"), 0.0);
+        assert!(index_of_coincidence(PLAINTEXT) > 0.055);
+        let ciphertext = encrypt([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')], "");
+        assert!(index_of_coincidence(&ciphertext) < 0.05);
+    }
+
+    #[test]
+    fn test_ngram_fitness_prefers_english() {
+        let model = NgramModel::english_trigrams();
+        let ciphertext = encrypt([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')], "");
+        assert!(model.score(PLAINTEXT) > model.score(&ciphertext));
+        let bigrams = NgramModel::english_bigrams();
+        assert!(bigrams.score("THEREISNOPLACE") > bigrams.score("QXZJVKQWZXJQVK"));
+    }
+
+    #[test]
+    fn test_ngram_table_errors() {
+        assert!(NgramModel::parse(2, "TH 10\nHE ten\n").is_err());
+        assert!(NgramModel::parse(2, "THE 10\n").is_err());
+        assert!(NgramModel::parse(2, "").is_err());
+        assert!(NgramModel::parse(2, "THEREISNOPLACELIKEHOME 1\n").is_err());
+        assert_eq!(NgramModel::parse(0, "TH 1\n").err(), Some(NgramError::UnsupportedN(0)));
+        let max = u64::MAX;
+        assert_eq!(NgramModel::parse(2, &format!("TH {max}\nHE {max}\n")).err(), Some(NgramError::Overflow));
+        assert_eq!(
+            NgramModel::parse(2, "TH 10\nHE ten\n").err().map(|e| e.to_string()),
+            Some("line 2: bad count \"ten\"".into())
+        );
+        assert_eq!(NgramModel::parse(2, "TH 3\n\nHE 1\n").unwrap().n(), 2);
+    }
+
+    #[test]
+    fn test_attack_recovers_settings() {
+        let ciphertext = encrypt([(RotorType::II, 'A', 'K'), (RotorType::III, 'A', 'D'), (RotorType::I, 'F', 'S')], "AR GK OX");
+        let config = AttackConfig {
+            rotor_pool: vec![RotorType::I, RotorType::II, RotorType::III],
+            keep: 3,
+            ..AttackConfig::default()
+        };
+        let ranked = attack(&ciphertext, &config);
+        let best = &ranked[0];
+        assert_eq!(best.rotors, [RotorType::II, RotorType::III, RotorType::I]);
+        assert_eq!(best.decrypt(&ciphertext).unwrap(), plain());
+        assert!(ranked.windows(2).all(|w| w[0].score >= w[1].score));
+    }
+
+    #[test]
+    fn test_bad_input_is_an_error() {
+        let ciphertext = encrypt([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')], "");
+        assert!(search_rotor_orders(&ciphertext, &[RotorType::I, RotorType::II, RotorType::III], ReflectorType::B, 0).is_empty());
+
+        let model = NgramModel::english_trigrams();
+        let candidate = Candidate {
+            rotors: [RotorType::I, RotorType::II, RotorType::III],
+            rings: ['A'; 3],
+            positions: ['A'; 3],
+            reflector: ReflectorType::B,
+            plugboard: "A1".into(),
+            score: 0.0,
+        };
+        assert!(hill_climb_plugboard(&ciphertext, &candidate, &model, 2).is_err());
+        assert!(candidate.decrypt(&ciphertext).is_err());
+        let bad_ring = Candidate { rings: ['A', '!', 'A'], plugboard: String::new(), ..candidate.clone() };
+        assert!(search_rings(&ciphertext, &bad_ring, &model).is_err());
+
+        // Lower case is fine and comes back upper case
+        let lower = Candidate { rings: ['a'; 3], positions: ['a'; 3], plugboard: "xy".into(), ..candidate };
+        let climbed = hill_climb_plugboard(&ciphertext, &lower, &model, 1).unwrap();
+        assert_eq!((climbed.rings, climbed.positions), (['A'; 3], ['A'; 3]));
+        assert!(climbed.plugboard.len() <= 2 && climbed.plugboard.chars().all(|c| c.is_ascii_uppercase()));
+    }
+}
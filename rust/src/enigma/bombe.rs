@@ -0,0 +1,322 @@
+// Turing-Welchman bombe. A crib (guessed plaintext) laid against the
+// ciphertext gives one menu edge per letter: at keypress i the plugboard maps
+// crib letter P_i and cipher letter C_i through the same scrambler S_i, so
+// stecker(C_i) = S_i(stecker(P_i)).
+//
+// For every rotor order and start position the bombe assumes a stecker
+// partner for the menu's best-connected letter and follows the edges. A
+// letter that needs two different partners, or two letters that claim the
+// same one, rules the assumption out; the diagonal board adds the reverse
+// pairing for every deduction. Whatever survives is a stop, reported with the
+// plugboard pairs it implies.
+//
+// Scramblers come from `EnigmaMachine` itself, so stepping, double-stepping
+// and ring settings behave exactly as in the app.
+
+use super::{EnigmaMachine, EnigmaState, ReflectorType, RotorType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BombeError {
+    EmptyCrib,
+    CribTooLong,
+    NonLetter(char),
+    // Enigma never enciphers a letter to itself, so a crib that lines up a
+    // letter with itself is in the wrong place
+    CribClash { index: usize, letter: char },
+}
+
+impl std::fmt::Display for BombeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BombeError::EmptyCrib => write!(f, "crib is empty"),
+            BombeError::CribTooLong => write!(f, "crib runs past the end of the ciphertext"),
+            BombeError::NonLetter(c) => write!(f, "crib contains non-letter {c:?}"),
+            BombeError::CribClash { index, letter } => {
+                write!(f, "crib letter {letter} at keypress {index} would encipher to itself")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BombeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Menu {
+    // Keypress index of the first crib letter
+    offset: usize,
+    // (crib letter, cipher letter) per keypress, as 0-25
+    edges: Vec<(u8, u8)>,
+}
+
+impl Menu {
+    // `offset` counts letters of the ciphertext; anything else is skipped.
+    pub fn new(ciphertext: &str, crib: &str, offset: usize) -> Result<Self, BombeError> {
+        let cipher: Vec<u8> = letters(ciphertext);
+        if let Some(c) = crib.chars().find(|c| !c.is_ascii_alphabetic()) {
+            return Err(BombeError::NonLetter(c));
+        }
+        let crib = letters(crib);
+        if crib.is_empty() {
+            return Err(BombeError::EmptyCrib);
+        }
+        if offset + crib.len() > cipher.len() {
+            return Err(BombeError::CribTooLong);
+        }
+        let mut edges = Vec::with_capacity(crib.len());
+        for (i, (&p, &c)) in crib.iter().zip(&cipher[offset..]).enumerate() {
+            if p == c {
+                return Err(BombeError::CribClash { index: offset + i, letter: (p + b'A') as char });
+            }
+            edges.push((p, c));
+        }
+        Ok(Menu { offset, edges })
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    // Independent cycles in the menu graph. Each closure is what makes a
+    // wrong stop unlikely; menus with none produce stops everywhere.
+    pub fn closures(&self) -> usize {
+        let mut parent: Vec<usize> = (0..26).collect();
+        fn find(parent: &mut [usize], x: usize) -> usize {
+            let mut x = x;
+            while parent[x] != x {
+                parent[x] = parent[parent[x]];
+                x = parent[x];
+            }
+            x
+        }
+        let mut closures = 0;
+        for &(p, c) in &self.edges {
+            let (a, b) = (find(&mut parent, p as usize), find(&mut parent, c as usize));
+            if a == b {
+                closures += 1;
+            } else {
+                parent[a] = b;
+            }
+        }
+        closures
+    }
+
+    // The letter on the most edges; the bombe's test register sits here.
+    fn test_letter(&self) -> u8 {
+        let mut degree = [0usize; 26];
+        for &(p, c) in &self.edges {
+            degree[p as usize] += 1;
+            degree[c as usize] += 1;
+        }
+        (0..26u8).max_by_key(|&l| (degree[l as usize], std::cmp::Reverse(l))).expect("26 letters")
+    }
+}
+
+// Crib offsets that don't line any letter up with itself.
+pub fn crib_offsets(ciphertext: &str, crib: &str) -> Vec<usize> {
+    let cipher = letters(ciphertext);
+    let crib = letters(crib);
+    if crib.is_empty() || crib.len() > cipher.len() {
+        return Vec::new();
+    }
+    (0..=cipher.len() - crib.len())
+        .filter(|&o| crib.iter().zip(&cipher[o..]).all(|(p, c)| p != c))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stop {
+    pub rotors: [RotorType; 3],
+    // Window letters at the start of the message
+    pub positions: [char; 3],
+    // Plugboard pairs implied by the menu, self-steckered letters left out
+    pub steckers: Vec<(char, char)>,
+}
+
+impl Stop {
+    pub fn plugboard(&self) -> String {
+        self.steckers.iter().map(|&(a, b)| format!("{a}{b}")).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bombe {
+    pub reflector: ReflectorType,
+    // Real bombes were run for fixed ring settings too; ZZZ in the UK, here
+    // AAA unless told otherwise.
+    pub rings: [char; 3],
+}
+
+impl Default for Bombe {
+    fn default() -> Self {
+        Bombe { reflector: ReflectorType::B, rings: ['A'; 3] }
+    }
+}
+
+impl Bombe {
+    // Runs every start position for each rotor order in `orders`.
+    pub fn run(&self, menu: &Menu, orders: &[[RotorType; 3]]) -> Vec<Stop> {
+        let mut stops = Vec::new();
+        for &rotors in orders {
+            let settings = [0, 1, 2].map(|i| (rotors[i], self.rings[i], 'A'));
+            let Ok(mut machine) = EnigmaMachine::try_new(settings, self.reflector, "") else {
+                continue;
+            };
+            for start in 0..26 * 26 * 26 {
+                let positions = [(start / 676) as u8, (start / 26 % 26) as u8, (start % 26) as u8];
+                machine.restore(EnigmaState { greek: None, positions }).expect("classic positions");
+                if let Some(steckers) = self.test_position(&mut machine, menu) {
+                    for steckers in steckers {
+                        stops.push(Stop { rotors, positions: positions.map(|p| (p + b'A') as char), steckers });
+                    }
+                }
+            }
+        }
+        stops
+    }
+
+    // Every order of three distinct rotors drawn from `pool`.
+    pub fn orders(pool: &[RotorType]) -> Vec<[RotorType; 3]> {
+        let mut orders = Vec::new();
+        for &l in pool {
+            for &m in pool {
+                for &r in pool {
+                    if l != m && m != r && l != r {
+                        orders.push([l, m, r]);
+                    }
+                }
+            }
+        }
+        orders
+    }
+
+    fn test_position(&self, machine: &mut EnigmaMachine, menu: &Menu) -> Option<Vec<Vec<(char, char)>>> {
+        // Crib offsets are far below the rotor period, so plain stepping is
+        // cheaper than `advance` and its cycle detection
+        for _ in 0..menu.offset {
+            machine.step_rotors();
+        }
+        let scramblers: Vec<[u8; 26]> = menu
+            .edges
+            .iter()
+            .map(|_| {
+                machine.step_rotors();
+                machine.scrambler()
+            })
+            .collect();
+
+        let test = menu.test_letter();
+        let mut survivors = Vec::new();
+        for guess in 0..26u8 {
+            if let Some(stecker) = propagate(&menu.edges, &scramblers, test, guess) {
+                let pairs = (0..26u8)
+                    .filter_map(|a| stecker[a as usize].filter(|&b| a < b).map(|b| ((a + b'A') as char, (b + b'A') as char)))
+                    .collect();
+                survivors.push(pairs);
+            }
+        }
+        (!survivors.is_empty()).then_some(survivors)
+    }
+}
+
+// Follows the menu from stecker(test) = guess. Returns the partial plugboard
+// or None on a contradiction.
+fn propagate(edges: &[(u8, u8)], scramblers: &[[u8; 26]], test: u8, guess: u8) -> Option<[Option<u8>; 26]> {
+    let mut stecker = [None; 26];
+    let mut queue = vec![(test, guess)];
+    while let Some((a, b)) = queue.pop() {
+        match (stecker[a as usize], stecker[b as usize]) {
+            (Some(x), _) if x != b => return None,
+            (_, Some(y)) if y != a => return None,
+            (Some(_), _) => continue,
+            _ => {}
+        }
+        // Diagonal board: a steckered to b means b is steckered to a
+        stecker[a as usize] = Some(b);
+        stecker[b as usize] = Some(a);
+        for (&(p, c), s) in edges.iter().zip(scramblers) {
+            for (from, value) in [(a, b), (b, a)] {
+                if from == p {
+                    queue.push((c, s[value as usize]));
+                }
+                if from == c {
+                    queue.push((p, s[value as usize]));
+                }
+            }
+        }
+    }
+    Some(stecker)
+}
+
+fn letters(text: &str) -> Vec<u8> {
+    text.bytes().filter(u8::is_ascii_alphabetic).map(|b| b.to_ascii_uppercase() - b'A').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLUGS: &str = "AR GK OX LN DW";
+
+    fn encrypt(text: &str) -> String {
+        EnigmaMachine::new([(RotorType::II, 'A', 'M'), (RotorType::V, 'A', 'C'), (RotorType::III, 'A', 'U')], ReflectorType::B, PLUGS)
+            .process_text(text)
+    }
+
+    #[test]
+    fn test_menu_errors() {
+        assert_eq!(Menu::new("ABC", "", 0), Err(BombeError::EmptyCrib));
+        assert_eq!(Menu::new("ABC", "XYZW", 0), Err(BombeError::CribTooLong));
+        assert_eq!(Menu::new("ABC", "X Y", 0), Err(BombeError::NonLetter(' ')));
+        assert_eq!(Menu::new("ABC", "XBZ", 0), Err(BombeError::CribClash { index: 1, letter: 'B' }));
+        assert_eq!(crib_offsets("ABCAB", "AB"), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_menu_closures() {
+        // A-B, B-C, C-A closes one loop
+        let menu = Menu::new("BCA", "ABC", 0).unwrap();
+        assert_eq!(menu.closures(), 1);
+        assert_eq!(Menu::new("BD", "AC", 0).unwrap().closures(), 0);
+    }
+
+    #[test]
+    fn test_bombe_finds_true_setting() {
+        let plaintext = "WETTERVORHERSAGEBISKAYAXKEINEBESONDERENVORKOMMNISSE";
+        let ciphertext = encrypt(plaintext);
+        let crib = "WETTERVORHERSAGEBISKAYA";
+        assert!(crib_offsets(&ciphertext, crib).contains(&0));
+        let menu = Menu::new(&ciphertext, crib, 0).unwrap();
+        assert!(menu.closures() >= 3);
+
+        let stops = Bombe::default().run(&menu, &[[RotorType::II, RotorType::V, RotorType::III]]);
+        let hit = stops.iter().find(|s| s.positions == ['M', 'C', 'U']).expect("true stop survives");
+        for &(a, b) in &hit.steckers {
+            assert!(PLUGS.contains(&format!("{a}{b}")) || PLUGS.contains(&format!("{b}{a}")), "{a}{b}");
+        }
+        assert!(stops.len() < 50, "{} stops", stops.len());
+
+        // The implied plugs plus the stop decrypt the crib
+        let mut m = EnigmaMachine::new(
+            [(RotorType::II, 'A', 'M'), (RotorType::V, 'A', 'C'), (RotorType::III, 'A', 'U')],
+            ReflectorType::B,
+            &hit.plugboard(),
+        );
+        let partial = m.process_text(&ciphertext);
+        let matching = partial.chars().zip(crib.chars()).filter(|(a, b)| a == b).count();
+        assert!(matching > crib.len() / 2);
+    }
+
+    #[test]
+    fn test_orders() {
+        assert_eq!(Bombe::orders(&[RotorType::I, RotorType::II, RotorType::III]).len(), 6);
+        assert_eq!(Bombe::orders(&RotorType::ALL[..5]).len(), 60);
+    }
+}
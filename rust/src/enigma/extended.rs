@@ -0,0 +1,133 @@
+// Extended-alphabet wiring. The rotors work on SIZE symbols instead of 26:
+// every printable ASCII character plus tab, newline and carriage return, with
+// A-Z first so window letters and notch positions keep their usual meaning.
+// Each extended rotor and reflector is derived deterministically from the
+// classic wiring of the same name.
+
+pub const SIZE: usize = 98;
+
+const SYMBOLS: &[u8; SIZE] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789 !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~\t\n\r";
+
+// Non-ASCII Unicode scalar values: everything from U+0080 up, minus the
+// surrogate range. An even count, which keeps the involution below free of
+// fixed points.
+const UNICODE_SCALARS: u32 = 0x11_0000 - 0x80 - 0x800;
+
+pub fn symbol(idx: usize) -> char {
+    SYMBOLS[idx] as char
+}
+
+pub fn index(c: char) -> Option<usize> {
+    // Only ASCII can be in the table, so the byte comparison is exact
+    if !c.is_ascii() {
+        return None;
+    }
+    SYMBOLS.iter().position(|&s| s == c as u8)
+}
+
+#[derive(Clone)]
+pub struct ExtendedWiring {
+    pub forward: Vec<u8>,
+    pub backward: Vec<u8>,
+}
+
+impl ExtendedWiring {
+    pub fn rotor(classic: &[u8; 26]) -> Self {
+        let forward = shuffled(seed(classic));
+        let mut backward = vec![0; SIZE];
+        for (i, &f) in forward.iter().enumerate() {
+            backward[f as usize] = i as u8;
+        }
+        ExtendedWiring { forward, backward }
+    }
+
+    // Reflectors pair up consecutive entries of a shuffle, so the result is
+    // an involution with no symbol wired to itself.
+    pub fn reflector(classic: &[u8; 26]) -> Self {
+        let order = shuffled(seed(classic));
+        let mut forward = vec![0; SIZE];
+        for pair in order.chunks(2) {
+            forward[pair[0] as usize] = pair[1];
+            forward[pair[1] as usize] = pair[0];
+        }
+        ExtendedWiring { backward: forward.clone(), forward }
+    }
+}
+
+// Reciprocal substitution for scalars outside the table: c -> key - c over
+// the non-ASCII scalars. With an odd key and an even range no scalar maps to
+// itself, and applying it twice with the same key gives the input back.
+pub fn reflect_scalar(c: char, key: u64) -> char {
+    let mut i = c as u32 - 0x80;
+    if c as u32 >= 0xE000 {
+        i -= 0x800;
+    }
+    let key = ((key % (UNICODE_SCALARS as u64 / 2)) * 2 + 1) as u32;
+    let mut out = (key + UNICODE_SCALARS - i) % UNICODE_SCALARS + 0x80;
+    if out >= 0xD800 {
+        out += 0x800;
+    }
+    char::from_u32(out).expect("scalar stays outside the surrogate range")
+}
+
+fn seed(classic: &[u8; 26]) -> u64 {
+    // FNV-1a over the classic wiring as letters
+    classic.iter().fold(0xcbf2_9ce4_8422_2325, |h, &i| (h ^ (i + b'A') as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+fn shuffled(mut state: u64) -> Vec<u8> {
+    let mut out: Vec<u8> = (0..SIZE as u8).collect();
+    for i in (1..SIZE).rev() {
+        let j = (splitmix(&mut state) % (i as u64 + 1)) as usize;
+        out.swap(i, j);
+    }
+    out
+}
+
+pub fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_table() {
+        let mut sorted = SYMBOLS.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), SIZE);
+        for (i, c) in ('A'..='Z').enumerate() {
+            assert_eq!(index(c), Some(i));
+        }
+        assert_eq!(index('é'), None);
+        assert_eq!(index('\0'), None);
+    }
+
+    #[test]
+    fn test_reflector_has_no_fixed_points() {
+        let r = ExtendedWiring::reflector(&crate::enigma::table(crate::enigma::ReflectorType::B.wiring()));
+        for i in 0..SIZE {
+            let o = r.forward[i] as usize;
+            assert_ne!(o, i);
+            assert_eq!(r.forward[o] as usize, i);
+        }
+    }
+
+    #[test]
+    fn test_reflect_scalar() {
+        for c in ['é', 'ß', '€', '\u{D7FF}', '\u{E000}', '😀', '\u{10FFFF}', '\u{80}'] {
+            for key in [0, 1, 12345, u64::MAX] {
+                let r = reflect_scalar(c, key);
+                assert!(!r.is_ascii());
+                assert_ne!(r, c);
+                assert_eq!(reflect_scalar(r, key), c);
+            }
+        }
+    }
+}
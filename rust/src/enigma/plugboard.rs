@@ -0,0 +1,394 @@
+// Plug connections between the keyboard and the entry wheel, as a
+// permutation of the 26 letters with its inverse, so a lookup costs one index
+// either way.
+//
+// The Steckerbrett with ordinary cables swaps letters in pairs. The Uhr
+// replaced the cables for ten pairs with a box whose 40-position switch
+// rewires them, and at most positions it is not reciprocal: A may go in as B
+// while B goes in as C. The signal enters through `forward` and comes back
+// through `backward`, so the machine as a whole still decrypts what it
+// encrypts.
+//
+// UKW-D, the field-rewirable reflector, lives here too: it is set up from
+// plug pairs like the Steckerbrett.
+
+use super::EnigmaError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Plugboard {
+    forward: [u8; 26],
+    backward: [u8; 26],
+}
+
+impl Default for Plugboard {
+    // Nothing plugged: every letter goes straight through
+    fn default() -> Self {
+        let identity = std::array::from_fn(|i| i as u8);
+        Plugboard { forward: identity, backward: identity }
+    }
+}
+
+impl Plugboard {
+    // Letter pairs in either case; each letter can be used once.
+    pub fn from_pairs(pairs: &[(char, char)]) -> Result<Self, EnigmaError> {
+        let mut plugboard = Plugboard::default();
+        for (a, b) in checked_pairs(pairs)? {
+            plugboard.forward[a as usize] = b;
+            plugboard.forward[b as usize] = a;
+        }
+        plugboard.backward = plugboard.forward;
+        Ok(plugboard)
+    }
+
+    // Space separated pairs, "AV BS CG". A single 26-letter token is read as
+    // the full table instead, which is how `Display` writes a board that
+    // isn't reciprocal.
+    pub fn parse(s: &str) -> Result<Self, EnigmaError> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        if let [table] = tokens[..] {
+            if table.chars().count() == 26 {
+                return Self::from_table(table);
+            }
+        }
+        let pairs = split_pairs(&tokens)?;
+        Self::from_pairs(&pairs)
+    }
+
+    fn from_table(table: &str) -> Result<Self, EnigmaError> {
+        let mut forward = [0; 26];
+        let mut backward = [None; 26];
+        for (i, c) in table.chars().enumerate() {
+            let j = letter_index(c).ok_or_else(|| EnigmaError::MalformedPlug(table.to_string()))?;
+            if backward[j as usize].replace(i as u8).is_some() {
+                return Err(EnigmaError::ReusedPlug(letter(j)));
+            }
+            forward[i] = j;
+        }
+        Ok(Plugboard { forward, backward: backward.map(|i| i.expect("26 distinct letters")) })
+    }
+
+    pub(crate) fn from_permutation(forward: [u8; 26]) -> Self {
+        let mut backward = [0; 26];
+        for (i, &j) in forward.iter().enumerate() {
+            backward[j as usize] = i as u8;
+        }
+        Plugboard { forward, backward }
+    }
+
+    pub fn is_reciprocal(&self) -> bool {
+        self.forward == self.backward
+    }
+
+    // Swapped pairs, lower letter first, in alphabetical order. Only
+    // meaningful for a reciprocal board.
+    pub fn pairs(&self) -> Vec<(char, char)> {
+        (0..26u8)
+            .filter(|&a| self.forward[a as usize] > a)
+            .map(|a| (letter(a), letter(self.forward[a as usize])))
+            .collect()
+    }
+
+    // Keyboard letter to entry wheel contact, both 0-25
+    pub fn forward(&self, i: u8) -> u8 {
+        self.forward[i as usize]
+    }
+
+    // Entry wheel contact back to the lamp
+    pub fn backward(&self, i: u8) -> u8 {
+        self.backward[i as usize]
+    }
+
+    // Anything but A-Z passes through
+    pub(crate) fn map(&self, c: char) -> char {
+        match c {
+            'A'..='Z' => letter(self.forward(c as u8 - b'A')),
+            _ => c,
+        }
+    }
+
+    pub(crate) fn unmap(&self, c: char) -> char {
+        match c {
+            'A'..='Z' => letter(self.backward(c as u8 - b'A')),
+            _ => c,
+        }
+    }
+}
+
+impl std::fmt::Display for Plugboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.is_reciprocal() {
+            return f.write_str(&self.forward.iter().map(|&i| letter(i)).collect::<String>());
+        }
+        let pairs: Vec<String> = self.pairs().iter().map(|&(a, b)| format!("{a}{b}")).collect();
+        f.write_str(&pairs.join(" "))
+    }
+}
+
+impl std::str::FromStr for Plugboard {
+    type Err = EnigmaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Plugboard::parse(s)
+    }
+}
+
+// Contacts of the Uhr's scrambling disc: entry contact i leads to exit
+// contact UHR_WIRING[i] at position 00. Each plug has a keyboard pin and an
+// entry wheel pin two contacts further on; the red a-plugs sit at every
+// fourth contact on the entry side, the white b-plugs at UHR_B_PLUGS on the
+// exit side.
+const UHR_WIRING: [u8; 40] = [
+    6, 31, 4, 29, 18, 39, 16, 25, 30, 23, 28, 1, 38, 11, 36, 37, 26, 27, 24, 21, 14, 3, 12, 17, 2, 7, 0, 33, 10, 35, 8,
+    5, 22, 19, 20, 13, 34, 15, 32, 9,
+];
+const UHR_B_PLUGS: [u8; 10] = [4, 16, 28, 36, 24, 12, 0, 8, 20, 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Uhr {
+    // Letters on plugs 1a-10a and 1b-10b
+    a: [u8; 10],
+    b: [u8; 10],
+    position: u8,
+}
+
+impl Uhr {
+    pub const POSITIONS: u8 = 40;
+
+    // Exactly ten pairs, "AB CD ..."; the first letter of pair n goes on
+    // plug na, the second on nb. At position 00, and only at multiples of
+    // four, the Uhr is reciprocal; at 00 it swaps the pairs like cables.
+    pub fn new(pairs: &str, position: u8) -> Result<Self, EnigmaError> {
+        let tokens: Vec<&str> = pairs.split_whitespace().collect();
+        let pairs = checked_pairs(&split_pairs(&tokens)?)?;
+        if pairs.len() != 10 {
+            return Err(EnigmaError::UhrPairs(pairs.len()));
+        }
+        let mut uhr = Uhr { a: [0; 10], b: [0; 10], position: 0 };
+        for (n, (a, b)) in pairs.into_iter().enumerate() {
+            uhr.a[n] = a;
+            uhr.b[n] = b;
+        }
+        uhr.set_position(position)?;
+        Ok(uhr)
+    }
+
+    pub fn position(&self) -> u8 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: u8) -> Result<(), EnigmaError> {
+        if position >= Self::POSITIONS {
+            return Err(EnigmaError::InvalidUhrPosition(position));
+        }
+        self.position = position;
+        Ok(())
+    }
+
+    // The plug connections at the current switch position.
+    pub fn plugboard(&self) -> Plugboard {
+        let n = Self::POSITIONS as usize;
+        let p = self.position as usize;
+        let mut inverse = [0u8; 40];
+        for (i, &w) in UHR_WIRING.iter().enumerate() {
+            inverse[w as usize] = i as u8;
+        }
+        // Plug number at each contact, keyboard pins only
+        let mut b_plug = [None; 40];
+        for (plug, &contact) in UHR_B_PLUGS.iter().enumerate() {
+            b_plug[contact as usize] = Some(plug);
+        }
+
+        let mut forward: [u8; 26] = std::array::from_fn(|i| i as u8);
+        for plug in 0..10 {
+            // a-plug keyboard pin through the disc to a b-plug entry wheel pin
+            let exit = (UHR_WIRING[(4 * plug + p) % n] as usize + n - p) % n;
+            forward[self.a[plug] as usize] = self.b[b_plug[exit - 2].expect("entry wheel pin of a b-plug")];
+            // b-plug keyboard pin back through the disc to an a-plug entry wheel pin
+            let entry = (inverse[(UHR_B_PLUGS[plug] as usize + p) % n] as usize + n - p) % n;
+            forward[self.b[plug] as usize] = self.a[(entry - 2) / 4];
+        }
+        Plugboard::from_permutation(forward)
+    }
+}
+
+// UKW-D with its twelve plugged pairs. J and Y (Bletchley Park's labelling of
+// the contacts) are wired together inside and can't be plugged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UkwD {
+    wiring: [u8; 26],
+}
+
+impl UkwD {
+    const FIXED: (char, char) = ('J', 'Y');
+
+    pub fn parse(pairs: &str) -> Result<Self, EnigmaError> {
+        let pairs = Plugboard::parse(pairs)?;
+        if let Some(c) = [Self::FIXED.0, Self::FIXED.1].into_iter().find(|&c| pairs.map(c) != c) {
+            return Err(EnigmaError::UkwDFixedPair(c));
+        }
+        let n = pairs.pairs().len();
+        if n != 12 {
+            return Err(EnigmaError::UkwDPairs(n));
+        }
+        let (j, y) = (Self::FIXED.0 as u8 - b'A', Self::FIXED.1 as u8 - b'A');
+        let mut wiring = pairs.forward;
+        wiring[j as usize] = y;
+        wiring[y as usize] = j;
+        Ok(UkwD { wiring })
+    }
+
+    // Reflector wiring in the same form as `ReflectorType::wiring`
+    pub fn wiring(&self) -> String {
+        self.wiring.iter().map(|&i| letter(i)).collect()
+    }
+}
+
+impl std::fmt::Display for UkwD {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pairs: Vec<String> = Plugboard::from_permutation(self.wiring)
+            .pairs()
+            .iter()
+            .filter(|&&pair| pair != Self::FIXED)
+            .map(|&(a, b)| format!("{a}{b}"))
+            .collect();
+        f.write_str(&pairs.join(" "))
+    }
+}
+
+impl std::str::FromStr for UkwD {
+    type Err = EnigmaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UkwD::parse(s)
+    }
+}
+
+fn letter(i: u8) -> char {
+    (i + b'A') as char
+}
+
+fn letter_index(c: char) -> Option<u8> {
+    c.is_ascii_alphabetic().then(|| c.to_ascii_uppercase() as u8 - b'A')
+}
+
+fn split_pairs(tokens: &[&str]) -> Result<Vec<(char, char)>, EnigmaError> {
+    tokens
+        .iter()
+        .map(|pair| match pair.chars().collect::<Vec<_>>()[..] {
+            [a, b] => Ok((a, b)),
+            _ => Err(EnigmaError::MalformedPlug(pair.to_string())),
+        })
+        .collect()
+}
+
+// Letter indices in the given order, after checking no letter is used twice
+fn checked_pairs(pairs: &[(char, char)]) -> Result<Vec<(u8, u8)>, EnigmaError> {
+    let mut used = [false; 26];
+    let mut checked = Vec::with_capacity(pairs.len());
+    for &(a, b) in pairs {
+        let malformed = || EnigmaError::MalformedPlug(format!("{a}{b}"));
+        let (a, b) = (letter_index(a).ok_or_else(malformed)?, letter_index(b).ok_or_else(malformed)?);
+        if a == b {
+            return Err(EnigmaError::SelfPair(letter(a)));
+        }
+        for i in [a, b] {
+            if std::mem::replace(&mut used[i as usize], true) {
+                return Err(EnigmaError::ReusedPlug(letter(i)));
+            }
+        }
+        checked.push((a, b));
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{EnigmaMachine, ReflectorType, RotorType};
+
+    const ROTORS: [(RotorType, char, char); 3] = [(RotorType::II, 'B', 'B'), (RotorType::IV, 'U', 'L'), (RotorType::V, 'L', 'A')];
+    const PAIRS: &str = "AV BS CG DL FU HZ IN KM OW RX";
+
+    #[test]
+    fn test_parse_and_format() {
+        let pb = Plugboard::parse("av sb CG").unwrap();
+        assert_eq!(pb.to_string(), "AV BS CG");
+        assert_eq!(pb.pairs(), vec![('A', 'V'), ('B', 'S'), ('C', 'G')]);
+        assert_eq!((pb.forward(0), pb.backward(21), pb.forward(25)), (21, 0, 25));
+        assert_eq!("".parse::<Plugboard>(), Ok(Plugboard::default()));
+        assert_eq!(Plugboard::from_pairs(&[('A', 'V'), ('S', 'B'), ('g', 'c')]), Ok(pb));
+        assert_eq!(Plugboard::parse(PAIRS).unwrap().to_string(), PAIRS);
+    }
+
+    #[test]
+    fn test_invalid_pairs() {
+        assert_eq!(Plugboard::parse("AB C"), Err(EnigmaError::MalformedPlug("C".into())));
+        assert_eq!(Plugboard::parse("A1"), Err(EnigmaError::MalformedPlug("A1".into())));
+        assert_eq!(Plugboard::parse("AA"), Err(EnigmaError::SelfPair('A')));
+        assert_eq!(Plugboard::parse("AB CB"), Err(EnigmaError::ReusedPlug('B')));
+        assert_eq!(Plugboard::from_pairs(&[('a', 'B'), ('b', 'C')]), Err(EnigmaError::ReusedPlug('B')));
+    }
+
+    #[test]
+    fn test_table_round_trip() {
+        let table = "BCDEFGHIJKLMNOPQRSTUVWXYZA";
+        let pb = Plugboard::parse(table).unwrap();
+        assert!(!pb.is_reciprocal());
+        assert_eq!((pb.forward(0), pb.backward(0)), (1, 25));
+        assert_eq!(pb.to_string(), table);
+        assert_eq!(Plugboard::parse("ABCDEFGHIJKLMNOPQRSTUVWXYY"), Err(EnigmaError::ReusedPlug('Y')));
+    }
+
+    #[test]
+    fn test_uhr_reciprocal_only_every_fourth_position() {
+        let mut uhr = Uhr::new(PAIRS, 0).unwrap();
+        assert_eq!(uhr.plugboard(), Plugboard::parse(PAIRS).unwrap());
+        for position in 0..Uhr::POSITIONS {
+            uhr.set_position(position).unwrap();
+            let pb = uhr.plugboard();
+            assert_eq!(pb.is_reciprocal(), position % 4 == 0, "position {position}");
+            // Letters not on the Uhr are left alone
+            for c in "EJPQTY".chars() {
+                assert_eq!(pb.map(c), c);
+            }
+        }
+        assert_eq!(uhr.set_position(40), Err(EnigmaError::InvalidUhrPosition(40)));
+        assert_eq!(Uhr::new("AB CD", 0), Err(EnigmaError::UhrPairs(2)));
+    }
+
+    #[test]
+    fn test_machine_with_uhr() {
+        let plain = "DIEUHRISTNICHTRECIPROKABERDIEMASCHINESCHON";
+        let mut cables = EnigmaMachine::new(ROTORS, ReflectorType::B, PAIRS);
+        let uhr = |position| EnigmaMachine::new(ROTORS, ReflectorType::B, "").with_plugboard(Uhr::new(PAIRS, position).unwrap().plugboard());
+        assert_eq!(uhr(0).process_text(plain), cables.process_text(plain));
+
+        let ciphertext = uhr(27).process_text(plain);
+        assert_ne!(ciphertext, EnigmaMachine::new(ROTORS, ReflectorType::B, PAIRS).process_text(plain));
+        assert_eq!(uhr(27).process_text(&ciphertext), plain);
+        assert!(ciphertext.chars().zip(plain.chars()).all(|(c, p)| c != p));
+    }
+
+    #[test]
+    fn test_ukw_d() {
+        let pairs = "AC BK DE FG HI LM NO PQ RS TU VW XZ";
+        let ukw = UkwD::parse(pairs).unwrap();
+        assert_eq!(ukw.to_string(), pairs);
+        let wiring: Vec<char> = ukw.wiring().chars().collect();
+        assert_eq!((wiring[9], wiring[24]), ('Y', 'J'));
+        for (i, &c) in wiring.iter().enumerate() {
+            assert_ne!(c as usize - 'A' as usize, i);
+            assert_eq!(wiring[c as usize - 'A' as usize] as usize - 'A' as usize, i);
+        }
+
+        let machine = || EnigmaMachine::new(ROTORS, ReflectorType::B, PAIRS).with_ukw_d(ukw).unwrap();
+        let ciphertext = machine().process_text("UMKEHRWALZEDORA");
+        assert_ne!(ciphertext, EnigmaMachine::new(ROTORS, ReflectorType::B, PAIRS).process_text("UMKEHRWALZEDORA"));
+        assert_eq!(machine().process_text(&ciphertext), "UMKEHRWALZEDORA");
+
+        assert_eq!(UkwD::parse("AJ"), Err(EnigmaError::UkwDFixedPair('J')));
+        assert_eq!(UkwD::parse("AC BK"), Err(EnigmaError::UkwDPairs(2)));
+        let m4 = EnigmaMachine::new_m4((RotorType::Beta, 'A', 'A'), ROTORS, ReflectorType::BThin, "");
+        assert!(matches!(m4.with_ukw_d(ukw), Err(EnigmaError::UkwDOnM4)));
+    }
+}
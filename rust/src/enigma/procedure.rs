@@ -0,0 +1,529 @@
+// Operating procedure on top of `EnigmaMachine`: daily key sheets and the
+// Heer/Luftwaffe message-key procedure in use from May 1940.
+//
+// For each message part the operator picks a random Grundstellung (sent in
+// the clear) and a random message key, enciphers the message key once at the
+// Grundstellung, then sets the rotors to the message key and enciphers the
+// text. A five-letter Buchstabenkenngruppe, two random letters followed by
+// one of the day's Kenngruppen, goes in front of the text unenciphered so the
+// receiver can tell which key to use. Messages longer than 250 letters are
+// split into parts, each keyed on its own, with a header of the form
+//
+//     1840 - 2tl - 1tl - 250 - WXC KCH -
+//
+// (time, number of parts, this part, letter count, Grundstellung, enciphered
+// message key).
+
+use rand::Rng;
+
+use super::{EnigmaError, EnigmaMachine, ReflectorType, RotorType};
+
+pub const PART_LETTERS: usize = 250;
+const GROUP: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcedureError {
+    Parse { line: usize, message: String },
+    Enigma(EnigmaError),
+    UnknownKenngruppe(String),
+    MalformedPart(String),
+    // Header letter count disagrees with the text that follows it
+    LengthMismatch { expected: usize, found: usize },
+    MissingPart(usize),
+}
+
+impl std::fmt::Display for ProcedureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcedureError::Parse { line, message } => write!(f, "key sheet line {line}: {message}"),
+            ProcedureError::Enigma(e) => write!(f, "{e}"),
+            ProcedureError::UnknownKenngruppe(g) => write!(f, "no key on the sheet has Kenngruppe {g}"),
+            ProcedureError::MalformedPart(m) => write!(f, "malformed message part: {m}"),
+            ProcedureError::LengthMismatch { expected, found } => {
+                write!(f, "header announces {expected} letters but the part has {found}")
+            }
+            ProcedureError::MissingPart(n) => write!(f, "message part {n} is missing"),
+        }
+    }
+}
+
+impl std::error::Error for ProcedureError {}
+
+impl From<EnigmaError> for ProcedureError {
+    fn from(e: EnigmaError) -> Self {
+        ProcedureError::Enigma(e)
+    }
+}
+
+// One row of a key sheet. Only built through `new`, so the settings always
+// make a machine and the rings are upper-case letters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailyKey {
+    day: u8,
+    reflector: ReflectorType,
+    // Walzenlage, left to right
+    rotors: [RotorType; 3],
+    // Ringstellung as letters
+    rings: [char; 3],
+    // Steckerverbindungen, e.g. "AD CN ET"
+    plugboard: String,
+    kenngruppen: Vec<String>,
+}
+
+impl DailyKey {
+    pub fn new(
+        day: u8,
+        reflector: ReflectorType,
+        rotors: [RotorType; 3],
+        rings: [char; 3],
+        plugboard: &str,
+        kenngruppen: Vec<String>,
+    ) -> Result<Self, EnigmaError> {
+        let key = DailyKey {
+            day,
+            reflector,
+            rotors,
+            rings: rings.map(|r| r.to_ascii_uppercase()),
+            plugboard: plugboard.to_ascii_uppercase(),
+            kenngruppen,
+        };
+        // Let the machine itself check rotor, ring and plug rules
+        key.machine(['A'; 3])?;
+        Ok(key)
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn reflector(&self) -> ReflectorType {
+        self.reflector
+    }
+
+    pub fn rotors(&self) -> [RotorType; 3] {
+        self.rotors
+    }
+
+    pub fn rings(&self) -> [char; 3] {
+        self.rings
+    }
+
+    pub fn plugboard(&self) -> &str {
+        &self.plugboard
+    }
+
+    pub fn kenngruppen(&self) -> &[String] {
+        &self.kenngruppen
+    }
+
+    pub fn machine(&self, positions: [char; 3]) -> Result<EnigmaMachine, EnigmaError> {
+        let settings = [0, 1, 2].map(|i| (self.rotors[i], self.rings[i], positions[i]));
+        EnigmaMachine::try_new(settings, self.reflector, &self.plugboard)
+    }
+
+    pub fn random(day: u8, rng: &mut impl Rng) -> Self {
+        let mut pool = vec![RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V];
+        let rotors = [0, 1, 2].map(|_| pool.remove(rng.gen_range(0..pool.len())));
+        let mut letters: Vec<char> = ('A'..='Z').collect();
+        let mut pairs = Vec::new();
+        for _ in 0..10 {
+            let a = letters.remove(rng.gen_range(0..letters.len()));
+            let b = letters.remove(rng.gen_range(0..letters.len()));
+            pairs.push(format!("{a}{b}"));
+        }
+        DailyKey {
+            day,
+            reflector: ReflectorType::B,
+            rotors,
+            rings: [0, 1, 2].map(|_| random_letter(rng)),
+            plugboard: pairs.join(" "),
+            kenngruppen: (0..4).map(|_| random_letters(rng, 3)).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for DailyKey {
+    // Same layout `KeySheet::parse` reads
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rotors: Vec<String> = self.rotors.iter().map(|r| r.to_string()).collect();
+        let rings: Vec<String> = self.rings.iter().map(|&r| format!("{:02}", r as u8 - b'A' + 1)).collect();
+        write!(
+            f,
+            "{:02} | {} | {} | {} | {} | {}",
+            self.day,
+            self.reflector,
+            rotors.join(" "),
+            rings.join(" "),
+            self.plugboard,
+            self.kenngruppen.join(" ")
+        )
+    }
+}
+
+// A month's keys, one row per day:
+//
+//     # Tag | UKW | Walzenlage | Ringstellung | Steckerverbindungen | Kenngruppen
+//     31 | B | I IV III | 16 26 08 | AD CN ET FL GI JV KZ PU QY WX | JKM OGI NCJ GLP
+//
+// The UKW column may be left out (UKW-B is assumed). Ring settings are
+// numbers 01-26 or letters. Blank lines and `#` comments are skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeySheet {
+    pub keys: Vec<DailyKey>,
+}
+
+impl KeySheet {
+    pub fn parse(text: &str) -> Result<Self, ProcedureError> {
+        let mut keys = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            keys.push(parse_row(line).map_err(|message| ProcedureError::Parse { line: i + 1, message })?);
+        }
+        Ok(KeySheet { keys })
+    }
+
+    pub fn random(days: u8, rng: &mut impl Rng) -> Self {
+        KeySheet { keys: (1..=days).rev().map(|d| DailyKey::random(d, rng)).collect() }
+    }
+
+    pub fn day(&self, day: u8) -> Option<&DailyKey> {
+        self.keys.iter().find(|k| k.day == day)
+    }
+
+    // The day a received message belongs to, from the last three letters of
+    // its Buchstabenkenngruppe.
+    pub fn by_kenngruppe(&self, group: &str) -> Option<&DailyKey> {
+        let tail = group.get(group.len().saturating_sub(3)..)?;
+        self.keys.iter().find(|k| k.kenngruppen.iter().any(|g| g == tail))
+    }
+}
+
+impl std::fmt::Display for KeySheet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# Tag | UKW | Walzenlage | Ringstellung | Steckerverbindungen | Kenngruppen")?;
+        for key in &self.keys {
+            writeln!(f, "{key}")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_row(line: &str) -> Result<DailyKey, String> {
+    let mut cols: Vec<&str> = line.split('|').map(str::trim).collect();
+    if cols.len() == 5 {
+        cols.insert(1, "B");
+    }
+    if cols.len() != 6 {
+        return Err(format!("expected 5 or 6 columns, found {}", cols.len()));
+    }
+    let day = cols[0].parse().map_err(|_| format!("bad day {:?}", cols[0]))?;
+    let reflector = match cols[1].chars().collect::<Vec<_>>()[..] {
+        [c] => ReflectorType::try_from(c).map_err(|e| e.to_string())?,
+        _ => return Err(format!("bad reflector {:?}", cols[1])),
+    };
+
+    let rotors: Vec<RotorType> = cols[2]
+        .split_whitespace()
+        .map(|r| r.parse().map_err(|_| format!("unknown rotor {r:?}")))
+        .collect::<Result<_, _>>()?;
+    let rotors: [RotorType; 3] = rotors.try_into().map_err(|_| "Walzenlage needs three rotors".to_string())?;
+
+    let rings: Vec<char> = cols[3].split_whitespace().map(parse_ring).collect::<Result<_, _>>()?;
+    let rings: [char; 3] = rings.try_into().map_err(|_| "Ringstellung needs three settings".to_string())?;
+
+    let kenngruppen: Vec<String> = cols[5].split_whitespace().map(str::to_ascii_uppercase).collect();
+    if let Some(bad) = kenngruppen.iter().find(|g| g.len() != 3 || !g.bytes().all(|b| b.is_ascii_uppercase())) {
+        return Err(format!("bad Kenngruppe {bad:?}"));
+    }
+
+    DailyKey::new(day, reflector, rotors, rings, cols[4], kenngruppen).map_err(|e| e.to_string())
+}
+
+fn parse_ring(s: &str) -> Result<char, String> {
+    if let Ok(n) = s.parse::<u8>() {
+        if (1..=26).contains(&n) {
+            return Ok((b'A' + n - 1) as char);
+        }
+    } else if let [c] = s.as_bytes() {
+        if c.is_ascii_alphabetic() {
+            return Ok(c.to_ascii_uppercase() as char);
+        }
+    }
+    Err(format!("bad ring setting {s:?}"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessagePart {
+    pub time: String,
+    pub part: usize,
+    pub parts: usize,
+    pub grundstellung: String,
+    pub encrypted_key: String,
+    // Buchstabenkenngruppe, sent in the clear as the first group
+    pub kenngruppe: String,
+    pub ciphertext: String,
+}
+
+impl MessagePart {
+    pub fn letter_count(&self) -> usize {
+        self.kenngruppe.len() + self.ciphertext.len()
+    }
+
+    pub fn header(&self) -> String {
+        format!(
+            "{} - {}tl - {}tl - {} - {} {} -",
+            self.time,
+            self.parts,
+            self.part,
+            self.letter_count(),
+            self.grundstellung,
+            self.encrypted_key
+        )
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ProcedureError> {
+        let malformed = |m: &str| ProcedureError::MalformedPart(m.to_string());
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        let header = lines.next().ok_or_else(|| malformed("empty part"))?;
+        let fields: Vec<&str> = header.split('-').map(str::trim).collect();
+        let [time, parts, part, count, keys, ""] = fields[..] else {
+            return Err(malformed("header must read `time - Ntl - ntl - count - GRU KEY -`"));
+        };
+        let parts = parse_tl(parts).ok_or_else(|| malformed("bad part count"))?;
+        let part = parse_tl(part).ok_or_else(|| malformed("bad part number"))?;
+        let count: usize = count.parse().map_err(|_| malformed("bad letter count"))?;
+        let Some((grundstellung, encrypted_key)) = keys.split_once(' ') else {
+            return Err(malformed("missing Grundstellung or message key"));
+        };
+
+        let body: String = lines.flat_map(|l| l.split_whitespace()).collect();
+        if body.len() < GROUP || !body.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(malformed("text must be upper-case letter groups"));
+        }
+        let (kenngruppe, ciphertext) = body.split_at(GROUP);
+        let parsed = MessagePart {
+            time: time.to_string(),
+            part,
+            parts,
+            grundstellung: grundstellung.trim().to_string(),
+            encrypted_key: encrypted_key.trim().to_string(),
+            kenngruppe: kenngruppe.to_string(),
+            ciphertext: ciphertext.to_string(),
+        };
+        if parsed.letter_count() != count {
+            return Err(ProcedureError::LengthMismatch { expected: count, found: parsed.letter_count() });
+        }
+        Ok(parsed)
+    }
+}
+
+impl std::fmt::Display for MessagePart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.header())?;
+        let text = format!("{}{}", self.kenngruppe, self.ciphertext);
+        let groups: Vec<&str> = text.as_bytes().chunks(GROUP).map(|g| std::str::from_utf8(g).expect("ASCII")).collect();
+        for line in groups.chunks(10) {
+            writeln!(f, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_tl(s: &str) -> Option<usize> {
+    s.strip_suffix("tl")?.parse().ok().filter(|&n| n > 0)
+}
+
+// Enciphers one part with explicit keys; `encipher_message` picks them at
+// random. `plaintext` must already be letters only.
+pub fn encipher_part(
+    key: &DailyKey,
+    time: &str,
+    (part, parts): (usize, usize),
+    grundstellung: [char; 3],
+    message_key: [char; 3],
+    kenngruppe: &str,
+    plaintext: &str,
+) -> Result<MessagePart, ProcedureError> {
+    let encrypted_key = key.machine(grundstellung)?.process_text(&message_key.iter().collect::<String>());
+    let ciphertext = key.machine(message_key)?.process_text(plaintext);
+    Ok(MessagePart {
+        time: time.to_string(),
+        part,
+        parts,
+        grundstellung: grundstellung.iter().collect(),
+        encrypted_key,
+        kenngruppe: kenngruppe.to_string(),
+        ciphertext,
+    })
+}
+
+// Splits `plaintext` into parts of at most 250 letters (Kenngruppe included)
+// and keys each one separately. Anything but letters is dropped; spell out
+// punctuation the usual way (X for a full stop) beforehand.
+pub fn encipher_message(key: &DailyKey, time: &str, plaintext: &str, rng: &mut impl Rng) -> Result<Vec<MessagePart>, ProcedureError> {
+    let letters: String = plaintext.chars().filter(char::is_ascii_alphabetic).map(|c| c.to_ascii_uppercase()).collect();
+    let chunks: Vec<&str> = if letters.is_empty() {
+        vec![""]
+    } else {
+        letters.as_bytes().chunks(PART_LETTERS - GROUP).map(|c| std::str::from_utf8(c).expect("ASCII")).collect()
+    };
+    let parts = chunks.len();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let grundstellung = [0, 1, 2].map(|_| random_letter(rng));
+            let message_key = [0, 1, 2].map(|_| random_letter(rng));
+            let group = key.kenngruppen.get(rng.gen_range(0..key.kenngruppen.len().max(1))).cloned();
+            let kenngruppe = random_letters(rng, 2) + &group.unwrap_or_else(|| random_letters(rng, 3));
+            encipher_part(key, time, (i + 1, parts), grundstellung, message_key, &kenngruppe, chunk)
+        })
+        .collect()
+}
+
+// Recovers the message key of a part and deciphers it.
+pub fn decipher_part(key: &DailyKey, part: &MessagePart) -> Result<String, ProcedureError> {
+    let grundstellung = three_letters(&part.grundstellung)?;
+    let message_key = three_letters(&key.machine(grundstellung)?.process_text(&part.encrypted_key))?;
+    Ok(key.machine(message_key)?.process_text(&part.ciphertext))
+}
+
+// Puts the parts back in order, finds the day from each part's Kenngruppe and
+// joins the plaintext.
+pub fn decipher_message(sheet: &KeySheet, parts: &[MessagePart]) -> Result<String, ProcedureError> {
+    let total = parts.first().map_or(0, |p| p.parts);
+    let mut text = String::new();
+    for n in 1..=total {
+        let part = parts.iter().find(|p| p.part == n).ok_or(ProcedureError::MissingPart(n))?;
+        let key = sheet
+            .by_kenngruppe(&part.kenngruppe)
+            .ok_or_else(|| ProcedureError::UnknownKenngruppe(part.kenngruppe.clone()))?;
+        text += &decipher_part(key, part)?;
+    }
+    Ok(text)
+}
+
+fn three_letters(s: &str) -> Result<[char; 3], ProcedureError> {
+    let chars: Vec<char> = s.chars().collect();
+    chars.try_into().map_err(|_| ProcedureError::MalformedPart(format!("expected three letters, found {s:?}")))
+}
+
+fn random_letter(rng: &mut impl Rng) -> char {
+    (b'A' + rng.gen_range(0..26u8)) as char
+}
+
+fn random_letters(rng: &mut impl Rng, n: usize) -> String {
+    (0..n).map(|_| random_letter(rng)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SHEET: &str = "\
+# Tag | UKW | Walzenlage | Ringstellung | Steckerverbindungen | Kenngruppen
+31 | B | I IV III | 16 26 08 | AD CN ET FL GI JV KZ PU QY WX | JKM OGI NCJ GLP
+30 | II V I | P H K | BQ CR DI EJ KW MT OS PX UZ GH | RAW KTL VMZ OQY
+";
+
+    #[test]
+    fn test_parse_key_sheet() {
+        let sheet = KeySheet::parse(SHEET).unwrap();
+        let day = sheet.day(31).unwrap();
+        assert_eq!(day.rotors(), [RotorType::I, RotorType::IV, RotorType::III]);
+        assert_eq!(day.rings(), ['P', 'Z', 'H']);
+        assert_eq!(day.kenngruppen(), ["JKM", "OGI", "NCJ", "GLP"]);
+        let day = sheet.day(30).unwrap();
+        assert_eq!(day.reflector(), ReflectorType::B);
+        assert_eq!(day.rings(), ['P', 'H', 'K']);
+        assert_eq!(sheet.by_kenngruppe("XYVMZ").map(|k| k.day()), Some(30));
+        assert_eq!(sheet.by_kenngruppe("XYABC"), None);
+    }
+
+    #[test]
+    fn test_daily_key_checks_rings() {
+        let rotors = [RotorType::I, RotorType::IV, RotorType::III];
+        let key = DailyKey::new(7, ReflectorType::B, rotors, ['p', 'z', 'h'], "ad cn", vec!["JKM".into()]).unwrap();
+        assert_eq!((key.rings(), key.plugboard()), (['P', 'Z', 'H'], "AD CN"));
+        assert!(key.to_string().contains("| 16 26 08 |"));
+        let bad = DailyKey::new(7, ReflectorType::B, rotors, ['P', '!', 'H'], "", Vec::new());
+        assert_eq!(bad, Err(EnigmaError::InvalidRing('!')));
+    }
+
+    #[test]
+    fn test_key_sheet_round_trip() {
+        let sheet = KeySheet::parse(SHEET).unwrap();
+        assert_eq!(KeySheet::parse(&sheet.to_string()).unwrap(), sheet);
+        let random = KeySheet::random(31, &mut StdRng::seed_from_u64(7));
+        assert_eq!(random.keys.len(), 31);
+        assert_eq!(KeySheet::parse(&random.to_string()).unwrap(), random);
+    }
+
+    #[test]
+    fn test_key_sheet_errors() {
+        let err = |text| match KeySheet::parse(text) {
+            Err(ProcedureError::Parse { line, message }) => (line, message),
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(err("\n31 | I II | 01 02 03 | | ABC").0, 2);
+        assert!(err("31 | I II IX | 01 02 03 | | ABC").1.contains("IX"));
+        assert!(err("31 | I II III | 01 02 27 | | ABC").1.contains("27"));
+        assert!(err("31 | I II III | 01 02 03 | AB BC | ABC").1.contains("more than once"));
+        assert!(err("31 | I I III | 01 02 03 | | ABC").1.contains("more than once"));
+        assert!(err("31 | I II III | 01 02 03 | | AB").1.contains("Kenngruppe"));
+        assert!(err("31 | D | I II III | 01 02 03 | | ABC").1.contains("reflector"));
+    }
+
+    #[test]
+    fn test_indicator_procedure() {
+        let sheet = KeySheet::parse(SHEET).unwrap();
+        let key = sheet.day(31).unwrap();
+        let part = encipher_part(key, "1840", (1, 1), ['W', 'X', 'C'], ['A', 'B', 'L'], "XYNCJ", "FEINDLIQEINFANTERIE").unwrap();
+        assert_eq!(part.grundstellung, "WXC");
+        // The message key goes out enciphered once at the Grundstellung
+        assert_eq!(key.machine(['W', 'X', 'C']).unwrap().process_text(&part.encrypted_key), "ABL");
+        assert_eq!(part.ciphertext, key.machine(['A', 'B', 'L']).unwrap().process_text("FEINDLIQEINFANTERIE"));
+        assert_eq!(part.header(), format!("1840 - 1tl - 1tl - 24 - WXC {} -", part.encrypted_key));
+        assert_eq!(decipher_part(key, &part).unwrap(), "FEINDLIQEINFANTERIE");
+    }
+
+    #[test]
+    fn test_message_split_and_round_trip() {
+        let sheet = KeySheet::parse(SHEET).unwrap();
+        let key = sheet.day(30).unwrap();
+        let plaintext = "DREIGEHTLANGSAMABERSIQERVORWAERTS".repeat(20);
+        let parts = encipher_message(key, "0915", &plaintext, &mut StdRng::seed_from_u64(1)).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| p.letter_count() <= PART_LETTERS && p.parts == 3));
+        assert_eq!(parts[0].letter_count(), PART_LETTERS);
+        assert!(parts.iter().all(|p| key.kenngruppen().contains(&p.kenngruppe[2..].to_string())));
+        // Each part has its own keys
+        assert_ne!(parts[0].grundstellung, parts[1].grundstellung);
+
+        let transmitted: Vec<String> = parts.iter().rev().map(|p| p.to_string()).collect();
+        let received: Vec<MessagePart> = transmitted.iter().map(|t| MessagePart::parse(t).unwrap()).collect();
+        assert_eq!(decipher_message(&sheet, &received).unwrap(), plaintext);
+        assert_eq!(decipher_message(&sheet, &received[1..]), Err(ProcedureError::MissingPart(3)));
+    }
+
+    #[test]
+    fn test_part_parse_errors() {
+        let sheet = KeySheet::parse(SHEET).unwrap();
+        let key = sheet.day(31).unwrap();
+        let part = encipher_part(key, "1840", (1, 1), ['W', 'X', 'C'], ['A', 'B', 'L'], "XYNCJ", "ANGRIFF").unwrap();
+        let text = part.to_string();
+        assert_eq!(MessagePart::parse(&text).unwrap(), part);
+        let short = text.replace(" - 12 - ", " - 13 - ");
+        assert_eq!(MessagePart::parse(&short), Err(ProcedureError::LengthMismatch { expected: 13, found: 12 }));
+        assert!(MessagePart::parse("1840 - 1tl - 12 - WXC ABC -\nXYNCJ ABCDE FG").is_err());
+        assert!(MessagePart::parse("").is_err());
+
+        let mut stray = part.clone();
+        stray.kenngruppe = "XYQQQ".into();
+        assert_eq!(decipher_message(&sheet, &[stray]), Err(ProcedureError::UnknownKenngruppe("XYQQQ".into())));
+    }
+}
@@ -0,0 +1,149 @@
+// Property tests over arbitrary machines and texts. `test_symmetry` and the
+// known-answer tests pin down single settings; these check the invariants
+// that have to hold for every one of them.
+
+use super::*;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    greek: Option<(RotorType, char, char)>,
+    rotors: [(RotorType, char, char); 3],
+    reflector: ReflectorType,
+    plugs: String,
+    alphabet: Alphabet,
+}
+
+impl Settings {
+    fn machine(&self) -> EnigmaMachine {
+        let machine = match self.greek {
+            Some(greek) => EnigmaMachine::try_new_m4(greek, self.rotors, self.reflector, &self.plugs),
+            None => EnigmaMachine::try_new(self.rotors, self.reflector, &self.plugs),
+        };
+        machine.expect("generated settings are valid").with_alphabet(self.alphabet)
+    }
+}
+
+fn letter() -> impl Strategy<Value = char> {
+    (b'A'..=b'Z').prop_map(char::from)
+}
+
+fn setting(kind: RotorType) -> impl Strategy<Value = (RotorType, char, char)> {
+    (letter(), letter()).prop_map(move |(ring, position)| (kind, ring, position))
+}
+
+// Up to 13 plug pairs over distinct letters
+fn plugs() -> impl Strategy<Value = String> {
+    let letters: Vec<char> = ('A'..='Z').collect();
+    (0..=13usize)
+        .prop_flat_map(move |n| subsequence(letters.clone(), 2 * n).prop_shuffle())
+        .prop_map(|letters| letters.chunks(2).map(|p| p.iter().collect::<String>()).collect::<Vec<_>>().join(" "))
+}
+
+fn classic() -> impl Strategy<Value = Settings> {
+    settings(Just(Alphabet::Classic))
+}
+
+fn settings(alphabet: impl Strategy<Value = Alphabet>) -> impl Strategy<Value = Settings> {
+    let walzen = subsequence(RotorType::ALL[..8].to_vec(), 3).prop_shuffle();
+    let greek = proptest::option::of(prop_oneof![Just(RotorType::Beta), Just(RotorType::Gamma)]);
+    (walzen, greek, alphabet, plugs())
+        .prop_flat_map(|(walzen, greek, alphabet, plugs)| {
+            let rotors = (setting(walzen[0]), setting(walzen[1]), setting(walzen[2]));
+            let reflector = match greek {
+                Some(_) => prop_oneof![Just(ReflectorType::BThin), Just(ReflectorType::CThin)].boxed(),
+                None => prop_oneof![Just(ReflectorType::A), Just(ReflectorType::B), Just(ReflectorType::C)].boxed(),
+            };
+            let greek = match greek {
+                Some(kind) => setting(kind).prop_map(Some).boxed(),
+                None => Just(None).boxed(),
+            };
+            (rotors, greek, reflector, Just(plugs), Just(alphabet))
+        })
+        .prop_map(|((left, middle, right), greek, reflector, plugs, alphabet)| Settings {
+            greek,
+            rotors: [left, middle, right],
+            reflector,
+            plugs,
+            alphabet,
+        })
+}
+
+fn any_settings() -> impl Strategy<Value = Settings> {
+    settings(prop_oneof![Just(Alphabet::Classic), Just(Alphabet::Extended)])
+}
+
+// Mostly readable text, sometimes any Unicode at all
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        3 => "[A-Za-z0-9 .,;:!?'\n-]{0,200}",
+        1 => any::<String>(),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_encryption_is_an_involution(settings in any_settings(), plain in text()) {
+        let ciphertext = settings.machine().process_text(&plain);
+        prop_assert_eq!(settings.machine().process_text(&ciphertext), plain);
+    }
+
+    #[test]
+    fn test_no_letter_encrypts_to_itself(settings in classic(), plain in "[A-Za-z]{1,200}") {
+        let ciphertext = settings.machine().process_text(&plain);
+        for (p, c) in plain.chars().zip(ciphertext.chars()) {
+            prop_assert_ne!(p, c);
+        }
+    }
+
+    #[test]
+    fn test_no_symbol_encrypts_to_itself_extended(
+        settings in settings(Just(Alphabet::Extended)),
+        plain in "[ -~\t\n]{1,200}",
+    ) {
+        let ciphertext = settings.machine().process_text(&plain);
+        for (p, c) in plain.chars().zip(ciphertext.chars()) {
+            prop_assert_ne!(p, c);
+        }
+    }
+
+    #[test]
+    fn test_non_letters_and_case_preserved(settings in classic(), plain in text()) {
+        let ciphertext = settings.machine().process_text(&plain);
+        prop_assert_eq!(ciphertext.chars().count(), plain.chars().count());
+        for (p, c) in plain.chars().zip(ciphertext.chars()) {
+            if p.is_ascii_alphabetic() {
+                prop_assert!(c.is_ascii_alphabetic());
+                prop_assert_eq!(p.is_ascii_uppercase(), c.is_ascii_uppercase());
+            } else {
+                prop_assert_eq!(p, c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_state_follows_step_rotors(settings in any_settings(), plain in text()) {
+        let mut typed = settings.machine();
+        typed.process_text(&plain);
+        let mut stepped = settings.machine();
+        let presses = match settings.alphabet {
+            Alphabet::Classic => plain.chars().filter(char::is_ascii_alphabetic).count(),
+            Alphabet::Extended => plain.chars().filter(|&c| !c.is_ascii() || extended::index(c).is_some()).count(),
+        };
+        for _ in 0..presses {
+            stepped.step_rotors();
+        }
+        prop_assert_eq!(typed.state(), stepped.state());
+    }
+
+    #[test]
+    fn test_bytes_never_panic(settings in any_settings(), bytes in proptest::collection::vec(any::<u8>(), 0..200)) {
+        let mut machine = settings.machine();
+        let out: Vec<u8> = bytes.iter().map(|&b| machine.process_byte(b)).collect();
+        prop_assert_eq!(out.len(), bytes.len());
+        // Whatever comes in, text-level processing of the lossy decoding
+        // must not panic either
+        settings.machine().process_text(&String::from_utf8_lossy(&bytes));
+    }
+}
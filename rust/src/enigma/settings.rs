@@ -0,0 +1,266 @@
+// Machine configuration as a file, so tools and the app can share settings
+// instead of repeating constructor tuples:
+//
+//     reflector = "B"
+//     plugboard = "AV BS CG"
+//     rotors = [
+//         { rotor = "II", ring = "B", position = "B" },
+//         { rotor = "IV", ring = "U", position = "L" },
+//         { rotor = "V", ring = "L", position = "A" },
+//     ]
+//
+// An M4 adds `greek = { rotor = "Beta", ring = "A", position = "A" }` and a
+// thin reflector ("B-thin" or "C-thin"); `alphabet = "extended"` selects the
+// extended alphabet. Settings are validated when they are loaded.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Alphabet, EnigmaError, EnigmaMachine, ReflectorType, RotorType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotorSetting {
+    pub rotor: RotorType,
+    pub ring: char,
+    pub position: char,
+}
+
+impl RotorSetting {
+    pub fn new(rotor: RotorType, ring: char, position: char) -> Self {
+        RotorSetting { rotor, ring, position }
+    }
+
+    fn tuple(self) -> (RotorType, char, char) {
+        (self.rotor, self.ring, self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnigmaSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub greek: Option<RotorSetting>,
+    // Left to right
+    pub rotors: [RotorSetting; 3],
+    pub reflector: ReflectorType,
+    #[serde(default)]
+    pub plugboard: String,
+    #[serde(default)]
+    pub alphabet: Alphabet,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(std::io::Error),
+    Toml(String),
+    Json(String),
+    // Only .toml and .json files are understood
+    UnknownFormat(PathBuf),
+    Invalid(EnigmaError),
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "{e}"),
+            SettingsError::Toml(e) => write!(f, "invalid TOML settings: {e}"),
+            SettingsError::Json(e) => write!(f, "invalid JSON settings: {e}"),
+            SettingsError::UnknownFormat(p) => write!(f, "{}: expected a .toml or .json file", p.display()),
+            SettingsError::Invalid(e) => write!(f, "invalid Enigma settings: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<std::io::Error> for SettingsError {
+    fn from(e: std::io::Error) -> Self {
+        SettingsError::Io(e)
+    }
+}
+
+impl From<EnigmaError> for SettingsError {
+    fn from(e: EnigmaError) -> Self {
+        SettingsError::Invalid(e)
+    }
+}
+
+impl Default for EnigmaSettings {
+    // Rotors I, II, III at AAA with UKW-B and an empty plugboard
+    fn default() -> Self {
+        EnigmaSettings {
+            greek: None,
+            rotors: [
+                RotorSetting::new(RotorType::I, 'A', 'A'),
+                RotorSetting::new(RotorType::II, 'A', 'A'),
+                RotorSetting::new(RotorType::III, 'A', 'A'),
+            ],
+            reflector: ReflectorType::B,
+            plugboard: String::new(),
+            alphabet: Alphabet::Classic,
+        }
+    }
+}
+
+impl EnigmaSettings {
+    pub fn machine(&self) -> Result<EnigmaMachine, EnigmaError> {
+        let rotors = self.rotors.map(RotorSetting::tuple);
+        let machine = match self.greek {
+            Some(greek) => EnigmaMachine::try_new_m4(greek.tuple(), rotors, self.reflector, &self.plugboard)?,
+            None => EnigmaMachine::try_new(rotors, self.reflector, &self.plugboard)?,
+        };
+        Ok(machine.with_alphabet(self.alphabet))
+    }
+
+    pub fn validate(&self) -> Result<(), EnigmaError> {
+        self.machine().map(|_| ())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, SettingsError> {
+        let settings: Self = toml::from_str(text).map_err(|e| SettingsError::Toml(e.to_string()))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, SettingsError> {
+        let settings: Self = serde_json::from_str(text).map_err(|e| SettingsError::Json(e.to_string()))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("settings always serialize")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("settings always serialize")
+    }
+
+    // Format follows the file extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SettingsError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match Format::of(path)? {
+            Format::Toml => Self::from_toml(&text),
+            Format::Json => Self::from_json(&text),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SettingsError> {
+        let path = path.as_ref();
+        let text = match Format::of(path)? {
+            Format::Toml => self.to_toml(),
+            Format::Json => self.to_json(),
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, SettingsError> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("toml") => Ok(Format::Toml),
+            Some("json") => Ok(Format::Json),
+            _ => Err(SettingsError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m4() -> EnigmaSettings {
+        EnigmaSettings {
+            greek: Some(RotorSetting::new(RotorType::Beta, 'A', 'V')),
+            rotors: [
+                RotorSetting::new(RotorType::II, 'A', 'J'),
+                RotorSetting::new(RotorType::IV, 'A', 'N'),
+                RotorSetting::new(RotorType::I, 'V', 'A'),
+            ],
+            reflector: ReflectorType::BThin,
+            plugboard: "AT BL DF GJ HM NW OP QY RZ VX".into(),
+            alphabet: Alphabet::Classic,
+        }
+    }
+
+    #[test]
+    fn test_toml_and_json_round_trip() {
+        for settings in [EnigmaSettings::default(), m4()] {
+            assert_eq!(EnigmaSettings::from_toml(&settings.to_toml()).unwrap(), settings);
+            assert_eq!(EnigmaSettings::from_json(&settings.to_json()).unwrap(), settings);
+        }
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let settings = EnigmaSettings::from_toml(
+            r#"
+            reflector = "B"
+            plugboard = "AV BS CG DL FU HZ IN KM OW RX"
+            rotors = [
+                { rotor = "II", ring = "B", position = "B" },
+                { rotor = "IV", ring = "U", position = "L" },
+                { rotor = "V", ring = "L", position = "A" },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.alphabet, Alphabet::Classic);
+        assert_eq!(settings.machine().unwrap().process_text("EDPUDNRGYS"), "AUFKLXABTE");
+    }
+
+    #[test]
+    fn test_parse_json_m4() {
+        let json = r#"{
+            "greek": { "rotor": "Beta", "ring": "A", "position": "V" },
+            "rotors": [
+                { "rotor": "II", "ring": "A", "position": "J" },
+                { "rotor": "IV", "ring": "A", "position": "N" },
+                { "rotor": "I", "ring": "V", "position": "A" }
+            ],
+            "reflector": "B-thin",
+            "plugboard": "AT BL DF GJ HM NW OP QY RZ VX"
+        }"#;
+        assert_eq!(EnigmaSettings::from_json(json).unwrap(), m4());
+    }
+
+    #[test]
+    fn test_validation_on_load() {
+        let bad = EnigmaSettings { plugboard: "AB AC".into(), ..EnigmaSettings::default() };
+        assert!(matches!(
+            EnigmaSettings::from_toml(&bad.to_toml()),
+            Err(SettingsError::Invalid(EnigmaError::ReusedPlug('A')))
+        ));
+        let mut bad = m4();
+        bad.reflector = ReflectorType::B;
+        assert!(matches!(
+            EnigmaSettings::from_json(&bad.to_json()),
+            Err(SettingsError::Invalid(EnigmaError::ReflectorMismatch(ReflectorType::B)))
+        ));
+        assert!(matches!(EnigmaSettings::from_toml("reflector = \"Q\""), Err(SettingsError::Toml(_))));
+        assert!(matches!(EnigmaSettings::from_json("{\"extra\": 1}"), Err(SettingsError::Json(_))));
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = std::env::temp_dir().join(format!("enigma-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["m4.toml", "m4.json"] {
+            let path = dir.join(name);
+            m4().save(&path).unwrap();
+            assert_eq!(EnigmaSettings::load(&path).unwrap(), m4());
+        }
+        assert!(matches!(m4().save(dir.join("m4.yaml")), Err(SettingsError::UnknownFormat(_))));
+        assert!(matches!(EnigmaSettings::load(dir.join("missing.toml")), Err(SettingsError::Io(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use std::io::{self, Read, Write};
+
+use super::{Alphabet, EnigmaMachine};
+
+// In the extended alphabet non-ASCII scalars are enciphered too, and their
+// UTF-8 length can change, so the stream has to be decoded. A character split
+// across two reads or writes is held back until it is complete.
+#[derive(Default)]
+struct Utf8Transform {
+    pending: Vec<u8>,
+}
+
+impl Utf8Transform {
+    fn feed(&mut self, machine: &mut EnigmaMachine, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        self.pending.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "stream is not valid UTF-8")),
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("prefix was validated");
+        let mut encoded = [0u8; 4];
+        for c in text.chars() {
+            out.extend_from_slice(machine.process_char(c).encode_utf8(&mut encoded).as_bytes());
+        }
+        self.pending.drain(..valid);
+        Ok(())
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream ends inside a UTF-8 sequence"))
+        }
+    }
+}
+
+// Transforms everything read from `inner` as it streams through, with the
+// same pass-through and case rules as `EnigmaMachine::process_text`.
+pub struct EnigmaReader<R> {
+    inner: R,
+    machine: EnigmaMachine,
+    utf8: Utf8Transform,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> EnigmaReader<R> {
+    pub fn new(inner: R, machine: EnigmaMachine) -> Self {
+        EnigmaReader { inner, machine, utf8: Utf8Transform::default(), out: Vec::new(), pos: 0 }
+    }
+
+    pub fn machine(&self) -> &EnigmaMachine {
+        &self.machine
+    }
+
+    pub fn into_inner(self) -> (R, EnigmaMachine) {
+        (self.inner, self.machine)
+    }
+}
+
+impl<R: Read> Read for EnigmaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.machine.alphabet() == Alphabet::Classic {
+            let n = self.inner.read(buf)?;
+            for b in &mut buf[..n] {
+                *b = self.machine.process_byte(*b);
+            }
+            return Ok(n);
+        }
+        loop {
+            if self.pos < self.out.len() {
+                let n = buf.len().min(self.out.len() - self.pos);
+                buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            self.out.clear();
+            self.pos = 0;
+            let mut chunk = [0u8; 8192];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                self.utf8.finish()?;
+                return Ok(0);
+            }
+            self.utf8.feed(&mut self.machine, &chunk[..n], &mut self.out)?;
+        }
+    }
+}
+
+// Transforms everything written before passing it on to `inner`.
+pub struct EnigmaWriter<W: Write> {
+    inner: W,
+    machine: EnigmaMachine,
+    utf8: Utf8Transform,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EnigmaWriter<W> {
+    pub fn new(inner: W, machine: EnigmaMachine) -> Self {
+        EnigmaWriter { inner, machine, utf8: Utf8Transform::default(), buf: Vec::new() }
+    }
+
+    pub fn machine(&self) -> &EnigmaMachine {
+        &self.machine
+    }
+
+    pub fn into_inner(self) -> (W, EnigmaMachine) {
+        (self.inner, self.machine)
+    }
+
+    // Like `into_inner`, but fails if the data written so far ends inside a
+    // UTF-8 sequence that extended mode is still holding back.
+    pub fn finish(mut self) -> io::Result<(W, EnigmaMachine)> {
+        self.utf8.finish()?;
+        self.inner.flush()?;
+        Ok((self.inner, self.machine))
+    }
+}
+
+impl<W: Write> Write for EnigmaWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // The machine has already stepped once the bytes are transformed, so
+        // they have to reach `inner` in full rather than be retried.
+        self.buf.clear();
+        match self.machine.alphabet() {
+            Alphabet::Classic => self.buf.extend(data.iter().map(|&b| self.machine.process_byte(b))),
+            Alphabet::Extended => self.utf8.feed(&mut self.machine, data, &mut self.buf)?,
+        }
+        self.inner.write_all(&self.buf)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{ReflectorType, RotorType};
+
+    fn machine() -> EnigmaMachine {
+        EnigmaMachine::new(
+            [(RotorType::III, 'K', 'W'), (RotorType::VI, 'D', 'E'), (RotorType::VIII, 'P', 'Z')],
+            ReflectorType::C,
+            "AE IO UY",
+        )
+    }
+
+    const SCRIPT: &str = "(() => {\n  const idle = setTimeout(() => location.reload(), 10 * 60 * 1000);\n  // Zähler für Neustarts\n})();\n";
+
+    #[test]
+    fn test_reader_matches_process_text() {
+        let expected = machine().process_text(SCRIPT);
+        let mut reader = EnigmaReader::new(SCRIPT.as_bytes(), machine());
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_reader_small_reads() {
+        // Chunk boundaries, including ones inside a UTF-8 sequence, don't matter
+        let expected = machine().process_text(SCRIPT);
+        let mut reader = EnigmaReader::new(SCRIPT.as_bytes(), machine());
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 3];
+        loop {
+            let n = reader.read(&mut chunk).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&chunk[..n]);
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let mut writer = EnigmaWriter::new(Vec::new(), machine());
+        for line in SCRIPT.split_inclusive('\n') {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+        let (encrypted, _) = writer.into_inner();
+        assert_eq!(encrypted, machine().process_text(SCRIPT).into_bytes());
+
+        let mut decrypted = Vec::new();
+        io::copy(&mut EnigmaReader::new(&encrypted[..], machine()), &mut decrypted).unwrap();
+        assert_eq!(decrypted, SCRIPT.as_bytes());
+    }
+
+    #[test]
+    fn test_extended_reader_small_reads() {
+        let expected = machine().with_alphabet(Alphabet::Extended).process_text(SCRIPT);
+        let mut reader = EnigmaReader::new(SCRIPT.as_bytes(), machine().with_alphabet(Alphabet::Extended));
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 1];
+        while reader.read(&mut chunk).unwrap() == 1 {
+            out.push(chunk[0]);
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_extended_writer_split_characters() {
+        let mut writer = EnigmaWriter::new(Vec::new(), machine().with_alphabet(Alphabet::Extended));
+        for b in SCRIPT.as_bytes() {
+            writer.write_all(std::slice::from_ref(b)).unwrap();
+        }
+        let (encrypted, _) = writer.finish().unwrap();
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        assert_eq!(machine().with_alphabet(Alphabet::Extended).process_text(&encrypted), SCRIPT);
+    }
+
+    #[test]
+    fn test_extended_rejects_invalid_utf8() {
+        let mut reader = EnigmaReader::new(&b"ok\xff"[..], machine().with_alphabet(Alphabet::Extended));
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut reader = EnigmaReader::new(&b"ok\xc3"[..], machine().with_alphabet(Alphabet::Extended));
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut writer = EnigmaWriter::new(Vec::new(), machine().with_alphabet(Alphabet::Extended));
+        writer.write_all(b"ok\xc3").unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
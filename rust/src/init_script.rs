@@ -0,0 +1,63 @@
+// The script injected into the page: hooks that report a win or the Konami
+// code back over IPC, and the key blocking on the page side. The source is
+// init_script.js; it ships Enigma-encrypted with the settings in
+// init_script.toml, and with the sealed-script feature inside an encrypted,
+// signed payload container (see payload.rs) that has to verify against the
+// public key before anything is injected.
+
+use crate::enigma::EnigmaSettings;
+#[cfg(feature = "sealed-script")]
+use crate::payload::{self, PayloadError, KEY_LEN, PUBLIC_KEY_LEN};
+
+// Written by build.rs from init_script.js
+#[cfg(feature = "sealed-script")]
+const SEALED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/init_script.sealed"));
+#[cfg(feature = "sealed-script")]
+const PUBLIC_KEY: &[u8; PUBLIC_KEY_LEN] = include_bytes!(concat!(env!("OUT_DIR"), "/init_script.pub"));
+#[cfg(feature = "sealed-script")]
+const KEY: &[u8; KEY_LEN] = include_bytes!(concat!(env!("OUT_DIR"), "/init_script.key"));
+#[cfg(not(feature = "sealed-script"))]
+const ENCRYPTED: &str = include_str!(concat!(env!("OUT_DIR"), "/init_script.enc.js"));
+
+#[cfg(feature = "sealed-script")]
+pub fn load() -> Result<String, PayloadError> {
+    open_sealed(PUBLIC_KEY, KEY, SEALED)
+}
+
+#[cfg(not(feature = "sealed-script"))]
+pub fn load() -> Result<String, std::convert::Infallible> {
+    Ok(decrypt(ENCRYPTED))
+}
+
+#[cfg(feature = "sealed-script")]
+fn open_sealed(public_key: &[u8; PUBLIC_KEY_LEN], key: &[u8; KEY_LEN], blob: &[u8]) -> Result<String, PayloadError> {
+    let encrypted = payload::open(public_key, key, blob)?;
+    // Signed, so this is what build.rs wrote
+    Ok(decrypt(std::str::from_utf8(&encrypted).map_err(|_| PayloadError::Verification)?))
+}
+
+pub fn decrypt(encrypted: &str) -> String {
+    let mut enigma = EnigmaSettings::from_toml(include_str!("../init_script.toml"))
+        .and_then(|settings| Ok(settings.machine()?))
+        .expect("invalid init_script.toml");
+    enigma.process_text(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypts_to_script() {
+        assert_eq!(load().unwrap(), include_str!("../init_script.js"));
+    }
+
+    #[cfg(feature = "sealed-script")]
+    #[test]
+    fn test_tampered_script_is_refused() {
+        assert!(!SEALED.windows(6).any(|w| w == b"window"));
+        let mut patched = SEALED.to_vec();
+        patched[10] ^= 1;
+        assert_eq!(open_sealed(PUBLIC_KEY, KEY, &patched), Err(PayloadError::Verification));
+    }
+}
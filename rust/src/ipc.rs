@@ -0,0 +1,37 @@
+// Messages the injected script posts through `window.ipc.postMessage`, as
+// JSON objects like {"event": "won"}.
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpcEvent {
+    // The fight was won; the window closes
+    Won,
+    // Konami code entered; the app exits at once
+    Konami,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    event: IpcEvent,
+}
+
+// Anything that isn't a known event is ignored.
+pub fn parse(msg: &str) -> Option<IpcEvent> {
+    serde_json::from_str::<Message>(msg).ok().map(|m| m.event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(r#"{"event":"won"}"#), Some(IpcEvent::Won));
+        assert_eq!(parse(r#"{"event": "konami", "at": 12}"#), Some(IpcEvent::Konami));
+        assert_eq!(parse(r#"{"event":"lost"}"#), None);
+        assert_eq!(parse("won"), None);
+        assert_eq!(parse("{}"), None);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod attack;
+pub mod config;
+pub mod enigma;
+pub mod init_script;
+pub mod ipc;
+pub mod machines;
+pub mod payload;
@@ -0,0 +1,225 @@
+// One interface over the rotor and wheel machines, so they can be driven and
+// compared side by side: the Enigma, the British Typex, the American SIGABA
+// and the Lorenz SZ40/42 teleprinter attachment.
+//
+// Machines with a reflector and the Lorenz (which adds key with XOR) undo
+// themselves, so decrypting is encrypting from the same start. SIGABA runs
+// its cipher rotors the other way round to decrypt.
+
+pub mod lorenz;
+pub mod sigaba;
+pub mod typex;
+
+pub use lorenz::{Lorenz, LorenzModel};
+pub use sigaba::Sigaba;
+pub use typex::{Typex, TypexRotor};
+
+use crate::enigma::{EnigmaError, EnigmaMachine};
+
+pub trait RotorMachine {
+    fn name(&self) -> &'static str;
+
+    // Rotor or wheel positions as the operator would read them
+    fn window(&self) -> String;
+
+    // Characters outside the machine's alphabet pass through without moving
+    // anything.
+    fn encipher_char(&mut self, c: char) -> char;
+
+    fn decipher_char(&mut self, c: char) -> char {
+        self.encipher_char(c)
+    }
+
+    fn encrypt(&mut self, text: &str) -> String {
+        text.chars().map(|c| self.encipher_char(c)).collect()
+    }
+
+    fn decrypt(&mut self, text: &str) -> String {
+        text.chars().map(|c| self.decipher_char(c)).collect()
+    }
+}
+
+impl RotorMachine for EnigmaMachine {
+    fn name(&self) -> &'static str {
+        "Enigma"
+    }
+
+    fn window(&self) -> String {
+        EnigmaMachine::window(self)
+    }
+
+    fn encipher_char(&mut self, c: char) -> char {
+        self.process_char(c)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    // Not a permutation of the rotor's alphabet
+    InvalidWiring(String),
+    // A reflector has to pair every contact with another one
+    InvalidReflector(String),
+    InvalidPosition(char),
+    InvalidPins { wheel: String, expected: usize, found: usize },
+    // A pin that is neither a cross nor a dot
+    InvalidPin { wheel: String, pin: char },
+    InvalidWheelPosition { wheel: String, position: usize },
+    Enigma(EnigmaError),
+}
+
+impl std::fmt::Display for MachineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineError::InvalidWiring(w) => write!(f, "invalid rotor wiring {w:?}"),
+            MachineError::InvalidReflector(w) => write!(f, "invalid reflector wiring {w:?}"),
+            MachineError::InvalidPosition(c) => write!(f, "invalid rotor position {c:?}"),
+            MachineError::InvalidPins { wheel, expected, found } => {
+                write!(f, "wheel {wheel} has {expected} pins, got {found}")
+            }
+            MachineError::InvalidPin { wheel, pin } => write!(f, "wheel {wheel} has invalid pin {pin:?}"),
+            MachineError::InvalidWheelPosition { wheel, position } => {
+                write!(f, "wheel {wheel} has no position {position}")
+            }
+            MachineError::Enigma(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+impl From<EnigmaError> for MachineError {
+    fn from(e: EnigmaError) -> Self {
+        MachineError::Enigma(e)
+    }
+}
+
+// A wired rotor over an alphabet of `n` contacts (26 letters or SIGABA's ten
+// index digits), as forward and inverse tables.
+#[derive(Debug, Clone)]
+struct Rotor {
+    forward: Vec<u8>,
+    backward: Vec<u8>,
+    position: usize,
+    ring: usize,
+}
+
+impl Rotor {
+    // `wiring` lists where each contact of `alphabet` leads. A reversed rotor
+    // is the same core turned round: entered from the other side and read
+    // with its contacts mirrored.
+    fn new(wiring: &str, alphabet: &str, reversed: bool) -> Result<Self, MachineError> {
+        let invalid = || MachineError::InvalidWiring(wiring.to_string());
+        let n = alphabet.len();
+        let mut forward = Vec::with_capacity(n);
+        for c in wiring.chars() {
+            forward.push(alphabet.find(c.to_ascii_uppercase()).ok_or_else(invalid)? as u8);
+        }
+        let mut backward = vec![u8::MAX; n];
+        for (i, &f) in forward.iter().enumerate() {
+            if backward[f as usize] != u8::MAX {
+                return Err(invalid());
+            }
+            backward[f as usize] = i as u8;
+        }
+        if forward.len() != n {
+            return Err(invalid());
+        }
+        if reversed {
+            let mirror = |i: usize| (n - i) % n;
+            let turned: Vec<u8> = (0..n).map(|i| mirror(backward[mirror(i)] as usize) as u8).collect();
+            backward = vec![0; n];
+            for (i, &f) in turned.iter().enumerate() {
+                backward[f as usize] = i as u8;
+            }
+            forward = turned;
+        }
+        Ok(Rotor { forward, backward, position: 0, ring: 0 })
+    }
+
+    fn len(&self) -> usize {
+        self.forward.len()
+    }
+
+    fn offset(&self) -> usize {
+        (self.position + self.len() - self.ring) % self.len()
+    }
+
+    fn forward(&self, i: usize) -> usize {
+        let (n, offset) = (self.len(), self.offset());
+        (self.forward[(i + offset) % n] as usize + n - offset) % n
+    }
+
+    fn backward(&self, i: usize) -> usize {
+        let (n, offset) = (self.len(), self.offset());
+        (self.backward[(i + offset) % n] as usize + n - offset) % n
+    }
+
+    fn step(&mut self) {
+        self.position = (self.position + 1) % self.len();
+    }
+}
+
+fn letter_index(c: char) -> Option<usize> {
+    c.is_ascii_alphabetic().then(|| (c.to_ascii_uppercase() as u8 - b'A') as usize)
+}
+
+fn letter(i: usize) -> char {
+    (i as u8 + b'A') as char
+}
+
+const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{ReflectorType, RotorType};
+
+    #[test]
+    fn test_reversed_rotor() {
+        let rotor = Rotor::new(RotorType::I.wiring(), LETTERS, false).unwrap();
+        let turned = Rotor::new(RotorType::I.wiring(), LETTERS, true).unwrap();
+        for i in 0..26 {
+            assert_eq!(turned.forward(i), (26 - rotor.backward((26 - i) % 26)) % 26);
+            assert_eq!(turned.backward(turned.forward(i)), i);
+        }
+        assert!(Rotor::new("ABC", LETTERS, false).is_err());
+        assert!(Rotor::new("AACDEFGHIJKLMNOPQRSTUVWXYZ", LETTERS, false).is_err());
+    }
+
+    #[test]
+    fn test_machines_through_one_interface() {
+        let settings = [(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'A'), (RotorType::III, 'A', 'A')];
+        let stators = || TypexRotor::new(LETTERS, "").unwrap();
+        let typex_rotors = || {
+            [
+                (stators(), 'A', 'A'),
+                (stators(), 'A', 'A'),
+                (TypexRotor::enigma(RotorType::I), 'A', 'A'),
+                (TypexRotor::enigma(RotorType::II), 'A', 'A'),
+                (TypexRotor::enigma(RotorType::III), 'A', 'A'),
+            ]
+        };
+        let machines = || -> Vec<Box<dyn RotorMachine>> {
+            vec![
+                Box::new(EnigmaMachine::new(settings, ReflectorType::B, "")),
+                Box::new(Typex::new(typex_rotors(), ReflectorType::B.wiring(), "").unwrap()),
+                Box::new(sigaba::tests::machine()),
+                Box::new(lorenz::tests::machine(LorenzModel::SZ42A)),
+            ]
+        };
+        let text = "ATTACK AT DAWN";
+        let names: Vec<&str> = machines().iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["Enigma", "Typex", "SIGABA", "Lorenz SZ42A"]);
+        for (mut enc, mut dec) in machines().into_iter().zip(machines()) {
+            let start = enc.window();
+            let ciphertext = enc.encrypt(text);
+            assert_ne!(ciphertext, text, "{}", enc.name());
+            assert_ne!(enc.window(), start, "{}", enc.name());
+            assert_eq!(dec.decrypt(&ciphertext), text, "{}", dec.name());
+        }
+        // A Typex with straight-through stators and Enigma rotors is an Enigma
+        let mut enigma: Box<dyn RotorMachine> = Box::new(EnigmaMachine::new(settings, ReflectorType::B, ""));
+        let mut typex = Typex::new(typex_rotors(), ReflectorType::B.wiring(), "").unwrap();
+        assert_eq!(typex.encrypt(text), enigma.encrypt(text));
+    }
+}
@@ -0,0 +1,318 @@
+// Lorenz SZ40/42, the teleprinter cipher attachment Bletchley Park called
+// Tunny. Each 5-bit teleprinter character is added (XOR) to a key character
+// made by two sets of five pin wheels, the chi and the psi wheels. The chis
+// move on every character; the psis move together only when the motor
+// wheels say so:
+//
+// - mu61 moves on every character;
+// - mu37 moves when mu61 showed a cross;
+// - the psis move when mu37 showed a cross (the basic motor), except that on
+//   the SZ42 a cross in the limitation holds them still. The SZ42A limits on
+//   chi2 one character back, the SZ42B on chi2 one back added to psi1 two
+//   back.
+//
+// Characters are in Bletchley Park notation: A-Z, and / 9 3 4 5 8 for null,
+// space, carriage return, line feed, figure shift and letter shift. Pin
+// patterns use x (or X or 1) for a cross and . (or 0) for a dot; whitespace
+// between pins is ignored.
+//
+// The tests check the key stream against cases worked out by hand from the
+// description above. No published message with its full pin and start
+// settings is checked here yet, so they test this model and not the machine.
+
+use super::{MachineError, RotorMachine};
+
+pub const CHI_PINS: [usize; 5] = [41, 31, 29, 26, 23];
+pub const PSI_PINS: [usize; 5] = [43, 47, 51, 53, 59];
+pub const MU_PINS: [usize; 2] = [61, 37];
+
+// ITA2 codes, first impulse in the highest bit, in order of value
+const BAUDOT: &str = "/T3O9HNM4LRGIPCVEZDBSYFXAWJ5UQK8";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LorenzModel {
+    SZ40,
+    SZ42A,
+    SZ42B,
+}
+
+#[derive(Debug, Clone)]
+struct Wheel {
+    name: String,
+    pins: Vec<bool>,
+    position: usize,
+}
+
+impl Wheel {
+    fn new(name: String, pattern: &str, expected: usize) -> Result<Self, MachineError> {
+        let pins = pattern
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                'x' | 'X' | '1' => Ok(true),
+                '.' | '0' => Ok(false),
+                _ => Err(MachineError::InvalidPin { wheel: name.clone(), pin: c }),
+            })
+            .collect::<Result<Vec<bool>, _>>()?;
+        if pins.len() != expected {
+            return Err(MachineError::InvalidPins { wheel: name, expected, found: pins.len() });
+        }
+        Ok(Wheel { name, pins, position: 0 })
+    }
+
+    fn bit(&self) -> bool {
+        self.pins[self.position]
+    }
+
+    fn step(&mut self) {
+        self.position = (self.position + 1) % self.pins.len();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lorenz {
+    model: LorenzModel,
+    chi: [Wheel; 5],
+    psi: [Wheel; 5],
+    // mu61, mu37
+    mu: [Wheel; 2],
+    // chi2 one back, psi1 one and two back, for the limitation
+    last_chi2: bool,
+    last_psi1: [bool; 2],
+}
+
+impl Lorenz {
+    // Pin patterns per wheel, all wheels at their first pin.
+    pub fn new(model: LorenzModel, chi: [&str; 5], psi: [&str; 5], mu: [&str; 2]) -> Result<Self, MachineError> {
+        let bank = |prefix: &str, patterns: &[&str], sizes: &[usize]| -> Result<Vec<Wheel>, MachineError> {
+            patterns.iter().zip(sizes).enumerate().map(|(i, (p, &n))| Wheel::new(format!("{prefix}{}", i + 1), p, n)).collect()
+        };
+        let chi = bank("chi", &chi, &CHI_PINS)?.try_into().expect("five wheels");
+        let psi = bank("psi", &psi, &PSI_PINS)?.try_into().expect("five wheels");
+        let mu = [Wheel::new("mu61".into(), mu[0], MU_PINS[0])?, Wheel::new("mu37".into(), mu[1], MU_PINS[1])?];
+        Ok(Lorenz {
+            model,
+            chi,
+            psi,
+            mu,
+            last_chi2: false,
+            last_psi1: [false; 2],
+        })
+    }
+
+    // Start positions, counted from 1 as on the wheels: chi1-5, psi1-5,
+    // mu61 and mu37.
+    pub fn with_positions(mut self, positions: [usize; 12]) -> Result<Self, MachineError> {
+        let wheels = self.chi.iter_mut().chain(self.psi.iter_mut()).chain(self.mu.iter_mut());
+        for (wheel, &position) in wheels.zip(&positions) {
+            if position == 0 || position > wheel.pins.len() {
+                return Err(MachineError::InvalidWheelPosition { wheel: wheel.name.clone(), position });
+            }
+            wheel.position = position - 1;
+        }
+        Ok(self)
+    }
+
+    fn key(&self) -> u8 {
+        let chi = self.chi.iter().fold(0, |k, w| k << 1 | w.bit() as u8);
+        let psi = self.psi.iter().fold(0, |k, w| k << 1 | w.bit() as u8);
+        chi ^ psi
+    }
+
+    fn step(&mut self) {
+        let limitation = match self.model {
+            LorenzModel::SZ40 => false,
+            LorenzModel::SZ42A => self.last_chi2,
+            LorenzModel::SZ42B => self.last_chi2 ^ self.last_psi1[1],
+        };
+        let basic_motor = self.mu[1].bit();
+        let psis_move = basic_motor && !limitation;
+
+        self.last_chi2 = self.chi[1].bit();
+        self.last_psi1 = [self.psi[0].bit(), self.last_psi1[0]];
+
+        if self.mu[0].bit() {
+            self.mu[1].step();
+        }
+        self.mu[0].step();
+        if psis_move {
+            for wheel in &mut self.psi {
+                wheel.step();
+            }
+        }
+        for wheel in &mut self.chi {
+            wheel.step();
+        }
+    }
+}
+
+impl RotorMachine for Lorenz {
+    fn name(&self) -> &'static str {
+        match self.model {
+            LorenzModel::SZ40 => "Lorenz SZ40",
+            LorenzModel::SZ42A => "Lorenz SZ42A",
+            LorenzModel::SZ42B => "Lorenz SZ42B",
+        }
+    }
+
+    fn window(&self) -> String {
+        let wheels = self.chi.iter().chain(self.psi.iter()).chain(self.mu.iter());
+        wheels.map(|w| format!("{:02}", w.position + 1)).collect::<Vec<_>>().join(" ")
+    }
+
+    // Teleprinters have no case: lower case letters go in as upper case.
+    fn encipher_char(&mut self, c: char) -> char {
+        let Some(code) = BAUDOT.find(c.to_ascii_uppercase()) else {
+            return c;
+        };
+        let out = code as u8 ^ self.key();
+        self.step();
+        BAUDOT.as_bytes()[out as usize] as char
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    fn pattern(n: usize, period: usize) -> String {
+        (0..n).map(|i| if i % period == 0 || i % 3 == 1 { 'x' } else { '.' }).collect()
+    }
+
+    pub(crate) fn machine(model: LorenzModel) -> Lorenz {
+        let chi = CHI_PINS.map(|n| pattern(n, 5));
+        let psi = PSI_PINS.map(|n| pattern(n, 7));
+        let mu = MU_PINS.map(|n| pattern(n, 2));
+        Lorenz::new(model, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str)).unwrap()
+    }
+
+    fn single_pin(n: usize) -> String {
+        (0..n).map(|i| if i == 0 { 'x' } else { '.' }).collect()
+    }
+
+    #[test]
+    fn test_baudot_table() {
+        // ITA2 values worked out by hand: E is 10000, T 00001, space 00100
+        assert_eq!(BAUDOT.find('E'), Some(0b10000));
+        assert_eq!(BAUDOT.find('T'), Some(0b00001));
+        assert_eq!(BAUDOT.find('9'), Some(0b00100));
+        assert_eq!(BAUDOT.find('8'), Some(0b11111));
+        assert_eq!(BAUDOT.find('5'), Some(0b11011));
+    }
+
+    #[test]
+    fn test_regression_single_pin() {
+        // Only the first pin of chi1 is a cross, and the psis never move
+        // because mu37 is all dots: the key is E (10000) once, then null.
+        // E + E = null, T + E = Z (10001), / + E = E.
+        let dots = |n| ".".repeat(n);
+        let mut chi = CHI_PINS.map(dots);
+        chi[0] = single_pin(CHI_PINS[0]);
+        let psi = PSI_PINS.map(dots);
+        let mu = MU_PINS.map(dots);
+        let machine = || {
+            Lorenz::new(LorenzModel::SZ40, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str))
+                .unwrap()
+        };
+        assert_eq!(machine().encrypt("EEE"), "/EE");
+        assert_eq!(machine().encrypt("T"), "Z");
+        assert_eq!(machine().encrypt("/"), "E");
+        // chi1 comes round again after 41 characters
+        assert_eq!(machine().encrypt(&"/".repeat(42)), format!("E{}E", "/".repeat(40)));
+    }
+
+    #[test]
+    fn test_motor_wheels() {
+        // mu61 all crosses: mu37 moves every time; mu37 all crosses: the psis
+        // move every time, so psi1's single cross is keyed once per 43
+        let dots = |n| ".".repeat(n);
+        let crosses = |n| "x".repeat(n);
+        let chi = CHI_PINS.map(dots);
+        let mut psi = PSI_PINS.map(dots);
+        psi[0] = single_pin(PSI_PINS[0]);
+        let mu = MU_PINS.map(crosses);
+        let mut lorenz =
+            Lorenz::new(LorenzModel::SZ40, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str))
+                .unwrap();
+        assert_eq!(lorenz.encrypt(&"/".repeat(44)), format!("E{}E", "/".repeat(42)));
+        assert_eq!(lorenz.window(), "04 14 16 19 22 02 45 45 45 45 45 08");
+
+        // Same wheels with mu37 all dots: psi1 stays on its cross
+        let mu = [crosses(61), dots(37)];
+        let mut stuck =
+            Lorenz::new(LorenzModel::SZ40, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str))
+                .unwrap();
+        assert_eq!(stuck.encrypt("////"), "EEEE");
+    }
+
+    #[test]
+    fn test_limitation() {
+        // The motor always says move, chi2 has one cross at pin 1 and psi1
+        // crosses at pins 2 and 3. The SZ40 reads psi1 pins 1-5. The SZ42A
+        // holds the psis once, after chi2's cross, so psi1 pin 2 is read
+        // twice. The SZ42B also holds when chi2 one back (dot) and psi1 two
+        // back (cross, pin 2) add to a cross, reading pin 3 twice as well.
+        // chi2 alone keys 01000 (4) and psi1 alone 10000 (E).
+        let dots = |n| ".".repeat(n);
+        let mut chi = CHI_PINS.map(dots);
+        chi[1] = single_pin(CHI_PINS[1]);
+        let mut psi = PSI_PINS.map(dots);
+        psi[0] = format!(".xx{}", dots(PSI_PINS[0] - 3));
+        let mu = MU_PINS.map(|n| "x".repeat(n));
+        let encrypt = |model| {
+            Lorenz::new(model, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str))
+                .unwrap()
+                .encrypt("/////")
+        };
+        assert_eq!(encrypt(LorenzModel::SZ40), "4EE//");
+        assert_eq!(encrypt(LorenzModel::SZ42A), "4EEE/");
+        assert_eq!(encrypt(LorenzModel::SZ42B), "4EEEE");
+    }
+
+    #[test]
+    fn test_models_differ_and_decrypt() {
+        let plain = "ATTACK9AT9DAWN34";
+        let mut ciphertexts = Vec::new();
+        for model in [LorenzModel::SZ40, LorenzModel::SZ42A, LorenzModel::SZ42B] {
+            let ciphertext = machine(model).encrypt(plain);
+            assert_eq!(machine(model).decrypt(&ciphertext), plain);
+            ciphertexts.push(ciphertext);
+        }
+        assert_ne!(ciphertexts[0], ciphertexts[1]);
+        assert_ne!(ciphertexts[1], ciphertexts[2]);
+        // Outside the teleprinter alphabet nothing moves
+        let mut lorenz = machine(LorenzModel::SZ40);
+        assert_eq!(lorenz.encrypt("!?"), "!?");
+        assert_eq!(lorenz.window(), machine(LorenzModel::SZ40).window());
+    }
+
+    #[test]
+    fn test_positions_and_pins() {
+        let lorenz = machine(LorenzModel::SZ40).with_positions([2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]).unwrap();
+        assert_eq!(lorenz.window(), "02 03 04 05 06 07 08 09 10 11 12 13");
+        assert_eq!(
+            machine(LorenzModel::SZ40).with_positions([1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0]).unwrap_err(),
+            MachineError::InvalidWheelPosition { wheel: "mu37".into(), position: 0 }
+        );
+        assert!(machine(LorenzModel::SZ40).with_positions([42, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]).is_err());
+        let chi = ["x"; 5];
+        let err = Lorenz::new(LorenzModel::SZ40, chi, chi, ["x"; 2]).unwrap_err();
+        assert_eq!(err, MachineError::InvalidPins { wheel: "chi1".into(), expected: 41, found: 1 });
+    }
+
+    #[test]
+    fn test_pin_characters() {
+        // Every accepted character, in groups of five
+        let spaced = |n: usize| (0..n).map(|i| format!("{}{}", ['x', '.', '1', '0', 'X'][i % 5], if i % 5 == 4 { " " } else { "" })).collect::<String>();
+        let chi = CHI_PINS.map(spaced);
+        let psi = PSI_PINS.map(spaced);
+        let mu = MU_PINS.map(spaced);
+        assert!(Lorenz::new(LorenzModel::SZ40, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str)).is_ok());
+        let mut psi = psi;
+        psi[2] = format!("x.q?{}", ".".repeat(PSI_PINS[2] - 4));
+        let err = Lorenz::new(LorenzModel::SZ40, chi.each_ref().map(String::as_str), psi.each_ref().map(String::as_str), mu.each_ref().map(String::as_str))
+            .unwrap_err();
+        assert_eq!(err, MachineError::InvalidPin { wheel: "psi3".into(), pin: 'q' });
+        assert_eq!(err.to_string(), "wheel psi3 has invalid pin 'q'");
+    }
+}
@@ -0,0 +1,252 @@
+// SIGABA (ECM Mark II), the US machine that moved its cipher rotors
+// irregularly. Three banks of five rotors:
+//
+// - cipher rotors, which encipher the letter and step only when the index
+//   bank tells them to;
+// - control rotors, whose middle three step like an odometer (the centre one
+//   every letter, the one to its right when the centre one passes O, the one
+//   to its left when that one does) and whose outputs drive the index bank;
+// - index rotors with ten contacts, set by hand and never moving.
+//
+// Four contacts, F, G, H and I, are live at the control bank's input. Its 26
+// outputs are bundled into the index inputs (A to 9, B to 1, C to 2, DE to 3,
+// FGH to 4, IJK to 5, LMNO to 6, P-T to 7, U-Z to 8), and the index outputs
+// in pairs choose the cipher rotors to step, so one to four of them move on
+// every letter.
+//
+// The machine had no space bar: space is typed as Z, which deciphers back to
+// a space, and a plaintext Z goes out as X.
+//
+// `ROTORS` and `INDEX_ROTORS` are the wirings of the surviving rotor set as
+// tabulated by Stamp and Chan, "SIGABA: Cryptanalysis of the Full Keyspace",
+// Cryptologia 31(3), 2007. Any five of the ten go in the cipher bank and the
+// other five in the control bank.
+//
+// Only the rotor wirings come from there. The tests trace letters through
+// them by hand and keep this model's own output as a regression vector; no
+// published plaintext and ciphertext pair with its settings is checked yet.
+
+use super::{letter, letter_index, MachineError, RotorMachine, Rotor, LETTERS};
+
+pub const ROTORS: [&str; 10] = [
+    "YCHLQSUGBDIXNZKERPVJTAWFOM",
+    "INPXBWETGUYSAOCHVLDMQKZJFR",
+    "WNDRIOZPTAXHFJYQBMSVEKUCGL",
+    "TZGHOBKRVUXLQDMPNFWCJYEIAS",
+    "YWTAHRQJVLCEXUNGBIPZMSDFOK",
+    "QSLRBTEKOGAICFWYVMHJNXZUDP",
+    "CHJDQIGNBSAKVTUOXFWLEPRMZY",
+    "CDFAJXTIMNBEQHSUGRYLWZKVPO",
+    "XHFESZDNRBCGKQIJLTVMUOYAPW",
+    "EZJQXMOGYTCSFRIUPVNADLHWBK",
+];
+pub const INDEX_ROTORS: [&str; 5] = ["7591482630", "3810592764", "4086153297", "3980526174", "6497135280"];
+
+const DIGITS: &str = "0123456789";
+const LIVE_CONTROL_INPUTS: [usize; 4] = [5, 6, 7, 8];
+const INDEX_INPUT: [u8; 26] = [9, 1, 2, 3, 3, 4, 4, 4, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7, 7, 8, 8, 8, 8, 8, 8];
+// Cipher rotor moved by each index output
+const CIPHER_ROTOR: [usize; 10] = [0, 4, 4, 3, 3, 2, 2, 1, 1, 0];
+// Carry position of the fast and medium control rotors
+const CONTROL_CARRY: usize = b'O' as usize - b'A' as usize;
+
+#[derive(Debug, Clone)]
+pub struct Sigaba {
+    cipher: [Rotor; 5],
+    control: [Rotor; 5],
+    index: [Rotor; 5],
+}
+
+impl Sigaba {
+    // Cipher and control rotors as (wiring, inserted reversed, position),
+    // index rotors as (digit wiring, position digit), each bank left to right.
+    pub fn new(
+        cipher: [(&str, bool, char); 5],
+        control: [(&str, bool, char); 5],
+        index: [(&str, char); 5],
+    ) -> Result<Self, MachineError> {
+        let bank = |rotors: [(&str, bool, char); 5]| -> Result<[Rotor; 5], MachineError> {
+            let mut placed = Vec::with_capacity(5);
+            for (wiring, reversed, position) in rotors {
+                let mut rotor = Rotor::new(wiring, LETTERS, reversed)?;
+                rotor.position = letter_index(position).ok_or(MachineError::InvalidPosition(position))?;
+                placed.push(rotor);
+            }
+            Ok(placed.try_into().expect("five rotors"))
+        };
+        let mut index_bank = Vec::with_capacity(5);
+        for (wiring, position) in index {
+            let mut rotor = Rotor::new(wiring, DIGITS, false)?;
+            rotor.position = position.to_digit(10).ok_or(MachineError::InvalidPosition(position))? as usize;
+            index_bank.push(rotor);
+        }
+        Ok(Sigaba { cipher: bank(cipher)?, control: bank(control)?, index: index_bank.try_into().expect("five rotors") })
+    }
+
+    // Cipher rotors the maze steps at the current control positions
+    fn stepping(&self) -> [bool; 5] {
+        let mut step = [false; 5];
+        for input in LIVE_CONTROL_INPUTS {
+            let out = self.control.iter().fold(input, |i, rotor| rotor.forward(i));
+            let index = self.index.iter().fold(INDEX_INPUT[out] as usize, |i, rotor| rotor.forward(i));
+            step[CIPHER_ROTOR[index]] = true;
+        }
+        step
+    }
+
+    fn step(&mut self) {
+        let stepping = self.stepping();
+        for (rotor, step) in self.cipher.iter_mut().zip(stepping) {
+            if step {
+                rotor.step();
+            }
+        }
+        let fast_carries = self.control[2].position == CONTROL_CARRY;
+        let medium_carries = fast_carries && self.control[3].position == CONTROL_CARRY;
+        self.control[2].step();
+        if fast_carries {
+            self.control[3].step();
+        }
+        if medium_carries {
+            self.control[1].step();
+        }
+    }
+}
+
+impl RotorMachine for Sigaba {
+    fn name(&self) -> &'static str {
+        "SIGABA"
+    }
+
+    // Cipher, control and index bank
+    fn window(&self) -> String {
+        let letters = |bank: &[Rotor; 5]| bank.iter().map(|r| letter(r.position)).collect::<String>();
+        let digits: String = self.index.iter().map(|r| DIGITS.as_bytes()[r.position] as char).collect();
+        format!("{} {} {}", letters(&self.cipher), letters(&self.control), digits)
+    }
+
+    // Letters come out in upper case.
+    fn encipher_char(&mut self, c: char) -> char {
+        let i = match c.to_ascii_uppercase() {
+            ' ' => 25,
+            'Z' => 23,
+            c => match letter_index(c) {
+                Some(i) => i,
+                None => return c,
+            },
+        };
+        let out = self.cipher.iter().fold(i, |i, rotor| rotor.forward(i));
+        self.step();
+        letter(out)
+    }
+
+    fn decipher_char(&mut self, c: char) -> char {
+        let Some(i) = letter_index(c) else {
+            return c;
+        };
+        let out = self.cipher.iter().rev().fold(i, |i, rotor| rotor.backward(i));
+        self.step();
+        if out == 25 { ' ' } else { letter(out) }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::enigma::ReflectorType;
+
+    // Rotors 0-4 in the cipher bank, 5-9 in the control bank
+    pub(crate) fn machine() -> Sigaba {
+        let [c0, c1, c2, c3, c4, k0, k1, k2, k3, k4] = ROTORS;
+        let [i0, i1, i2, i3, i4] = INDEX_ROTORS;
+        Sigaba::new(
+            [(c0, false, 'A'), (c1, true, 'B'), (c2, false, 'C'), (c3, false, 'D'), (c4, true, 'E')],
+            [(k0, false, 'F'), (k1, false, 'G'), (k2, false, 'M'), (k3, true, 'I'), (k4, false, 'J')],
+            [(i0, '0'), (i1, '1'), (i2, '2'), (i3, '3'), (i4, '4')],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cipher_path() {
+        // The first letter goes through the cipher bank before anything
+        // steps, so it follows from the wiring table alone: with rotors 0-4
+        // straight in at A, A -> Y -> F -> O -> M -> X and T -> J -> U -> E
+        // -> O -> N.
+        let [c0, c1, c2, c3, c4, k0, k1, k2, k3, k4] = ROTORS;
+        let straight = |bank: [&'static str; 5]| bank.map(|w| (w, false, 'A'));
+        let sigaba = Sigaba::new(straight([c0, c1, c2, c3, c4]), straight([k0, k1, k2, k3, k4]), INDEX_ROTORS.map(|w| (w, '0'))).unwrap();
+        assert_eq!(sigaba.clone().encrypt("A"), "X");
+        assert_eq!(sigaba.clone().encrypt("T"), "N");
+        assert_eq!(sigaba.clone().decrypt("X"), "A");
+    }
+
+    // Not a published vector: this implementation's own output, kept so a
+    // change to the maze or the stepping order shows up
+    #[test]
+    fn test_regression() {
+        let ciphertext = machine().encrypt("ATTACK AT DAWN ZULU");
+        assert_eq!(ciphertext, REGRESSION_CIPHERTEXT);
+        assert_eq!(machine().decrypt(&ciphertext), "ATTACK AT DAWN XULU");
+    }
+
+    const REGRESSION_CIPHERTEXT: &str = "BZWMYIMXKOZRKYEDJRY";
+
+    #[test]
+    fn test_one_to_four_cipher_rotors_step() {
+        let mut sigaba = machine();
+        let mut counts = [0; 5];
+        for _ in 0..26 * 26 * 3 {
+            let step = sigaba.stepping();
+            let moving = step.iter().filter(|&&s| s).count();
+            assert!((1..=4).contains(&moving), "{moving} rotors at {}", sigaba.window());
+            for (count, s) in counts.iter_mut().zip(step) {
+                *count += s as usize;
+            }
+            sigaba.step();
+        }
+        // Every cipher rotor gets its turn
+        assert!(counts.iter().all(|&c| c > 0), "{counts:?}");
+    }
+
+    #[test]
+    fn test_control_rotors_count() {
+        let mut sigaba = machine();
+        // Fast rotor at M: two presses to pass O, then 26 more for the next carry
+        for _ in 0..3 {
+            sigaba.encipher_char('A');
+        }
+        assert_eq!(&sigaba.window()[6..11], "FGPJJ");
+        for _ in 0..26 {
+            sigaba.encipher_char('A');
+        }
+        assert_eq!(&sigaba.window()[6..11], "FGPKJ");
+    }
+
+    #[test]
+    fn test_not_reciprocal() {
+        let plain = "THE QUICK BROWN FOX";
+        let ciphertext = machine().encrypt(plain);
+        assert_ne!(machine().encrypt(&ciphertext), plain);
+        assert_eq!(machine().decrypt(&ciphertext), plain);
+        assert!(!ciphertext.contains(' '));
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let w = ReflectorType::B.wiring();
+        let index = [("0123456789", '0'); 5];
+        assert_eq!(
+            Sigaba::new([(w, false, 'A'); 5], [(w, false, '!'); 5], index).unwrap_err(),
+            MachineError::InvalidPosition('!')
+        );
+        assert_eq!(
+            Sigaba::new([(w, false, 'A'); 5], [(w, false, 'A'); 5], [("0123456788", '0'); 5]).unwrap_err(),
+            MachineError::InvalidWiring("0123456788".into())
+        );
+        assert_eq!(
+            Sigaba::new([(w, false, 'A'); 5], [(w, false, 'A'); 5], [("0123456789", 'X'); 5]).unwrap_err(),
+            MachineError::InvalidPosition('X')
+        );
+    }
+}
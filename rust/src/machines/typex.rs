@@ -0,0 +1,211 @@
+// Typex, the British development of the commercial Enigma. Five rotors sit
+// between the entry plugboard and the reflector: the two next to the
+// reflector are stators, set by hand and never moving, and the three on the
+// entry side step like an Enigma's, the rightmost fastest, with the same
+// double step. Typex rotors carry several notches and any rotor can go in
+// reversed.
+//
+// The wartime rotor wirings were never published, so rotors are built from
+// whatever wiring the caller has; `TypexRotor::enigma` borrows the Enigma
+// catalogue. For the same reason there is no known-answer test: no Typex
+// message survives with a rotor set to check it against, so the tests only
+// trace the Enigma wirings by hand and check Typex reduces to the Enigma.
+
+use super::{letter, letter_index, MachineError, RotorMachine, Rotor, LETTERS};
+use crate::enigma::{Plugboard, RotorType};
+
+#[derive(Debug, Clone)]
+pub struct TypexRotor {
+    rotor: Rotor,
+    // Bit n set if the rotor carries its neighbour when leaving position n
+    notches: u32,
+}
+
+impl TypexRotor {
+    pub fn new(wiring: &str, notches: &str) -> Result<Self, MachineError> {
+        Self::build(wiring, notches, false)
+    }
+
+    // The same core inserted the other way round. The notches are on the
+    // tyre, which keeps its orientation.
+    pub fn reversed(wiring: &str, notches: &str) -> Result<Self, MachineError> {
+        Self::build(wiring, notches, true)
+    }
+
+    pub fn enigma(kind: RotorType) -> Self {
+        Self::new(kind.wiring(), kind.notches()).expect("catalogue wiring is valid")
+    }
+
+    fn build(wiring: &str, notches: &str, reversed: bool) -> Result<Self, MachineError> {
+        let mut bits = 0;
+        for n in notches.chars() {
+            bits |= 1 << letter_index(n).ok_or(MachineError::InvalidPosition(n))?;
+        }
+        Ok(TypexRotor { rotor: Rotor::new(wiring, LETTERS, reversed)?, notches: bits })
+    }
+
+    fn at_notch(&self) -> bool {
+        self.notches & 1 << self.rotor.position != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Typex {
+    // Left to right: stators in 0 and 1, stepping rotors in 2 to 4
+    rotors: [TypexRotor; 5],
+    reflector: [u8; 26],
+    plugboard: Plugboard,
+}
+
+impl Typex {
+    // Rotors as (rotor, ring, position), left to right; the reflector as a
+    // 26-letter wiring like `ReflectorType::wiring`.
+    pub fn new(rotors: [(TypexRotor, char, char); 5], reflector: &str, plugboard: &str) -> Result<Self, MachineError> {
+        let mut placed = Vec::with_capacity(5);
+        for (mut rotor, ring, position) in rotors {
+            rotor.rotor.ring = letter_index(ring).ok_or(MachineError::InvalidPosition(ring))?;
+            rotor.rotor.position = letter_index(position).ok_or(MachineError::InvalidPosition(position))?;
+            placed.push(rotor);
+        }
+        Ok(Typex {
+            rotors: placed.try_into().expect("five rotors"),
+            reflector: parse_reflector(reflector)?,
+            plugboard: Plugboard::parse(plugboard)?,
+        })
+    }
+
+    fn step(&mut self) {
+        let right_at_notch = self.rotors[4].at_notch();
+        let middle_at_notch = self.rotors[3].at_notch();
+        if middle_at_notch {
+            self.rotors[2].rotor.step();
+        }
+        if right_at_notch || middle_at_notch {
+            self.rotors[3].rotor.step();
+        }
+        self.rotors[4].rotor.step();
+    }
+}
+
+fn parse_reflector(wiring: &str) -> Result<[u8; 26], MachineError> {
+    let invalid = || MachineError::InvalidReflector(wiring.to_string());
+    let rotor = Rotor::new(wiring, LETTERS, false).map_err(|_| invalid())?;
+    let mut table = [0; 26];
+    for (i, slot) in table.iter_mut().enumerate() {
+        *slot = rotor.forward[i];
+        if rotor.forward[i] as usize == i || rotor.backward[i] != rotor.forward[i] {
+            return Err(invalid());
+        }
+    }
+    Ok(table)
+}
+
+impl RotorMachine for Typex {
+    fn name(&self) -> &'static str {
+        "Typex"
+    }
+
+    fn window(&self) -> String {
+        self.rotors.iter().map(|r| letter(r.rotor.position)).collect()
+    }
+
+    fn encipher_char(&mut self, c: char) -> char {
+        let Some(i) = letter_index(c) else {
+            return c;
+        };
+        self.step();
+        let mut i = self.plugboard.forward(i as u8) as usize;
+        for rotor in self.rotors.iter().rev() {
+            i = rotor.rotor.forward(i);
+        }
+        i = self.reflector[i] as usize;
+        for rotor in &self.rotors {
+            i = rotor.rotor.backward(i);
+        }
+        let out = letter(self.plugboard.backward(i as u8) as usize);
+        if c.is_ascii_lowercase() { out.to_ascii_lowercase() } else { out }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{EnigmaMachine, ReflectorType};
+
+    fn rotors(kinds: [RotorType; 5], rings: &str, positions: &str) -> [(TypexRotor, char, char); 5] {
+        let mut settings = rings.chars().zip(positions.chars());
+        kinds.map(|kind| {
+            let (ring, position) = settings.next().expect("five settings");
+            (TypexRotor::enigma(kind), ring, position)
+        })
+    }
+
+    #[test]
+    fn test_matches_enigma_with_straight_stators() {
+        // Barbarossa, part 1 (see the Enigma tests): with the stators wired
+        // straight through, Typex is the three-rotor Enigma
+        let stator = || (TypexRotor::new(LETTERS, "").unwrap(), 'A', 'Q');
+        let [.., left, middle, right] = rotors([RotorType::I, RotorType::I, RotorType::II, RotorType::IV, RotorType::V], "AABUL", "AABLA");
+        let mut typex =
+            Typex::new([stator(), stator(), left, middle, right], ReflectorType::B.wiring(), "AV BS CG DL FU HZ IN KM OW RX")
+                .unwrap();
+        assert_eq!(typex.decrypt("EDPUD NRGYS ZRCXN UYTPO MRMBO"), "AUFKL XABTE ILUNG XVONX KURTI");
+    }
+
+    #[test]
+    fn test_stators_in_the_path() {
+        // Worked through the Enigma catalogue wirings by hand: stators VI and
+        // VII, rotors I II III, all at A, reflector B. The right rotor steps
+        // to B first, then A goes III C, II D, I F, VII R, VI K, reflector N,
+        // and back VI M, VII I, I V, II X, III N.
+        let settings = rotors([RotorType::VI, RotorType::VII, RotorType::I, RotorType::II, RotorType::III], "AAAAA", "AAAAA");
+        let mut typex = Typex::new(settings, ReflectorType::B.wiring(), "").unwrap();
+        assert_eq!(typex.encrypt("A"), "N");
+        assert_eq!(typex.window(), "AAAAB");
+    }
+
+    #[test]
+    fn test_stators_never_move() {
+        let mut typex = Typex::new(
+            rotors([RotorType::VI, RotorType::VII, RotorType::I, RotorType::II, RotorType::VIII], "AAAAA", "KQADY"),
+            ReflectorType::C.wiring(),
+            "",
+        )
+        .unwrap();
+        typex.encrypt(&"X".repeat(1000));
+        assert_eq!(&typex.window()[..2], "KQ");
+        assert_ne!(&typex.window()[2..], "ADY");
+    }
+
+    #[test]
+    fn test_reversed_rotor_and_stators_change_the_cipher() {
+        let plain = "THEQUICKBROWNFOXJUMPSOVERTHELAZYDOG";
+        let settings = || rotors([RotorType::VI, RotorType::VII, RotorType::I, RotorType::II, RotorType::VIII], "AAAAA", "KQADY");
+        let ciphertext = Typex::new(settings(), ReflectorType::C.wiring(), "AB").unwrap().encrypt(plain);
+        assert_eq!(Typex::new(settings(), ReflectorType::C.wiring(), "AB").unwrap().decrypt(&ciphertext), plain);
+        assert!(ciphertext.chars().zip(plain.chars()).all(|(c, p)| c != p));
+
+        let mut reversed = settings();
+        reversed[0].0 = TypexRotor::reversed(RotorType::VI.wiring(), "").unwrap();
+        let mut typex = Typex::new(reversed, ReflectorType::C.wiring(), "AB").unwrap();
+        let other = typex.encrypt(plain);
+        assert_ne!(other, ciphertext);
+        let enigma = EnigmaMachine::new([(RotorType::I, 'A', 'A'), (RotorType::II, 'A', 'D'), (RotorType::VIII, 'A', 'Y')], ReflectorType::C, "AB")
+            .process_text(plain);
+        assert_ne!(enigma, ciphertext);
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let settings = || rotors([RotorType::I, RotorType::II, RotorType::III, RotorType::IV, RotorType::V], "AAAAA", "AAAAA");
+        assert_eq!(
+            Typex::new(settings(), LETTERS, "").unwrap_err(),
+            MachineError::InvalidReflector(LETTERS.to_string())
+        );
+        assert!(matches!(Typex::new(settings(), ReflectorType::B.wiring(), "AA"), Err(MachineError::Enigma(_))));
+        assert_eq!(TypexRotor::new(LETTERS, "A1").unwrap_err(), MachineError::InvalidPosition('1'));
+        let mut bad = settings();
+        bad[2].2 = '?';
+        assert_eq!(Typex::new(bad, ReflectorType::B.wiring(), "").unwrap_err(), MachineError::InvalidPosition('?'));
+    }
+}
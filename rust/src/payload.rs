@@ -0,0 +1,163 @@
+// Encrypted and signed container for the init script. Layout:
+//
+//     magic "SGPL" | version u8 | cipher u8 | scheme u8 | nonce [u8; 12]
+//         | ciphertext | tag [u8; 16] | signature [u8; 64]
+//
+// The body is ChaCha20-Poly1305 with the header as associated data, and the
+// Ed25519 signature covers everything before it, so nothing in the blob can
+// be changed without `open` failing. The cipher key is compiled in next to
+// the blob and only keeps the script from being read straight off the
+// binary; the signing key stays with whoever builds the release, so patching
+// the blob means forging a signature and not just re-sealing it.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+pub const MAGIC: [u8; 4] = *b"SGPL";
+pub const VERSION: u8 = 1;
+pub const CHACHA20_POLY1305: u8 = 1;
+pub const ED25519: u8 = 1;
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const SECRET_KEY_LEN: usize = 32;
+pub const PUBLIC_KEY_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 3 + NONCE_LEN;
+const TAG_LEN: usize = 16;
+const SIGNATURE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnsupportedCipher(u8),
+    UnsupportedScheme(u8),
+    // Wrong key, or the header or body was modified
+    Verification,
+}
+
+impl std::fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadError::Truncated => write!(f, "payload is truncated"),
+            PayloadError::BadMagic => write!(f, "not a payload container"),
+            PayloadError::UnsupportedVersion(v) => write!(f, "unsupported payload version {v}"),
+            PayloadError::UnsupportedCipher(c) => write!(f, "unsupported payload cipher {c}"),
+            PayloadError::UnsupportedScheme(c) => write!(f, "unsupported payload signature scheme {c}"),
+            PayloadError::Verification => write!(f, "payload failed verification"),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+// The key `open` checks blobs sealed with `secret_key` against
+pub fn public_key(secret_key: &[u8; SECRET_KEY_LEN]) -> [u8; PUBLIC_KEY_LEN] {
+    SigningKey::from_bytes(secret_key).verifying_key().to_bytes()
+}
+
+pub fn seal(secret_key: &[u8; SECRET_KEY_LEN], key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], plaintext: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN + SIGNATURE_LEN);
+    blob.extend_from_slice(&MAGIC);
+    blob.push(VERSION);
+    blob.push(CHACHA20_POLY1305);
+    blob.push(ED25519);
+    blob.extend_from_slice(nonce);
+    let body = ChaCha20Poly1305::new(key.into())
+        .encrypt(nonce.into(), Payload { msg: plaintext, aad: &blob })
+        .expect("plaintext fits in one ChaCha20 stream");
+    blob.extend_from_slice(&body);
+    let signature = SigningKey::from_bytes(secret_key).sign(&blob);
+    blob.extend_from_slice(&signature.to_bytes());
+    blob
+}
+
+pub fn open(public_key: &[u8; PUBLIC_KEY_LEN], key: &[u8; KEY_LEN], blob: &[u8]) -> Result<Vec<u8>, PayloadError> {
+    if blob.len() < HEADER_LEN + TAG_LEN + SIGNATURE_LEN {
+        return Err(PayloadError::Truncated);
+    }
+    let (signed, signature) = blob.split_at(blob.len() - SIGNATURE_LEN);
+    let (header, body) = signed.split_at(HEADER_LEN);
+    if header[..4] != MAGIC {
+        return Err(PayloadError::BadMagic);
+    }
+    match (header[4], header[5], header[6]) {
+        (VERSION, CHACHA20_POLY1305, ED25519) => {}
+        (VERSION, CHACHA20_POLY1305, scheme) => return Err(PayloadError::UnsupportedScheme(scheme)),
+        (VERSION, cipher, _) => return Err(PayloadError::UnsupportedCipher(cipher)),
+        (version, _, _) => return Err(PayloadError::UnsupportedVersion(version)),
+    }
+    let verifying_key = VerifyingKey::from_bytes(public_key).map_err(|_| PayloadError::Verification)?;
+    let signature = Signature::from_slice(signature).map_err(|_| PayloadError::Verification)?;
+    verifying_key.verify_strict(signed, &signature).map_err(|_| PayloadError::Verification)?;
+    let nonce = &header[7..];
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(nonce.into(), Payload { msg: body, aad: header })
+        .map_err(|_| PayloadError::Verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; SECRET_KEY_LEN] = [7; SECRET_KEY_LEN];
+    const KEY: [u8; KEY_LEN] = [3; KEY_LEN];
+    const NONCE: [u8; NONCE_LEN] = [5; NONCE_LEN];
+
+    fn seal_with(secret: &[u8; SECRET_KEY_LEN], plaintext: &[u8]) -> Vec<u8> {
+        seal(secret, &KEY, &NONCE, plaintext)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let blob = seal_with(&SECRET, b"console.log('hi')");
+        assert_eq!(&blob[..4], b"SGPL");
+        assert_eq!(blob.len(), HEADER_LEN + 17 + TAG_LEN + SIGNATURE_LEN);
+        assert!(!blob.windows(7).any(|w| w == b"console"));
+        assert_eq!(open(&public_key(&SECRET), &KEY, &blob).unwrap(), b"console.log('hi')");
+        assert_eq!(open(&public_key(&SECRET), &KEY, &seal_with(&SECRET, b"")).unwrap(), b"");
+        // Ed25519 signatures are deterministic, so builds with the same keys
+        // come out the same
+        assert_eq!(seal_with(&SECRET, b"x"), seal_with(&SECRET, b"x"));
+    }
+
+    #[test]
+    fn test_any_modified_byte_fails() {
+        let blob = seal_with(&SECRET, b"let x = 1;");
+        for i in 7..blob.len() {
+            let mut bad = blob.clone();
+            bad[i] ^= 0x20;
+            assert_eq!(open(&public_key(&SECRET), &KEY, &bad), Err(PayloadError::Verification), "byte {i}");
+        }
+        assert_eq!(open(&public_key(&[8; SECRET_KEY_LEN]), &KEY, &blob), Err(PayloadError::Verification));
+        assert_eq!(open(&public_key(&SECRET), &[4; KEY_LEN], &blob), Err(PayloadError::Verification));
+    }
+
+    // Resealing a patched body needs the secret key; the binary only has the
+    // public one and the cipher key, which don't sign
+    #[test]
+    fn test_resealing_with_another_key_fails() {
+        let forged = seal_with(&[9; SECRET_KEY_LEN], b"alert('patched')");
+        assert_eq!(open(&public_key(&SECRET), &KEY, &forged), Err(PayloadError::Verification));
+    }
+
+    #[test]
+    fn test_header_errors() {
+        let blob = seal_with(&SECRET, b"x");
+        let key = public_key(&SECRET);
+        assert_eq!(open(&key, &KEY, &blob[..HEADER_LEN + TAG_LEN + SIGNATURE_LEN - 1]), Err(PayloadError::Truncated));
+        let mut bad = blob.clone();
+        bad[0] = b'X';
+        assert_eq!(open(&key, &KEY, &bad), Err(PayloadError::BadMagic));
+        let mut bad = blob.clone();
+        bad[4] = 2;
+        assert_eq!(open(&key, &KEY, &bad), Err(PayloadError::UnsupportedVersion(2)));
+        let mut bad = blob.clone();
+        bad[5] = 9;
+        assert_eq!(open(&key, &KEY, &bad), Err(PayloadError::UnsupportedCipher(9)));
+        let mut bad = blob;
+        bad[6] = 9;
+        assert_eq!(open(&key, &KEY, &bad), Err(PayloadError::UnsupportedScheme(9)));
+    }
+}